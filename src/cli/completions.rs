@@ -0,0 +1,269 @@
+use crate::data::Item;
+use crate::index::Indexer;
+use anyhow::{bail, Result};
+use clap::{Arg, Command};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Shells that completion scripts can be generated for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => bail!("unsupported shell: {}", s),
+        }
+    }
+}
+
+impl From<Shell> for clap_complete::Shell {
+    fn from(shell: Shell) -> Self {
+        match shell {
+            Shell::Bash => clap_complete::Shell::Bash,
+            Shell::Zsh => clap_complete::Shell::Zsh,
+            Shell::Fish => clap_complete::Shell::Fish,
+        }
+    }
+}
+
+// Arguments that are completed using the dotfile, as (id, kinds). The kinds
+// are passed to `dotf __complete`.
+const DYNAMIC: [(&str, &str); 5] = [
+    ("items", "items"),
+    ("item", "items"),
+    ("group", "groups"),
+    ("exclude", "items groups"),
+    ("only", "paths"),
+];
+
+// Scripts are generated with these as the only possible value of the dynamic
+// arguments, which are then replaced with calls to `dotf __complete`.
+const PLACEHOLDERS: [(&str, &str); 4] = [
+    ("items", "__dotf_items"),
+    ("groups", "__dotf_groups"),
+    ("items groups", "__dotf_items_groups"),
+    ("paths", "__dotf_paths"),
+];
+
+/// Generates the completion script of the command for the shell, using
+/// clap_complete, where item names, groups and paths are completed by
+/// calling `dotf __complete <kinds>`.
+pub fn generate(cmd: &Command<'static>, shell: Shell) -> String {
+    let mut cmd = with_placeholders(cmd.clone());
+    let name = cmd.get_name().to_string();
+    let mut buf = Vec::new();
+    clap_complete::generate(clap_complete::Shell::from(shell), &mut cmd, &name, &mut buf);
+    let script = String::from_utf8_lossy(&buf).into_owned();
+
+    match shell {
+        Shell::Bash => bash(script, &name),
+        Shell::Zsh => zsh(script, &name),
+        Shell::Fish => fish(&cmd, script, &name),
+    }
+}
+
+/// Returns the candidates of the kinds, i.e. item names, groups or the
+/// relative paths of the files of the items, sorted and without duplicates.
+pub fn candidates(
+    kinds: &[String],
+    home: PathBuf,
+    repository: PathBuf,
+    items: &[Item],
+) -> Vec<String> {
+    let mut candidates = BTreeSet::new();
+    for kind in kinds {
+        match kind.as_str() {
+            "items" => candidates.extend(items.iter().map(|item| item.name.clone())),
+            "groups" => candidates.extend(items.iter().flat_map(|item| item.groups.clone())),
+            "paths" => {
+                // Completion must be fast, so files are not read.
+                let indexer = Indexer::new(home.clone(), repository.clone(), None);
+                let relpaths = indexer.relpaths(items);
+                candidates.extend(relpaths.iter().map(|p| p.display().to_string()));
+            }
+            _ => {}
+        }
+    }
+    candidates.into_iter().collect()
+}
+
+fn bash(script: String, bin: &str) -> String {
+    // The values of positional arguments are listed with the options.
+    let mut script = script;
+    for (kinds, placeholder) in PLACEHOLDERS {
+        script = script.replace(
+            &format!(" {}\"", placeholder),
+            &format!(" $({} __complete {} 2>/dev/null)\"", bin, kinds),
+        );
+    }
+    replace(script, |kinds| {
+        format!("\"$({} __complete {} 2>/dev/null)\"", bin, kinds)
+    })
+}
+
+fn zsh(script: String, bin: &str) -> String {
+    let script = replace(script, |kinds| format!("_{}_complete {}", bin, kinds));
+    let function = format!(
+        r#"(( $+functions[_{bin}_complete] )) ||
+_{bin}_complete() {{
+    local -a candidates
+    candidates=(${{(f)"$({bin} __complete "$@" 2>/dev/null)"}})
+    compadd -a candidates
+}}
+
+"#,
+        bin = bin
+    );
+
+    // The function must be defined before the script completes.
+    let call = format!("\n_{} \"$@\"", bin);
+    match script.rfind(&call) {
+        Some(i) => format!("{}{}{}", &script[..=i], function, &script[i + 1..]),
+        None => script + &function,
+    }
+}
+
+// The generated script only completes options, so the positional arguments
+// are added.
+fn fish(cmd: &Command, script: String, bin: &str) -> String {
+    let mut script = replace(script, |kinds| {
+        format!("\"({} __complete {} 2>/dev/null)\"", bin, kinds)
+    });
+    for sub in cmd.get_subcommands() {
+        if let Some((_, kinds)) = dynamic_positionals(sub).next() {
+            script.push_str(&format!(
+                "complete -c {bin} -n \"__fish_seen_subcommand_from {}\" -f -a \"({bin} __complete {} 2>/dev/null)\"\n",
+                sub.get_name(),
+                kinds,
+                bin = bin
+            ));
+        }
+    }
+    script
+}
+
+// Replaces the placeholders, as quoted by the shell, with the completion of
+// their kinds.
+fn replace(mut script: String, completion: impl Fn(&str) -> String) -> String {
+    for (kinds, placeholder) in PLACEHOLDERS {
+        let replacement = completion(kinds);
+        for quoted in [
+            format!("\"{}\"", placeholder),
+            format!("\"{{{}\t}}\"", placeholder),
+            format!("({})", placeholder),
+        ] {
+            script = script.replace(&quoted, &replacement);
+        }
+    }
+    script
+}
+
+// Sets the placeholder of the kinds as the only possible value of the
+// dynamic arguments of the command and its subcommands.
+fn with_placeholders(mut cmd: Command<'static>) -> Command<'static> {
+    let ids: Vec<&'static str> = cmd
+        .get_arguments()
+        .filter(|arg| arg.is_takes_value_set())
+        .map(|arg| arg.get_id())
+        .collect();
+    for id in ids {
+        if let Some(placeholder) = dynamic_kinds(id).and_then(placeholder) {
+            cmd = cmd.mut_arg(id, |arg| arg.value_parser([placeholder]));
+        }
+    }
+
+    for sub in cmd.get_subcommands_mut() {
+        let owned = std::mem::replace(sub, Command::new(""));
+        *sub = with_placeholders(owned);
+    }
+    cmd
+}
+
+// The dynamic positional arguments of the command, and their kinds.
+fn dynamic_positionals<'a, 'help>(
+    cmd: &'a Command<'help>,
+) -> impl Iterator<Item = (&'a Arg<'help>, &'static str)> + 'a {
+    cmd.get_positionals()
+        .filter_map(|arg| Some((arg, dynamic_kinds(arg.get_id())?)))
+}
+
+fn dynamic_kinds(id: &str) -> Option<&'static str> {
+    DYNAMIC
+        .iter()
+        .find(|(arg, _)| *arg == id)
+        .map(|(_, kinds)| *kinds)
+}
+
+fn placeholder(kinds: &str) -> Option<&'static str> {
+    PLACEHOLDERS
+        .iter()
+        .find(|(k, _)| *k == kinds)
+        .map(|(_, placeholder)| *placeholder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::command;
+    use crate::testing::TestContext;
+
+    #[test]
+    fn scripts_complete_subcommands_and_items() {
+        // Arrange
+        let cmd = command();
+
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            // Act
+            let script = generate(&cmd, shell);
+
+            // Assert
+            assert!(script.contains("status"), "{:?}", shell);
+            assert!(script.contains("fail-fast"), "{:?}", shell);
+            assert!(script.contains("_complete items"), "{:?}", shell);
+            assert!(script.contains("_complete items groups"), "{:?}", shell);
+            assert!(!script.contains("__dotf_"), "{:?}", shell);
+        }
+    }
+
+    #[test]
+    fn bash_completes_items_as_positionals() {
+        let script = generate(&command(), Shell::Bash);
+        assert!(!script.contains("<items>"));
+        assert!(script.contains("compgen -W \"trace debug info warn error\""));
+    }
+
+    #[test]
+    fn candidates_from_items() {
+        // Arrange
+        let cx = TestContext::default();
+        cx.setup().expect("to setup test context");
+        let mut vim = Item::simple_new("vim", "init.vim");
+        vim.groups = vec!["editor".to_string()];
+        let mut tmux = Item::simple_new("tmux", "tmux.conf");
+        tmux.groups = vec!["editor".to_string(), "term".to_string()];
+        let items = vec![vim, tmux];
+        let candidates = |kinds: &[&str]| {
+            let kinds: Vec<String> = kinds.iter().map(|s| s.to_string()).collect();
+            candidates(&kinds, cx.home_dir.clone(), cx.repo_dir.clone(), &items)
+        };
+
+        // Act & Assert
+        assert_eq!(vec!["tmux", "vim"], candidates(&["items"]));
+        assert_eq!(
+            vec!["editor", "term", "tmux", "vim"],
+            candidates(&["items", "groups"])
+        );
+        assert_eq!(vec!["init.vim", "tmux.conf"], candidates(&["paths"]));
+    }
+}
@@ -0,0 +1,314 @@
+use crate::data::Settings;
+use crate::handler::{DiffOptions, Target};
+use crate::style::{Color, Icons};
+use anyhow::{bail, Result};
+use std::str::FromStr;
+
+/// Settings given as command line flags, which take precedence over
+/// everything else.
+#[derive(Debug, Default)]
+pub struct Flags {
+    pub diff_command: Option<String>,
+    pub editor: Option<String>,
+    // Set by --no-pager.
+    pub no_pager: bool,
+    pub backup: Option<bool>,
+    pub confirm: Option<bool>,
+    pub direction: Option<Target>,
+    pub log_level: Option<String>,
+    pub color: Option<Color>,
+    pub icons: Option<Icons>,
+}
+
+/// The settings used by the handlers. Each one is resolved, in order of
+/// precedence, from a flag, a DOTF_* environment variable, the settings
+/// section of the dotfile and a default.
+#[derive(Debug)]
+pub struct Config {
+    pub diff_options: DiffOptions,
+    pub merge_command: Option<String>,
+    // Only used when stdout is a terminal.
+    pub pager: Option<String>,
+    pub editor: String,
+    pub backup: bool,
+    pub confirm: bool,
+    // Where dotf sync copies files to.
+    pub direction: Target,
+    pub log_level: Option<String>,
+    pub color: Color,
+    pub icons: Icons,
+}
+
+impl Config {
+    /// Resolves the config, where env returns the value of an environment
+    /// variable. The pager and editor fall back to $PAGER, $VISUAL and
+    /// $EDITOR after the settings, like git does, and colors are disabled by
+    /// $NO_COLOR after the settings.
+    pub fn resolve(
+        flags: &Flags,
+        settings: &Settings,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self> {
+        let string = |flag: &Option<String>, var: &str, setting: &Option<String>| {
+            flag.clone()
+                .or_else(|| env(var))
+                .or_else(|| setting.clone())
+                .filter(|s| !s.trim().is_empty())
+        };
+
+        let diff_options = match string(
+            &flags.diff_command,
+            "DOTF_DIFF_COMMAND",
+            &settings.diff_command,
+        ) {
+            Some(cmd) => DiffOptions::new(cmd.split_whitespace().map(String::from).collect()),
+            None => DiffOptions::default(),
+        };
+
+        let pager = match flags.no_pager {
+            true => None,
+            false => string(&None, "DOTF_PAGER", &settings.pager).or_else(|| env("PAGER")),
+        };
+
+        let editor = string(&flags.editor, "DOTF_EDITOR", &settings.editor)
+            .or_else(|| env("VISUAL"))
+            .or_else(|| env("EDITOR"))
+            .unwrap_or_else(|| "nano".to_string());
+
+        let backup = flags
+            .backup
+            .or(parse_env(&env, "DOTF_BACKUP", parse_bool)?)
+            .or(settings.backup)
+            .unwrap_or(true);
+        let confirm = flags
+            .confirm
+            .or(parse_env(&env, "DOTF_CONFIRM", parse_bool)?)
+            .or(settings.confirm)
+            .unwrap_or(true);
+
+        let direction = match flags.direction {
+            Some(direction) => direction,
+            None => parse_env(&env, "DOTF_DEFAULT_DIRECTION", Target::from_str)?
+                .or(parse_setting(&settings.default_direction)?)
+                .unwrap_or(Target::Repo),
+        };
+        let color = match flags.color {
+            Some(color) => color,
+            None => parse_env(&env, "DOTF_COLOR", Color::from_str)?
+                .or(parse_setting(&settings.color)?)
+                .or_else(|| {
+                    env("NO_COLOR")
+                        .filter(|s| !s.is_empty())
+                        .map(|_| Color::Never)
+                })
+                .unwrap_or_default(),
+        };
+        let icons = match flags.icons {
+            Some(icons) => icons,
+            None => parse_env(&env, "DOTF_ICONS", Icons::from_str)?
+                .or(parse_setting(&settings.icons)?)
+                .unwrap_or_default(),
+        };
+
+        Ok(Self {
+            diff_options,
+            merge_command: string(&None, "DOTF_MERGE_COMMAND", &settings.merge_command),
+            pager,
+            editor,
+            backup,
+            confirm,
+            direction,
+            log_level: string(&flags.log_level, "DOTF_LOG_LEVEL", &settings.log_level),
+            color,
+            icons,
+        })
+    }
+}
+
+// Parses the environment variable, if set.
+fn parse_env<T>(
+    env: impl Fn(&str) -> Option<String>,
+    var: &str,
+    parse: impl Fn(&str) -> Result<T>,
+) -> Result<Option<T>> {
+    match env(var) {
+        Some(value) => match parse(&value) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => bail!("invalid value of {}: {}", var, err),
+        },
+        None => Ok(None),
+    }
+}
+
+// Settings are validated with the dotfile, so this only fails for
+// dotfiles that are not.
+fn parse_setting<T: FromStr<Err = anyhow::Error>>(setting: &Option<String>) -> Result<Option<T>> {
+    setting.as_deref().map(str::parse).transpose()
+}
+
+fn parse_bool(s: &str) -> Result<bool> {
+    match s.to_lowercase().as_str() {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" => Ok(false),
+        _ => bail!("expected true or false, found {}", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn defaults_without_settings() {
+        let config = Config::resolve(&Flags::default(), &Settings::default(), env(&[])).unwrap();
+        assert_eq!(Some("diff"), config.diff_options.program());
+        assert_eq!(None, config.merge_command);
+        assert_eq!(None, config.pager);
+        assert_eq!("nano", config.editor);
+        assert!(config.backup);
+        assert!(config.confirm);
+        assert_eq!(Target::Repo, config.direction);
+        assert_eq!(None, config.log_level);
+        assert_eq!(Color::Auto, config.color);
+        assert_eq!(Icons::Nerd, config.icons);
+    }
+
+    #[test]
+    fn flags_take_precedence_over_env_and_settings() {
+        // Arrange
+        let flags = Flags {
+            editor: Some("vim".to_string()),
+            backup: Some(false),
+            direction: Some(Target::Repo),
+            ..Default::default()
+        };
+        let settings = Settings {
+            editor: Some("code".to_string()),
+            backup: Some(true),
+            default_direction: Some("home".to_string()),
+            ..Default::default()
+        };
+        let env = env(&[("DOTF_EDITOR", "emacs"), ("DOTF_BACKUP", "true")]);
+
+        // Act
+        let config = Config::resolve(&flags, &settings, env).unwrap();
+
+        // Assert
+        assert_eq!("vim", config.editor);
+        assert!(!config.backup);
+        assert_eq!(Target::Repo, config.direction);
+    }
+
+    #[test]
+    fn env_takes_precedence_over_settings() {
+        // Arrange
+        let settings = Settings {
+            diff_command: Some("delta".to_string()),
+            editor: Some("code".to_string()),
+            pager: Some("less -R".to_string()),
+            confirm: Some(true),
+            color: Some("always".to_string()),
+            ..Default::default()
+        };
+        let env = env(&[
+            ("DOTF_DIFF_COMMAND", "difft --color always"),
+            ("DOTF_CONFIRM", "0"),
+            ("DOTF_COLOR", "never"),
+            ("EDITOR", "vi"),
+            ("PAGER", "more"),
+        ]);
+
+        // Act
+        let config = Config::resolve(&Flags::default(), &settings, env).unwrap();
+
+        // Assert
+        assert_eq!(Some("difft"), config.diff_options.program());
+        assert!(!config.confirm);
+        assert_eq!(Color::Never, config.color);
+        // $EDITOR and $PAGER are only used without settings.
+        assert_eq!("code", config.editor);
+        assert_eq!(Some("less -R"), config.pager.as_deref());
+    }
+
+    #[test]
+    fn settings_take_precedence_over_defaults() {
+        // Arrange
+        let settings = Settings {
+            merge_command: Some("vimdiff".to_string()),
+            backup: Some(false),
+            default_direction: Some("home".to_string()),
+            log_level: Some("debug".to_string()),
+            ..Default::default()
+        };
+
+        // Act
+        let config =
+            Config::resolve(&Flags::default(), &settings, env(&[("VISUAL", "vi")])).unwrap();
+
+        // Assert
+        assert_eq!(Some("vimdiff"), config.merge_command.as_deref());
+        assert!(!config.backup);
+        assert_eq!(Target::Home, config.direction);
+        assert_eq!(Some("debug"), config.log_level.as_deref());
+        assert_eq!("vi", config.editor);
+    }
+
+    #[test]
+    fn no_pager_flag_disables_pager() {
+        let flags = Flags {
+            no_pager: true,
+            ..Default::default()
+        };
+        let config =
+            Config::resolve(&flags, &Settings::default(), env(&[("PAGER", "less")])).unwrap();
+        assert_eq!(None, config.pager);
+    }
+
+    #[test]
+    fn no_color_env_disables_colors() {
+        // Arrange
+        let settings = Settings {
+            icons: Some("ascii".to_string()),
+            ..Default::default()
+        };
+        let flags = Flags {
+            icons: Some(Icons::Unicode),
+            ..Default::default()
+        };
+
+        // Act
+        let config = Config::resolve(&Flags::default(), &settings, env(&[("NO_COLOR", "1")]));
+        let flagged = Config::resolve(&flags, &settings, env(&[("NO_COLOR", "")]));
+
+        // Assert
+        let config = config.unwrap();
+        assert_eq!(Color::Never, config.color);
+        assert_eq!(Icons::Ascii, config.icons);
+        let flagged = flagged.unwrap();
+        assert_eq!(Color::Auto, flagged.color);
+        assert_eq!(Icons::Unicode, flagged.icons);
+    }
+
+    #[test]
+    fn invalid_env_value() {
+        let err = Config::resolve(
+            &Flags::default(),
+            &Settings::default(),
+            env(&[("DOTF_DEFAULT_DIRECTION", "up")]),
+        )
+        .expect_err("invalid direction");
+        assert_eq!(
+            "invalid value of DOTF_DEFAULT_DIRECTION: invalid direction: up",
+            err.to_string()
+        );
+    }
+}
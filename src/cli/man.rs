@@ -0,0 +1,150 @@
+use clap::{Arg, Command};
+
+/// Renders the man page of the command, formatted using roff.
+pub fn render(cmd: &Command) -> String {
+    // Building adds --help, --version and the global arguments to every
+    // subcommand.
+    let mut cmd = cmd.clone();
+    cmd.build();
+
+    let name = cmd.get_name();
+    let version = cmd.get_version().unwrap_or_default();
+    let mut s = format!(
+        ".TH {} 1 \"\" \"{} {}\" \"User Commands\"\n",
+        name.to_uppercase(),
+        name,
+        version
+    );
+
+    s.push_str(".SH NAME\n");
+    s.push_str(&format!(
+        "{} \\- {}\n",
+        name,
+        escape(cmd.get_about().unwrap_or_default())
+    ));
+
+    s.push_str(".SH SYNOPSIS\n");
+    s.push_str(&format!(
+        "\\fB{}\\fR [\\fIOPTIONS\\fR] [\\fICOMMAND\\fR]\n",
+        name
+    ));
+
+    s.push_str(".SH OPTIONS\n");
+    s.push_str(&arguments(&cmd));
+
+    s.push_str(".SH COMMANDS\n");
+    for sub in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        let aliases: Vec<&str> = sub.get_all_aliases().collect();
+        let title = match aliases.is_empty() {
+            true => format!("{} {}", name, sub.get_name()),
+            false => format!("{} {} ({})", name, sub.get_name(), aliases.join(", ")),
+        };
+        s.push_str(&format!(".SS \"{}\"\n", escape(&title)));
+        if let Some(about) = sub.get_long_about().or_else(|| sub.get_about()) {
+            s.push_str(&paragraph(about));
+        }
+        s.push_str(&arguments(sub));
+    }
+    s
+}
+
+// Every argument of the command, as tagged paragraphs.
+fn arguments(cmd: &Command) -> String {
+    let mut s = String::new();
+    for arg in cmd.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        s.push_str(".TP\n");
+        s.push_str(&synopsis(arg));
+        s.push('\n');
+        if let Some(help) = arg.get_long_help().or_else(|| arg.get_help()) {
+            s.push_str(&paragraph(help));
+        }
+
+        let possible: Vec<String> = match arg.is_takes_value_set() {
+            true => arg
+                .get_value_parser()
+                .possible_values()
+                .map(|values| values.map(|v| v.get_name().to_string()).collect())
+                .unwrap_or_default(),
+            false => Vec::new(),
+        };
+        if !possible.is_empty() {
+            s.push_str(&format!(
+                "[possible values: {}]\n",
+                escape(&possible.join(", "))
+            ));
+        }
+        let defaults: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|v| v.to_string_lossy().to_string())
+            .collect();
+        if !defaults.is_empty() {
+            s.push_str(&format!("[default: {}]\n", escape(&defaults.join(", "))));
+        }
+    }
+    s
+}
+
+// E.g. -g, --group <group> or <items>... for positional arguments.
+fn synopsis(arg: &Arg) -> String {
+    let value = format!("\\fI<{}>\\fR", escape(arg.get_id()));
+    if arg.is_positional() {
+        return match arg.is_multiple_values_set() {
+            true => format!("{}...", value),
+            false => value,
+        };
+    }
+
+    let short = arg.get_short().map(|c| format!("\\fB\\-{}\\fR", c));
+    let long = arg
+        .get_long()
+        .map(|l| format!("\\fB\\-\\-{}\\fR", escape(l)));
+    let flags: Vec<String> = short.into_iter().chain(long).collect();
+    match arg.is_takes_value_set() {
+        true => format!("{} {}", flags.join(", "), value),
+        false => flags.join(", "),
+    }
+}
+
+// Text of a paragraph, where lines are kept as is.
+fn paragraph(s: &str) -> String {
+    s.lines()
+        .map(|line| format!("{}\n", escape(line.trim())))
+        .collect::<Vec<_>>()
+        .join(".br\n")
+}
+
+// Escapes text to be used in roff, where a line must not start with a
+// control character.
+fn escape(s: &str) -> String {
+    let s = s.replace('\\', "\\e").replace('-', "\\-");
+    match s.starts_with('.') || s.starts_with('\'') {
+        true => format!("\\&{}", s),
+        false => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::command;
+
+    #[test]
+    fn render_commands_and_options() {
+        // Act
+        let page = render(&command());
+
+        // Assert
+        assert!(page.starts_with(".TH DOTF 1"));
+        assert!(page.contains(".SS \"dotf status (st)\"\n"));
+        assert!(page.contains("\\fB\\-g\\fR, \\fB\\-\\-group\\fR \\fI<group>\\fR\n"));
+        assert!(page.contains("[possible values: text, json, jsonl]\n"));
+        assert!(!page.contains("__complete"));
+    }
+
+    #[test]
+    fn escape_control_characters() {
+        assert_eq!("\\&.vimrc", escape(".vimrc"));
+        assert_eq!("a\\-b\\ec", escape("a-b\\c"));
+    }
+}
@@ -1,230 +1,157 @@
+mod completions;
+mod config;
+mod man;
+
+use self::completions::Shell;
+use self::config::{Config, Flags};
 use crate::cmd::CmdRunner;
-use crate::data::Dotfile;
-use crate::files;
-use crate::handler::{DiffHandler, DiffOptions, Only, StatusHandler, SyncHandler, SyncOptions};
+use crate::data::{Dotfile, Settings};
+use crate::error::DotfError;
+use crate::files::{self, Filesystem, GitFs, RealFs};
+use crate::handler::{
+    DiffHandler, DoctorHandler, Format, History, ListHandler, LogHandler, Only, Plan, Selection,
+    StatusHandler, SyncHandler, SyncOptions, Target, WatchHandler, WatchOptions,
+};
 use crate::logging;
 use crate::path::HOME_DIR;
 use crate::prompt::StdinPrompt;
+use crate::report::{JsonLinesReporter, Reporter, TerminalReporter};
+use crate::style::{set_colors, set_icons, Color};
 use anyhow::{bail, Result};
 use clap::builder::PossibleValuesParser;
 use clap::{command, Arg, ArgAction, ArgMatches, Command};
 use std::env;
-use std::io::Write;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command as Cmd;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Default)]
 pub struct Cli;
 
 impl Cli {
     pub fn exec(&self) -> Result<()> {
-        let matches = command!()
-            .about("Simple dotfile management")
-            .arg(
-                Arg::new("log")
-                    .help("Display logs.")
-                    .long("log")
-                    .global(true)
-                    .takes_value(true)
-                    .value_parser(PossibleValuesParser::new(["trace", "debug", "info", "warn", "error"]))
-                    .default_missing_value("info"),
-            )
-            .subcommand(
-                Command::new("sync")
-                    .about("Sync home and repo files, defaults home -> repo.")
-                    .arg(
-                        Arg::new("home")
-                            .help("Sync files from repository to home.")
-                            .long("home")
-                    )
-                    .arg(Arg::new("dryrun").long("dryrun"))
-                    .arg(
-                        Arg::new("diff")
-                            .long("diff")
-                            .conflicts_with("no-confirm")
-                            .help("Display inline diffs before"),
-                    )
-                    .arg(
-                        Arg::new("diff-command")
-                            .help("Use as diff command (default: diff -u --color)")
-                            .long("diff-command")
-                            .requires("diff")
-                            .number_of_values(1),
-                    )
-                    .arg(
-                        Arg::new("no-confirm")
-                            .help("Skip confirmation prompt.")
-                            .long("no-confirm")
-                            .alias("yes")
-                            .short('y')
-                    )
-                    .arg(
-                        Arg::new("no-backup")
-                            .help("Do not create backups when copying to home.")
-                            .long("no-backup")
-                    )
-                    .arg(
-                        Arg::new("interactive").help("Sync files interactively.")
-                        .long("interactive")
-                        .short('i')
-                    )
-                    .arg(
-                        Arg::new("only")
-                            .help("Only include files matching patterns specified. Pattern uses glob by default. Set --regex to use regular expressions.")
-                            .long("only")
-                            .short('o')
-                            .takes_value(true)
-                            .action(ArgAction::Append)
-                            .required(false),
-                    )
-                    .arg(
-                        Arg::new("regex")
-                            .help("Use regular expressions in patterns specified in --only.")
-                            .long("regex")
-                            .short('r')
-                            .requires("only")
-                    )
-                    .arg(
-                        Arg::new("commit")
-                            .help("Create a git commit after syncing files. Only valid when copying files to repository.")
-                            .long("commit")
-                            .short('C')
-                            .takes_value(true)
-                    )
-                    .arg(
-                        Arg::new("push")
-                            .help("Run git push after commit.")
-                            .long("push")
-                            .requires("commit")
-                    ),
-            )
-            .subcommand(
-                Command::new("status")
-                    .alias("st")
-                    .about("Display the current status between home and repository.")
-                    .arg(
-                        Arg::new("only")
-                            .help("Only include files matching patterns specified. Pattern uses glob by default. Set --regex to use regular expressions.")
-                            .long("only")
-                            .short('o')
-                            .takes_value(true)
-                            .action(ArgAction::Append)
-                            .required(false),
-                    )
-                    .arg(
-                        Arg::new("regex")
-                            .help("Use regular expressions in patterns specified in --only.")
-                            .long("regex")
-                            .short('r')
-                    )
-                    .arg(
-                        Arg::new("brief")
-                            .long("brief")
-                            .short('b')
-                            .help("Only display files that are not up to date."),
-                    ),
-            )
-            .subcommand(
-                Command::new("diff")
-                    .about("Show diff between files that do not match.")
-                    .arg(
-                        Arg::new("only")
-                            .help("Only include files matching patterns specified. Pattern uses glob by default. Set --regex to use regular expressions.")
-                            .long("only")
-                            .short('o')
-                            .takes_value(true)
-                            .action(ArgAction::Append)
-                            .required(false),
-                    )
-                    .arg(
-                        Arg::new("regex")
-                            .help("Use regular expressions in patterns specified in --only.")
-                            .long("regex")
-                            .short('r')
-                    )
-                    .arg(
-                        Arg::new("diff-command")
-                            .long("diff-command")
-                            .help("Use as diff command (default: diff -u --color)")
-                            .number_of_values(1),
-                    ),
-            )
-            .subcommand(
-                Command::new("edit").about("Edit the dotfile").arg(
-                    Arg::new("editor")
-                        .long("editor")
-                        .short('e')
-                        .takes_value(true),
-                ),
-            )
-            .subcommand(
-                Command::new("git")
-                    .trailing_var_arg(true)
-                    .about("Run arbitrary git command in repository.")
-                    .long_about(
-                        "Runs an arbitrary git command in the configured repository.\
-Usage: dotf git <...>
-Example: dotf git status",
-                    )
-                    .arg(
-                        Arg::new("args")
-                            .takes_value(true)
-                            .multiple_values(true)
-                            .allow_hyphen_values(true),
-                    ),
-            )
-            .get_matches();
-
-        if let Some(level) = matches.get_one::<String>("log") {
-            logging::init(level)?
+        let home = HOME_DIR.clone();
+
+        // Used by the completion scripts to complete values from the
+        // dotfile, e.g. dotf __complete items groups. It is not part of the
+        // command, so that it is not completed itself.
+        let mut args = env::args_os().skip(1);
+        if args.next().is_some_and(|arg| arg == "__complete") {
+            let kinds: Vec<String> = args.filter_map(|arg| arg.into_string().ok()).collect();
+            complete(home, &kinds);
+            return Ok(());
         }
 
-        let home = PathBuf::from(HOME_DIR.as_str());
-        log::debug!("Home directory: {:?}", home);
+        let matches = command().get_matches();
+
+        match matches.subcommand() {
+            Some(("completions", matches)) => {
+                let shell: Shell = matches.get_one::<String>("shell").unwrap().parse()?;
+                print!("{}", completions::generate(&command(), shell));
+                return Ok(());
+            }
+            Some(("man", _)) => {
+                print!("{}", man::render(&command()));
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if let Some(("doctor", _)) = matches.subcommand() {
+            let path = get_dotfile_path(&home).unwrap_or_else(|| default_dotfile_path(&home));
+
+            // The doctor checks the dotfile, so it need not be valid.
+            let config = configure(&matches, &lenient_settings(&path))?;
+            let handler = DoctorHandler::new(home, path, config.editor, config.diff_options);
+            return handler.doctor();
+        }
 
         let dotfile_path = match get_dotfile_path(&home) {
             Some(path) => path,
             None => {
                 println!("~/.config/dotfiles.toml not found, creating new");
-                let mut path = home.clone();
-                path.push(".config");
-                path.push("dotfiles.toml");
-                bootstrap(&path)?;
+                bootstrap(&default_dotfile_path(&home))?;
                 return Ok(());
             }
         };
 
+        if let Some(("edit", _)) = matches.subcommand() {
+            // The dotfile is edited to fix it, so it need not be valid.
+            let config = configure(&matches, &lenient_settings(&dotfile_path))?;
+            let editor = config.editor;
+            log::debug!("Editing using {}", editor);
+
+            let mut args = editor.split_whitespace();
+            let program = match args.next() {
+                Some(program) => program,
+                None => bail!("empty editor command"),
+            };
+            let mut cmd = Cmd::new(program);
+            cmd.args(args).arg(&dotfile_path);
+            cmd.status()
+                .map_err(|err| DotfError::command(program, err))?;
+            return Ok(());
+        }
+
+        let dotfile = load_dotfile(&dotfile_path)?;
+        let config = configure(&matches, &dotfile.settings())?;
+        log::debug!("Home directory: {:?}", home);
+
         match matches.subcommand() {
             None => {
-                let dotfile = load_dotfile(&dotfile_path)?;
                 let handler = StatusHandler::new(home, dotfile.repository(), dotfile.items(), None);
-                handler.status(false)?;
-            }
-            Some(("edit", matches)) => {
-                let editor = get_editor(matches.get_one("editor"));
-                log::debug!("Editing using {}", editor);
-
-                let mut cmd = Cmd::new(&editor);
-                cmd.arg(&dotfile_path);
-                cmd.status()?;
+                handler.status(false, Format::Text)?;
             }
             Some(("status", matches)) => {
                 let only = get_only(matches)?;
-                let dotfile = load_dotfile(&dotfile_path)?;
-                let handler = StatusHandler::new(home, dotfile.repository(), dotfile.items(), only);
+                let fs = get_filesystem(matches, &dotfile.repository())?;
+                let handler = StatusHandler::new(home, dotfile.repository(), dotfile.items(), only)
+                    .with_selection(get_selection(matches))
+                    .with_fail_fast(matches.contains_id("fail-fast"))
+                    .with_filesystem(fs);
                 let brief = matches.contains_id("brief");
-                handler.status(brief)?;
+                let format = get_format(matches)?;
+                let handler = handler.with_reporter(get_reporter(format));
+                handler.status(brief, format)?;
             }
             Some(("diff", matches)) => {
                 let only = get_only(matches)?;
-                let dotfile = load_dotfile(&dotfile_path)?;
-                let options = get_diff_options(matches)?;
+                let options = config.diff_options;
+                // Output is only paged in terminals.
+                let pager = config.pager.filter(|_| io::stdout().is_terminal());
+                let fs = get_filesystem(matches, &dotfile.repository())?;
                 let handler =
-                    DiffHandler::new(home, dotfile.repository(), dotfile.items(), options, only);
+                    DiffHandler::new(home, dotfile.repository(), dotfile.items(), options, only)
+                        .with_selection(get_selection(matches))
+                        .with_fail_fast(matches.contains_id("fail-fast"))
+                        .with_filesystem(fs)
+                        .with_pager(pager);
                 handler.diff()?;
             }
+            Some(("list", matches)) => {
+                let handler = ListHandler::new(home, dotfile.repository(), dotfile.items());
+                handler.list(get_format(matches)?)?;
+            }
+            Some(("show", matches)) => {
+                let handler = ListHandler::new(home, dotfile.repository(), dotfile.items());
+                let name: &String = matches.get_one("item").unwrap();
+                handler.show(name, get_format(matches)?)?;
+            }
+            Some(("log", matches)) => {
+                let handler = LogHandler::new(
+                    home,
+                    dotfile.repository(),
+                    dotfile.items(),
+                    History::local(),
+                )
+                .with_patch(matches.contains_id("patch"));
+                let target = matches.get_one::<String>("item").map(String::as_str);
+                handler.log(target)?;
+            }
             Some(("git", matches)) => {
-                let dotfile = load_dotfile(&dotfile_path)?;
                 let runner = CmdRunner::new(dotfile.repository());
 
                 let args = match matches.get_many::<String>("args") {
@@ -234,24 +161,68 @@ Example: dotf git status",
 
                 runner.run("git", args)?;
             }
+            Some(("watch", matches)) => {
+                let mut options = WatchOptions {
+                    copy: matches.contains_id("copy"),
+                    ..Default::default()
+                };
+                if let Some(ms) = matches.get_one::<u64>("debounce") {
+                    options.debounce = Duration::from_millis(*ms);
+                }
+                if let Some(minutes) = matches.get_one::<u64>("commit-interval") {
+                    options.commit_interval = Some(Duration::from_secs(minutes * 60));
+                }
+                if let Some(msg) = matches.get_one::<String>("commit-message") {
+                    options.commit_message = msg.to_string();
+                }
+                log::debug!("Watch options {:?}", &options);
+
+                let handler =
+                    WatchHandler::new(home, dotfile.repository(), dotfile.items(), options);
+                handler.watch()?;
+            }
             Some(("sync", matches)) => {
-                let dotfile = load_dotfile(&dotfile_path)?;
                 let only = get_only(matches)?;
-                let diff_options = get_diff_options(matches)?;
+                let format = get_format(matches)?;
+
+                let non_interactive = matches.contains_id("non-interactive")
+                    || format != Format::Text
+                    || !io::stdin().is_terminal();
+                let on_conflict = match matches.get_one::<String>("on-conflict") {
+                    Some(s) => Some(s.parse()?),
+                    None => None,
+                };
 
                 let options = SyncOptions {
                     interactive: matches.contains_id("interactive"),
-                    confirm: !matches.contains_id("no-confirm"),
-                    backup: !matches.contains_id("no-backup"),
+                    non_interactive,
+                    on_conflict,
+                    confirm: config.confirm,
+                    backup: config.backup,
                     dryrun: matches.contains_id("dryrun"),
                     show_diff: matches.contains_id("diff"),
-                    diff_options,
+                    diff_options: config.diff_options,
                     git_commit: matches.get_one::<String>("commit").map(String::from),
                     git_push: matches.contains_id("push"),
+                    hooks: dotfile.hooks(),
+                    run_hooks: !matches.contains_id("no-hooks"),
+                    capture_hooks: false,
+                    editor: Some(config.editor),
+                    merge_command: config.merge_command,
+                    dotfile_path: Some(dotfile_path.clone()),
+                    format,
+                    plan_out: matches.get_one::<String>("plan-out").map(PathBuf::from),
+                    fail_fast: matches.contains_id("fail-fast"),
+                    history: Some(History::local()),
                 };
                 log::debug!("Sync options {:?}", &options);
 
+                // Files of a revision can only be synced to home.
+                if matches.contains_id("rev") && config.direction == Target::Repo {
+                    bail!("--rev can only be used when syncing to home, use --home");
+                }
                 let repository = dotfile.repository();
+                let fs = get_filesystem(matches, &repository)?;
                 let handler = SyncHandler::new(
                     Box::new(StdinPrompt {}),
                     home,
@@ -259,14 +230,54 @@ Example: dotf git status",
                     dotfile.items(),
                     options,
                     only,
-                );
-
-                if matches.contains_id("home") {
-                    log::info!("Syncing repo -> home");
-                    handler.copy_to_home()?;
-                } else {
-                    log::info!("Syncing home -> repo");
-                    handler.copy_to_repo()?;
+                )
+                .with_selection(get_selection(matches))
+                .with_filesystem(fs)
+                .with_reporter(get_reporter(format));
+
+                let summary = match config.direction {
+                    Target::Home => {
+                        log::info!("Syncing repo -> home");
+                        handler.copy_to_home()?
+                    }
+                    Target::Repo => {
+                        log::info!("Syncing home -> repo");
+                        handler.copy_to_repo()?
+                    }
+                };
+                log::debug!("Sync summary {:?}", summary);
+                if summary.errors > 0 {
+                    bail!("sync finished with {} error(s)", summary.errors);
+                }
+            }
+            Some(("apply", matches)) => {
+                let path = PathBuf::from(matches.get_one::<String>("plan").unwrap());
+                let plan = Plan::load(&path)?;
+                let format = get_format(matches)?;
+
+                let options = SyncOptions {
+                    non_interactive: true,
+                    confirm: false,
+                    hooks: dotfile.hooks(),
+                    run_hooks: !matches.contains_id("no-hooks"),
+                    format,
+                    fail_fast: matches.contains_id("fail-fast"),
+                    history: Some(History::local()),
+                    ..Default::default()
+                };
+                let handler = SyncHandler::new(
+                    Box::new(StdinPrompt {}),
+                    home,
+                    dotfile.repository(),
+                    dotfile.items(),
+                    options,
+                    None,
+                )
+                .with_reporter(get_reporter(format));
+                let summary = handler.apply(&plan)?;
+                log::debug!("Apply summary {:?}", summary);
+                if summary.errors > 0 {
+                    bail!("apply finished with {} error(s)", summary.errors);
                 }
             }
             _ => unreachable!(),
@@ -276,6 +287,372 @@ Example: dotf git status",
     }
 }
 
+/// Builds the command line interface, used both for parsing arguments and
+/// for generating completions and the man page.
+pub fn command() -> Command<'static> {
+    command!()
+        .name("dotf")
+        .about("Simple dotfile management")
+        .arg(
+            Arg::new("log")
+                .help("Display logs.")
+                .long("log")
+                .global(true)
+                .takes_value(true)
+                .value_parser(PossibleValuesParser::new(["trace", "debug", "info", "warn", "error"]))
+                .default_missing_value("info"),
+        )
+        .arg(
+            Arg::new("color")
+                .help("When to use colors.")
+                .long("color")
+                .global(true)
+                .takes_value(true)
+                .value_parser(PossibleValuesParser::new(["auto", "always", "never"])),
+        )
+        .arg(
+            Arg::new("icons")
+                .help("Icons in the output, nerd requires a Nerd Font.")
+                .long("icons")
+                .global(true)
+                .takes_value(true)
+                .value_parser(PossibleValuesParser::new(["nerd", "unicode", "ascii"])),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Sync home and repo files, defaults home -> repo.")
+                .args(selection_args())
+                .arg(fail_fast_arg())
+                .arg(
+                    Arg::new("home")
+                        .help("Sync files from repository to home.")
+                        .long("home")
+                )
+                .arg(
+                    Arg::new("repo")
+                        .help("Sync files from home to repository. This is the default, unless default_direction is set to home in the dotfile.")
+                        .long("repo")
+                        .conflicts_with("home")
+                )
+                .arg(rev_arg().conflicts_with("repo"))
+                .arg(Arg::new("dryrun").long("dryrun"))
+                .arg(
+                    Arg::new("diff")
+                        .long("diff")
+                        .conflicts_with("no-confirm")
+                        .help("Display inline diffs before"),
+                )
+                .arg(
+                    Arg::new("diff-command")
+                        .help("Use as diff command (default: diff -u --color)")
+                        .long("diff-command")
+                        .requires("diff")
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::new("no-confirm")
+                        .help("Skip confirmation prompt.")
+                        .long("no-confirm")
+                        .alias("yes")
+                        .short('y')
+                )
+                .arg(
+                    Arg::new("no-backup")
+                        .help("Do not create backups when copying to home.")
+                        .long("no-backup")
+                )
+                .arg(
+                    Arg::new("no-hooks")
+                        .help("Do not run any hooks, e.g. pre_sync, post_sync and on_change.")
+                        .long("no-hooks")
+                )
+                .arg(
+                    Arg::new("interactive").help("Review each file that is not up to date, e.g. to view diffs and choose which way to copy it.")
+                    .long("interactive")
+                    .short('i')
+                    .conflicts_with("non-interactive")
+                )
+                .arg(
+                    Arg::new("non-interactive")
+                        .help("Never prompt. Fails on files that differ unless --on-conflict is given. Implied when stdin is not a terminal.")
+                        .long("non-interactive")
+                )
+                .arg(
+                    Arg::new("on-conflict")
+                        .help("How to handle files that differ between home (ours) and repository (theirs).")
+                        .long("on-conflict")
+                        .takes_value(true)
+                        .value_parser(PossibleValuesParser::new(["skip", "ours", "theirs", "fail"]))
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Output format of the plan and summary, jsonl prints one JSON object per event. JSON implies --non-interactive.")
+                        .takes_value(true)
+                        .value_parser(PossibleValuesParser::new(["text", "json", "jsonl"]))
+                        .default_value("text"),
+                )
+                .arg(
+                    Arg::new("only")
+                        .help("Only include files matching patterns specified. Pattern uses glob by default. Set --regex to use regular expressions.")
+                        .long("only")
+                        .short('o')
+                        .takes_value(true)
+                        .action(ArgAction::Append)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("regex")
+                        .help("Use regular expressions in patterns specified in --only.")
+                        .long("regex")
+                        .short('r')
+                        .requires("only")
+                )
+                .arg(
+                    Arg::new("commit")
+                        .help("Create a git commit after syncing files. Only valid when copying files to repository.")
+                        .long("commit")
+                        .short('C')
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("push")
+                        .help("Run git push after commit.")
+                        .long("push")
+                        .requires("commit")
+                )
+                .arg(
+                    Arg::new("plan-out")
+                        .help("Save the plan to a file, to be applied later using dotf apply, instead of syncing.")
+                        .long("plan-out")
+                        .takes_value(true)
+                        .conflicts_with("commit")
+                ),
+        )
+        .subcommand(
+            Command::new("apply")
+                .about("Apply a plan saved using dotf sync --plan-out.")
+                .arg(fail_fast_arg())
+                .arg(
+                    Arg::new("plan")
+                        .help("Path to the plan.")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("no-hooks")
+                        .help("Do not run any hooks.")
+                        .long("no-hooks")
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Output format, jsonl prints one JSON object per event.")
+                        .takes_value(true)
+                        .value_parser(PossibleValuesParser::new(["text", "json", "jsonl"]))
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            Command::new("status")
+                .alias("st")
+                .about("Display the current status between home and repository.")
+                .args(selection_args())
+                .arg(fail_fast_arg())
+                .arg(rev_arg())
+                .arg(
+                    Arg::new("only")
+                        .help("Only include files matching patterns specified. Pattern uses glob by default. Set --regex to use regular expressions.")
+                        .long("only")
+                        .short('o')
+                        .takes_value(true)
+                        .action(ArgAction::Append)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("regex")
+                        .help("Use regular expressions in patterns specified in --only.")
+                        .long("regex")
+                        .short('r')
+                )
+                .arg(
+                    Arg::new("brief")
+                        .long("brief")
+                        .short('b')
+                        .help("Only display files that are not up to date."),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Output format, jsonl prints one JSON object per event.")
+                        .takes_value(true)
+                        .value_parser(PossibleValuesParser::new(["text", "json", "jsonl"]))
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show diff between files that do not match.")
+                .args(selection_args())
+                .arg(fail_fast_arg())
+                .arg(rev_arg())
+                .arg(
+                    Arg::new("only")
+                        .help("Only include files matching patterns specified. Pattern uses glob by default. Set --regex to use regular expressions.")
+                        .long("only")
+                        .short('o')
+                        .takes_value(true)
+                        .action(ArgAction::Append)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("regex")
+                        .help("Use regular expressions in patterns specified in --only.")
+                        .long("regex")
+                        .short('r')
+                )
+                .arg(
+                    Arg::new("diff-command")
+                        .long("diff-command")
+                        .help("Use as diff command (default: diff -u --color)")
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::new("no-pager")
+                        .long("no-pager")
+                        .help("Do not page the output, even if a pager is set."),
+                ),
+        )
+        .subcommand(
+            Command::new("list")
+                .alias("ls")
+                .about("List the items in the dotfile.")
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Output format.")
+                        .takes_value(true)
+                        .value_parser(PossibleValuesParser::new(["text", "json"]))
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            Command::new("log")
+                .about("Show the git history and the syncs on this machine of tracked files.")
+                .arg(
+                    Arg::new("item")
+                        .help("Name of an item, or a tracked file in home or the repository. Defaults to all files.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("patch")
+                        .help("Show the diff of each commit.")
+                        .long("patch")
+                        .short('p'),
+                ),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Show the files of an item, including ignored files.")
+                .arg(
+                    Arg::new("item")
+                        .help("Name of the item.")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Output format.")
+                        .takes_value(true)
+                        .value_parser(PossibleValuesParser::new(["text", "json"]))
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the dotfile, repository and environment for problems.")
+                .arg(
+                    Arg::new("diff-command")
+                        .long("diff-command")
+                        .help("Diff command to check for (default: diff -u --color)")
+                        .number_of_values(1),
+                ),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Watch files in home and sync changes to the repository.")
+                .arg(
+                    Arg::new("copy")
+                        .help("Copy changed files to the repository. Only prints changed files by default.")
+                        .long("copy")
+                        .short('c')
+                )
+                .arg(
+                    Arg::new("debounce")
+                        .help("Milliseconds to wait for further changes before handling them.")
+                        .long("debounce")
+                        .takes_value(true)
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("500")
+                )
+                .arg(
+                    Arg::new("commit-interval")
+                        .help("Create a git commit of copied files every N minutes.")
+                        .long("commit-interval")
+                        .takes_value(true)
+                        .value_parser(clap::value_parser!(u64))
+                        .requires("copy")
+                )
+                .arg(
+                    Arg::new("commit-message")
+                        .help("Message to use for commits (default: Update dotfiles).")
+                        .long("commit-message")
+                        .takes_value(true)
+                        .requires("commit-interval")
+                ),
+        )
+        .subcommand(
+            Command::new("edit").about("Edit the dotfile").arg(
+                Arg::new("editor")
+                    .long("editor")
+                    .short('e')
+                    .takes_value(true),
+            ),
+        )
+        .subcommand(
+            Command::new("git")
+                .trailing_var_arg(true)
+                .about("Run arbitrary git command in repository.")
+                .long_about(
+                    "Runs an arbitrary git command in the configured repository.\
+Usage: dotf git <...>
+Example: dotf git status",
+                )
+                .arg(
+                    Arg::new("args")
+                        .takes_value(true)
+                        .multiple_values(true)
+                        .allow_hyphen_values(true),
+                ),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print a completion script for the shell.")
+                .long_about(
+                    "Prints a completion script for the shell, e.g. for bash:
+    dotf completions bash > ~/.local/share/bash-completion/completions/dotf",
+                )
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .takes_value(true)
+                        .value_parser(PossibleValuesParser::new(["bash", "zsh", "fish"])),
+                ),
+        )
+        .subcommand(Command::new("man").about("Print the man page, formatted using roff."))
+}
+
 fn get_only(matches: &ArgMatches) -> Result<Option<Only>> {
     match matches.get_many::<String>("only") {
         Some(patterns) => {
@@ -291,30 +668,170 @@ fn get_only(matches: &ArgMatches) -> Result<Option<Only>> {
     }
 }
 
-fn get_diff_options(matches: &ArgMatches) -> Result<DiffOptions> {
-    match matches.get_one::<&str>("diff-command") {
-        Some(s) => {
-            let split: Vec<String> = s.split_whitespace().map(|s| s.to_string()).collect();
-            if split.is_empty() {
-                bail!("empty diff command")
-            }
-            Ok(DiffOptions::new(split))
+// Arguments for selecting items by name or group.
+fn selection_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("items")
+            .help("Only include these items. Includes all items by default.")
+            .takes_value(true)
+            .multiple_values(true),
+        Arg::new("group")
+            .help("Only include items in the group.")
+            .long("group")
+            .short('g')
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::new("exclude")
+            .help("Exclude the item, or the items in the group.")
+            .long("exclude")
+            .short('x')
+            .takes_value(true)
+            .action(ArgAction::Append),
+    ]
+}
+
+fn fail_fast_arg() -> Arg<'static> {
+    Arg::new("fail-fast")
+        .help("Stop at the first file that cannot be indexed or synced, instead of reporting all errors.")
+        .long("fail-fast")
+}
+
+fn rev_arg() -> Arg<'static> {
+    Arg::new("rev")
+        .help("Read repository files from a git revision, e.g. origin/main, without changing the working tree.")
+        .long("rev")
+        .takes_value(true)
+}
+
+// The filesystem to index and sync files with, where repository files are
+// read from the git revision given by --rev.
+fn get_filesystem(matches: &ArgMatches, repository: &Path) -> Result<Arc<dyn Filesystem>> {
+    let real: Arc<dyn Filesystem> = Arc::new(RealFs);
+    match matches.get_one::<String>("rev") {
+        Some(rev) => Ok(Arc::new(GitFs::new(real, repository.to_path_buf(), rev)?)),
+        None => Ok(real),
+    }
+}
+
+fn get_selection(matches: &ArgMatches) -> Selection {
+    let values = |id: &str| -> Vec<String> {
+        match matches.get_many::<String>(id) {
+            Some(values) => values.map(String::from).collect(),
+            None => Vec::new(),
         }
-        None => Ok(DiffOptions::default()),
+    };
+
+    Selection {
+        items: values("items"),
+        groups: values("group"),
+        exclude: values("exclude"),
+    }
+}
+
+fn get_reporter(format: Format) -> Arc<dyn Reporter> {
+    match format {
+        Format::JsonLines => Arc::new(JsonLinesReporter),
+        _ => Arc::new(TerminalReporter::new()),
+    }
+}
+
+fn get_format(matches: &ArgMatches) -> Result<Format> {
+    match matches.get_one::<String>("format") {
+        Some(s) => s.parse(),
+        None => Ok(Format::default()),
+    }
+}
+
+// Resolves the config from the flags of the command and the settings, and
+// sets up logging and colors accordingly.
+fn configure(matches: &ArgMatches, settings: &Settings) -> Result<Config> {
+    let config = Config::resolve(&get_flags(matches), settings, |name| env::var(name).ok())?;
+    if let Some(level) = &config.log_level {
+        logging::init(level)?;
+    }
+    set_colors(match config.color {
+        Color::Always => true,
+        Color::Never => false,
+        Color::Auto => io::stdout().is_terminal(),
+    });
+    set_icons(config.icons);
+    log::debug!("Config {:?}", config);
+    Ok(config)
+}
+
+fn get_flags(matches: &ArgMatches) -> Flags {
+    let mut flags = Flags {
+        log_level: matches.get_one::<String>("log").cloned(),
+        color: parse_flag(matches, "color"),
+        icons: parse_flag(matches, "icons"),
+        ..Default::default()
+    };
+    let matches = match matches.subcommand() {
+        Some((_, matches)) => matches,
+        None => return flags,
+    };
+
+    // Not every subcommand has all flags.
+    let value = |id: &str| matches.try_get_one::<String>(id).ok().flatten().cloned();
+    let present = |id: &str| matches.try_contains_id(id).unwrap_or(false);
+    flags.diff_command = value("diff-command");
+    flags.editor = value("editor");
+    flags.no_pager = present("no-pager");
+    flags.backup = present("no-backup").then_some(false);
+    flags.confirm = present("no-confirm").then_some(false);
+    if present("home") {
+        flags.direction = Some(Target::Home);
+    } else if present("repo") {
+        flags.direction = Some(Target::Repo);
+    }
+    flags
+}
+
+// A flag whose values are restricted by its parser, so it parses.
+fn parse_flag<T: FromStr>(matches: &ArgMatches, id: &str) -> Option<T> {
+    matches
+        .get_one::<String>(id)
+        .and_then(|value| value.parse().ok())
+}
+
+// The settings of the dotfile, or the default ones if it is not valid.
+fn lenient_settings(path: &Path) -> Settings {
+    match load_dotfile(path) {
+        Ok(dotfile) => dotfile.settings(),
+        Err(_) => Settings::default(),
     }
 }
 
 fn load_dotfile(path: &Path) -> Result<Dotfile> {
     let s = files::read_string(path)?;
-    let dotfile = Dotfile::from(&s)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let dotfile = Dotfile::from(&s, dir).map_err(|err| match err.downcast::<DotfError>() {
+        Ok(DotfError::Config { cause, .. }) => DotfError::Config {
+            path: Some(path.to_path_buf()),
+            cause,
+        }
+        .into(),
+        Ok(err) => err.into(),
+        Err(err) => err,
+    })?;
     Ok(dotfile)
 }
 
-fn get_dotfile_path(home: &Path) -> Option<PathBuf> {
-    let mut path = home.to_path_buf();
-    path.push(".config");
-    path.push("dotfiles.toml");
+// Prints the completion candidates of the kinds. Completion must never fail
+// or create a dotfile, so nothing is printed without a valid one.
+fn complete(home: PathBuf, kinds: &[String]) {
+    let dotfile = get_dotfile_path(&home).and_then(|path| load_dotfile(&path).ok());
+    if let Some(dotfile) = dotfile {
+        let repository = dotfile.repository();
+        let items = dotfile.items();
+        for candidate in completions::candidates(kinds, home, repository, &items) {
+            println!("{}", candidate);
+        }
+    }
+}
 
+fn get_dotfile_path(home: &Path) -> Option<PathBuf> {
+    let path = default_dotfile_path(home);
     if path.exists() {
         Some(path)
     } else {
@@ -322,11 +839,23 @@ fn get_dotfile_path(home: &Path) -> Option<PathBuf> {
     }
 }
 
+// The path of the dotfile, whether it exists or not.
+fn default_dotfile_path(home: &Path) -> PathBuf {
+    home.join(".config").join("dotfiles.toml")
+}
+
 fn bootstrap(path: &Path) -> Result<()> {
     let current_dir = std::env::current_dir()?;
-    let current_dir = current_dir.to_str().unwrap();
+    // The repository is a string in the dotfile.
+    let current_dir = match current_dir.to_str() {
+        Some(s) => toml::Value::from(s),
+        None => {
+            let err = io::Error::new(io::ErrorKind::InvalidData, "not valid UTF-8");
+            return Err(DotfError::io(&current_dir, err).into());
+        }
+    };
     let content = format!(
-        r#"repository = "{}"
+        r#"repository = {}
 
 [home]
 vim = ".vimrc"                 # type string
@@ -340,26 +869,8 @@ object = {{ files = ["scripts/*"], ignore = [ "*.out", ".cache" ] }}
     let mut file = std::fs::OpenOptions::new()
         .write(true)
         .create(true)
+        .truncate(true)
         .open(path)?;
     file.write_all(content.as_bytes())?;
     Ok(())
 }
-
-fn get_editor(flag: Option<&String>) -> String {
-    if let Some(s) = flag {
-        s.to_string()
-    } else if let Some(s) = try_get_env("VISUAK") {
-        s
-    } else if let Some(s) = try_get_env("EDITOR") {
-        s
-    } else {
-        "nano".to_string()
-    }
-}
-
-fn try_get_env(name: &str) -> Option<String> {
-    match env::var(name) {
-        Ok(s) => Some(s),
-        Err(_) => None,
-    }
-}
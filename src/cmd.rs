@@ -1,12 +1,29 @@
+use crate::error::DotfError;
 use anyhow::Result;
+use std::ffi::{OsStr, OsString};
 use std::path::PathBuf;
 use std::process::Command;
 use std::str::from_utf8;
 
-#[allow(dead_code)]
 pub struct Output {
     stdout: String,
     stderr: String,
+    success: bool,
+}
+
+impl Output {
+    pub fn stdout(&self) -> &str {
+        &self.stdout
+    }
+
+    pub fn stderr(&self) -> &str {
+        &self.stderr
+    }
+
+    // If the command exited successfully.
+    pub fn success(&self) -> bool {
+        self.success
+    }
 }
 
 /// Used to run external commands, such as git.
@@ -19,32 +36,87 @@ impl CmdRunner {
         Self { cwd }
     }
 
-    pub fn run(&self, cmd: &str, args: Vec<String>) -> Result<()> {
-        let mut cmd = self.build(cmd, args);
-        cmd.status()?;
+    pub fn run(&self, cmd: &str, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Result<()> {
+        let mut command = self.build(cmd, args);
+        command
+            .status()
+            .map_err(|err| DotfError::command(cmd, err))?;
+        Ok(())
+    }
+
+    /// Like `run`, but fails if the command exits with a non-zero status.
+    pub fn run_checked(
+        &self,
+        cmd: &str,
+        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    ) -> Result<()> {
+        let mut command = self.build(cmd, args);
+        let status = command
+            .status()
+            .map_err(|err| DotfError::command(cmd, err))?;
+        if !status.success() {
+            return Err(DotfError::Command {
+                cmd: cmd.to_string(),
+                cause: status.to_string(),
+            }
+            .into());
+        }
         Ok(())
     }
 
-    pub fn capture(&self, cmd: &str, args: Vec<String>) -> Result<Output> {
-        let mut cmd = self.build(cmd, args);
-        let output = cmd.output()?;
+    pub fn capture(
+        &self,
+        cmd: &str,
+        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    ) -> Result<Output> {
+        let mut command = self.build(cmd, args);
+        let output = command
+            .output()
+            .map_err(|err| DotfError::command(cmd, err))?;
         let stdout = from_utf8(&output.stdout)?;
         let stderr = from_utf8(&output.stderr)?;
         Ok(Output {
             stdout: stdout.to_string(),
             stderr: stderr.to_string(),
+            success: output.status.success(),
         })
     }
 
-    fn build(&self, cmd: &str, args: Vec<String>) -> Command {
-        log::info!("Running {} with args '{}'", cmd, args.join(" "));
+    /// Like `capture`, but returns stdout as is, e.g. for binary files, and
+    /// fails with stderr if the command exits with a non-zero status.
+    pub fn capture_bytes(
+        &self,
+        cmd: &str,
+        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    ) -> Result<Vec<u8>> {
+        let mut command = self.build(cmd, args);
+        let output = command
+            .output()
+            .map_err(|err| DotfError::command(cmd, err))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let cause = match stderr.trim() {
+                "" => output.status.to_string(),
+                stderr => stderr.to_string(),
+            };
+            return Err(DotfError::Command {
+                cmd: cmd.to_string(),
+                cause,
+            }
+            .into());
+        }
+        Ok(output.stdout)
+    }
+
+    fn build(&self, cmd: &str, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Command {
+        // Arguments may be paths that are not valid UTF-8.
+        let args: Vec<OsString> = args.into_iter().map(|a| a.as_ref().into()).collect();
+        let joined: Vec<_> = args.iter().map(|a| a.to_string_lossy()).collect();
+        log::info!("Running {} with args '{}'", cmd, joined.join(" "));
 
         let mut cmd = Command::new(cmd);
         cmd.current_dir(&self.cwd);
-
-        for arg in args {
-            cmd.arg(arg);
-        }
+        cmd.args(args);
         cmd
     }
 }
@@ -65,5 +137,13 @@ mod tests {
         let res = runner.capture("cargo", vec!["--help".to_string()]).unwrap();
         assert!(!res.stdout.is_empty());
         assert!(res.stderr.is_empty());
+        assert!(res.success());
+    }
+
+    #[test]
+    fn test_run_checked_failure() {
+        let runner = setup();
+        let res = runner.run_checked("cargo", vec!["--no-such-flag".to_string()]);
+        assert!(res.is_err());
     }
 }
@@ -1,24 +1,26 @@
 use crate::path::try_strip_home_prefix;
+use crate::style::{paint, Icon};
 use anyhow::Result;
 use crossterm::style::Stylize;
+use serde::{Serialize, Serializer};
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Entry {
     Ok {
         // The relative filepath for the dotfile, e.g .gitconfig
-        relpath: String,
+        relpath: PathBuf,
         status: Status,
         home_path: PathBuf,
         repo_path: PathBuf,
     },
-    Err(String),
+    Err(FileError),
 }
 
 impl Entry {
     pub fn new(
-        relpath: &str,
+        relpath: &Path,
         status: Status,
         home_path: PathBuf,
         repo_path: PathBuf,
@@ -32,8 +34,11 @@ impl Entry {
         })
     }
 
-    pub fn new_err(reason: String) -> Self {
-        Self::Err(reason)
+    pub fn new_err(path: impl AsRef<Path>, cause: String) -> Self {
+        Self::Err(FileError {
+            path: path.as_ref().to_path_buf(),
+            cause,
+        })
     }
 
     pub fn is_ok(&self) -> bool {
@@ -57,7 +62,7 @@ impl Entry {
         }
     }
 
-    pub fn get_relpath(&self) -> &str {
+    pub fn get_relpath(&self) -> &Path {
         if let Self::Ok { relpath, .. } = &self {
             relpath
         } else {
@@ -71,13 +76,27 @@ impl fmt::Display for Entry {
         match self {
             Entry::Ok {
                 relpath, status, ..
-            } => write!(f, "{} {}", status, relpath),
-            Entry::Err(reason) => write!(f, "{} {}", "".red(), reason),
+            } => write!(f, "{} {}", status, relpath.display()),
+            Entry::Err(err) => write!(f, "{} {}", paint(Icon::Error.as_str().red()), err),
         }
     }
 }
 
-#[derive(Clone)]
+/// A file, or pattern, of an item that could not be indexed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileError {
+    // The relative path of the file, or the pattern of the item.
+    pub path: PathBuf,
+    pub cause: String,
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.cause)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Status {
     Ok,
     Diff,
@@ -85,13 +104,31 @@ pub enum Status {
     MissingRepo,
 }
 
+impl Status {
+    /// A stable name of the status, e.g. for JSON output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Diff => "diff",
+            Status::MissingHome => "missing_home",
+            Status::MissingRepo => "missing_repo",
+        }
+    }
+}
+
+impl Serialize for Status {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
 impl fmt::Display for Status {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let icon = match self {
-            Status::Ok => "".green(),
-            Status::Diff => "".yellow(),
-            Status::MissingHome => "".yellow(),
-            Status::MissingRepo => "".yellow(),
+            Status::Ok => paint(Icon::Ok.as_str().green()),
+            Status::Diff => paint(Icon::Diff.as_str().yellow()),
+            Status::MissingHome => paint(Icon::MissingHome.as_str().yellow()),
+            Status::MissingRepo => paint(Icon::MissingRepo.as_str().yellow()),
         };
 
         write!(f, "{}", icon)
@@ -1,34 +1,64 @@
+use super::hooks::Hooks;
 use super::item::Item;
-use crate::path::{try_strip_home_prefix, LOCAL_CONFIG_DIR};
+use super::root::Root;
+use super::schema::validate;
+use super::settings::Settings;
+use crate::error::DotfError;
+use crate::path::{expand, home_path, try_strip_home_prefix, LOCAL_CONFIG_DIR, LOCAL_DATA_DIR};
 use anyhow::{bail, Result};
 use serde::Deserialize;
-use std::{collections::HashMap, path::PathBuf};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use toml::Value as Toml;
+use toml_edit::{Array, DocumentMut, InlineTable, TableLike, Value};
 
 /// Dotfile represents the ~/dotfiles.yml file (called DF),
 /// i.e the specification the user creates.
 #[derive(Debug)]
 pub struct Dotfile {
     // Path to the repository.
-    repository: String,
+    repository: PathBuf,
     // Files that should be tracked.
     items: Vec<Item>,
+    hooks: Hooks,
+    settings: Settings,
 }
 
 impl Dotfile {
-    pub fn from(s: &str) -> Result<Dotfile> {
+    /// Parses the dotfile content. Relative paths, such as the repository,
+    /// are resolved against `dir`, which should be the directory of the dotfile.
+    pub fn from(s: &str, dir: &Path) -> Result<Dotfile> {
+        // Everything but a missing repository is a problem with the dotfile.
+        Self::parse(s, dir).map_err(|err| match err.downcast::<DotfError>() {
+            Ok(err) => err.into(),
+            Err(err) => DotfError::Config {
+                path: None,
+                cause: format!("{:#}", err),
+            }
+            .into(),
+        })
+    }
+
+    fn parse(s: &str, dir: &Path) -> Result<Dotfile> {
+        let diagnostics = validate(s);
+        if !diagnostics.is_empty() {
+            let lines: Vec<String> = diagnostics.iter().map(|d| format!("  {}", d)).collect();
+            bail!("\n{}", lines.join("\n"));
+        }
+
         let df: RawDotfile = toml::from_str(s)?;
 
         // Validate that repository path exists
-        let path = PathBuf::from(&df.repository);
-        if !path.exists() {
-            bail!("invalid repository path: {}", df.repository)
+        let repository = expand(&df.repository, dir)?;
+        if !repository.exists() {
+            return Err(DotfError::MissingRepository(repository).into());
         }
 
+        let home = home_path();
         let mut items = Vec::new();
         if let Some(map) = df.home {
             for (name, value) in map {
-                let item = Item::from_toml(name, value)?;
+                let item = Item::from_toml(name, value)?.expand()?.relative_to(&home)?;
                 items.push(item);
             }
         }
@@ -36,19 +66,53 @@ impl Dotfile {
         if let Some(map) = df.config {
             let relative = try_strip_home_prefix(&LOCAL_CONFIG_DIR);
             for (name, value) in map {
-                let item = Item::from_toml(name, value)?.with_suffix(&relative);
+                let item = Item::from_toml(name, value)?
+                    .expand()?
+                    .with_suffix(&relative)
+                    .relative_to(&home)?
+                    .with_section("config");
                 items.push(item);
             }
         }
 
+        if let Some(roots) = df.roots {
+            for (root_name, raw) in roots {
+                let root = raw.to_root(&root_name, dir)?;
+                for (name, value) in raw_items(&root_name, raw.items) {
+                    let item = Item::from_toml(name, value)?
+                        .expand()?
+                        .relative_to(&root.path)?
+                        .with_root(root.clone())
+                        .with_section(&format!("roots.{}", root_name));
+                    items.push(item);
+                }
+            }
+        }
+
+        let hooks = Hooks {
+            pre_sync: df.pre_sync.unwrap_or_default(),
+            post_sync: df.post_sync.unwrap_or_default(),
+            fail_on_error: df.fail_on_hook_error,
+        };
+
         Ok(Dotfile {
-            repository: df.repository,
+            repository,
             items,
+            hooks,
+            settings: df.settings.unwrap_or_default(),
         })
     }
 
     pub fn repository(&self) -> PathBuf {
-        PathBuf::from(&self.repository)
+        self.repository.clone()
+    }
+
+    pub fn hooks(&self) -> Hooks {
+        self.hooks.clone()
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings.clone()
     }
 
     pub fn items(self) -> Vec<Item> {
@@ -56,6 +120,75 @@ impl Dotfile {
     }
 }
 
+/// Adds the pattern to the ignore list of the item in the dotfile content.
+/// Items in the string or list form are converted to the table form.
+/// Formatting and comments of the rest of the dotfile are preserved.
+pub fn add_ignore(s: &str, item: &Item, pattern: &str) -> Result<String> {
+    let mut doc: DocumentMut = s.parse()?;
+
+    // Items under a root are named <root>.<item>.
+    let (section, key) = match item.section.strip_prefix("roots.") {
+        Some(root) => {
+            let key = item.name.strip_prefix(&format!("{}.", root));
+            (vec!["roots", root], key.unwrap_or(&item.name))
+        }
+        None => (vec![item.section.as_str()], item.name.as_str()),
+    };
+
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for name in &section {
+        table = match table.get_mut(name).and_then(|t| t.as_table_like_mut()) {
+            Some(t) => t,
+            None => bail!("section {} not found", section.join(".")),
+        };
+    }
+
+    let value = match table.get_mut(key) {
+        Some(value) => value,
+        None => bail!("item {} not found", item.name),
+    };
+
+    if let Some(t) = value.as_table_like_mut() {
+        match t.get_mut("ignore").and_then(|i| i.as_array_mut()) {
+            Some(ignore) => push(ignore, pattern),
+            None => {
+                t.insert("ignore", toml_edit::value(Array::from_iter([pattern])));
+                t.fmt();
+            }
+        }
+        return Ok(doc.to_string());
+    }
+
+    let (files, decor) = match value.as_value() {
+        Some(Value::String(s)) => (Array::from_iter([s.value().as_str()]), s.decor()),
+        Some(Value::Array(a)) => (a.clone(), a.decor()),
+        _ => bail!("invalid type for {}", item.name),
+    };
+
+    let mut t = InlineTable::new();
+    t.insert("files", Value::Array(files));
+    t.insert("ignore", Value::Array(Array::from_iter([pattern])));
+    // Keep the whitespace and comment around the value.
+    *t.decor_mut() = decor.clone();
+    *value = toml_edit::value(t);
+    Ok(doc.to_string())
+}
+
+// Pushes the value to the array, moving the whitespace after the last
+// value to the new one, e.g. [ "a" ] -> [ "a", "b" ].
+fn push(array: &mut Array, value: &str) {
+    let suffix = array.iter_mut().last().map(|last| {
+        let suffix = last.decor().suffix().cloned();
+        last.decor_mut().set_suffix("");
+        suffix
+    });
+
+    array.push(value);
+    if let (Some(Some(suffix)), Some(last)) = (suffix, array.iter_mut().last()) {
+        last.decor_mut().set_suffix(suffix);
+    }
+}
+
 type ItemMap = HashMap<String, Toml>;
 
 // The type which is read from file.
@@ -63,8 +196,62 @@ type ItemMap = HashMap<String, Toml>;
 struct RawDotfile {
     // Path to the repository.
     repository: String,
+    pre_sync: Option<Vec<String>>,
+    post_sync: Option<Vec<String>>,
+    #[serde(default)]
+    fail_on_hook_error: bool,
     home: Option<ItemMap>,
     config: Option<ItemMap>,
+    roots: Option<HashMap<String, RawRoot>>,
+    settings: Option<Settings>,
+}
+
+#[derive(Deserialize)]
+struct RawRoot {
+    path: Option<String>,
+    #[serde(default)]
+    readonly: bool,
+    #[serde(default)]
+    sudo: bool,
+    #[serde(flatten)]
+    items: ItemMap,
+}
+
+impl RawRoot {
+    fn to_root(&self, name: &str, dir: &Path) -> Result<Root> {
+        let valid_name =
+            !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\']);
+        if !valid_name {
+            bail!("invalid root name: {}", name);
+        }
+
+        let path = match (&self.path, name) {
+            (Some(path), _) => expand(path, dir)?,
+            // Well-known roots that can be used without a path.
+            (None, "data") => LOCAL_DATA_DIR.clone(),
+            (None, _) => bail!("roots.{}: path is required", name),
+        };
+
+        if path == home_path() {
+            bail!(
+                "roots.{}: use the [home] section for the home directory",
+                name
+            );
+        }
+
+        let mut root = Root::new(name, path);
+        root.readonly = self.readonly;
+        root.sudo = self.sudo;
+        Ok(root)
+    }
+}
+
+// Items under a root are named <root>.<item> to keep them apart from
+// items in other sections.
+fn raw_items(root: &str, items: ItemMap) -> impl Iterator<Item = (String, Toml)> + '_ {
+    items
+        .into_iter()
+        .map(move |(name, value)| (format!("{}.{}", root, name), value))
 }
 
 #[cfg(test)]
@@ -82,10 +269,11 @@ mod tests {
         glob = "src/*.rs"
         object = { files = ["text.txt" ]}
         with-ignore = { files = ["text.txt"], ignore = [ ".cache" ] }
+        with-repo-dir = { files = ["src/*.rs"], repo_dir = "rust/src" }
         "#;
 
-        let dotfile = Dotfile::from(dotfile_content).expect("valid dotfile");
-        assert_eq!(dotfile.items.len(), 5);
+        let dotfile = Dotfile::from(dotfile_content, Path::new(".")).expect("valid dotfile");
+        assert_eq!(dotfile.items.len(), 6);
     }
 
     #[test]
@@ -96,6 +284,8 @@ mod tests {
             "empty-object = {}",
             "boolean = true",
             "integer = 1",
+            "escaping-repo-dir = { files = [\"a\"], repo_dir = \"../a\" }",
+            "absolute-repo-dir = { files = [\"a\"], repo_dir = \"/a\" }",
         ];
         for item in tests {
             let dotfile_content = format!(
@@ -106,8 +296,187 @@ mod tests {
         "#,
                 item
             );
-            let res = Dotfile::from(&dotfile_content);
+            let res = Dotfile::from(&dotfile_content, Path::new("."));
             assert!(res.is_err());
         }
     }
+
+    #[test]
+    fn test_from_expands_paths() {
+        // Variables are tested using expand_vars with a lookup, since the
+        // environment is shared by tests running in parallel.
+        let dotfile_content = r#"
+        repository = "src"
+
+        [home]
+        tilde = "~/.vimrc"
+        "#;
+
+        let dotfile = Dotfile::from(dotfile_content, Path::new(".")).expect("valid dotfile");
+        assert_eq!(PathBuf::from("./src"), dotfile.repository());
+
+        let items = dotfile.items();
+        let tilde = items.iter().find(|item| item.name == "tilde").unwrap();
+        assert_eq!(vec![PathBuf::from(".vimrc")], tilde.files);
+
+        let undefined = "repository = \"$DOTF_TEST_UNDEFINED\"\n[home]\nvim = \".vimrc\"";
+        assert!(Dotfile::from(undefined, Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_from_hooks() {
+        let dotfile_content = r#"
+        repository = "./"
+        pre_sync = [ "echo pre" ]
+        post_sync = [ "echo post", "echo done" ]
+
+        [home]
+        tmux = { files = [ "tmux.conf" ], on_change = [ "tmux source-file ~/tmux.conf" ] }
+        "#;
+
+        let dotfile = Dotfile::from(dotfile_content, Path::new(".")).expect("valid dotfile");
+        let hooks = dotfile.hooks();
+        assert_eq!(vec!["echo pre"], hooks.pre_sync);
+        assert_eq!(2, hooks.post_sync.len());
+        assert!(!hooks.fail_on_error);
+
+        let items = dotfile.items();
+        assert_eq!(1, items[0].on_change.len());
+    }
+
+    #[test]
+    fn test_from_settings() {
+        let dotfile_content = r#"
+        repository = "./"
+
+        [settings]
+        diff_command = "delta"
+        backup = false
+        default_direction = "home"
+        "#;
+
+        let dotfile = Dotfile::from(dotfile_content, Path::new(".")).expect("valid dotfile");
+        let settings = dotfile.settings();
+        assert_eq!(Some("delta"), settings.diff_command.as_deref());
+        assert_eq!(Some(false), settings.backup);
+        assert_eq!(Some("home"), settings.default_direction.as_deref());
+        assert_eq!(None, settings.editor);
+    }
+
+    #[test]
+    fn test_from_roots() {
+        let dotfile_content = r#"
+        repository = "./"
+
+        [roots.project]
+        path = "src"
+        readonly = true
+        rust = "*.rs"
+
+        [roots.data]
+        fonts = "fonts/*"
+        "#;
+
+        let dotfile = Dotfile::from(dotfile_content, Path::new(".")).expect("valid dotfile");
+        let items = dotfile.items();
+        assert_eq!(2, items.len());
+
+        let rust = items
+            .iter()
+            .find(|item| item.name == "project.rust")
+            .unwrap();
+        assert_eq!("roots.project", rust.section);
+        let root = rust.root.as_ref().expect("item to have a root");
+        assert_eq!("project", root.name);
+        assert_eq!(PathBuf::from("./src"), root.path);
+        assert!(root.readonly);
+        assert!(!root.sudo);
+
+        let fonts = items.iter().find(|item| item.name == "data.fonts").unwrap();
+        let root = fonts.root.as_ref().expect("item to have a root");
+        assert_eq!(*LOCAL_DATA_DIR, root.path);
+    }
+
+    #[test]
+    fn test_from_invalid_roots() {
+        let tests = [
+            // Missing path
+            "[roots.project]\nrust = \"*.rs\"",
+            // Invalid name
+            "[roots.\"a/b\"]\npath = \"src\"",
+            // Item outside of root
+            "[roots.project]\npath = \"src\"\nrust = \"/etc/*.rs\"",
+        ];
+        for root in tests {
+            let dotfile_content = format!("repository = \"./\"\n{}", root);
+            let res = Dotfile::from(&dotfile_content, Path::new("."));
+            assert!(res.is_err(), "{}", root);
+        }
+    }
+
+    #[test]
+    fn test_from_undefined_variable() {
+        let dotfile_content = r#"
+        repository = "./"
+        [home]
+        undefined = "$DOTF_TEST_UNDEFINED/file"
+        "#;
+
+        let res = Dotfile::from(dotfile_content, Path::new("."));
+        let err = res.expect_err("undefined variable").to_string();
+        assert!(err.contains("DOTF_TEST_UNDEFINED"));
+    }
+
+    #[test]
+    fn test_from_outside_home() {
+        let dotfile_content = r#"
+        repository = "./"
+        [home]
+        outside = "/etc/hosts"
+        "#;
+
+        let res = Dotfile::from(dotfile_content, Path::new("."));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_add_ignore() {
+        let content = r#"repository = "./"
+
+[home]
+# Notes
+notes = "notes/*" # comment
+list = [ "a/*", "b/*" ]
+table = { files = [ "c/*" ], ignore = [ "*.o" ] }
+
+[roots.etc]
+path = "/etc"
+conf = { files = [ "*.conf" ] }
+"#;
+        let tests = [
+            (
+                Item::simple_new("notes", "notes/*"),
+                r#"notes = { files = ["notes/*"], ignore = ["notes/a.md"] } # comment"#,
+            ),
+            (
+                Item::simple_new("list", "a/*"),
+                r#"list = { files = [ "a/*", "b/*" ], ignore = ["notes/a.md"] }"#,
+            ),
+            (
+                Item::simple_new("table", "c/*"),
+                r#"table = { files = [ "c/*" ], ignore = [ "*.o", "notes/a.md" ] }"#,
+            ),
+            (
+                Item::simple_new("etc.conf", "*.conf").with_section("roots.etc"),
+                r#"conf = { files = [ "*.conf" ], ignore = ["notes/a.md"] }"#,
+            ),
+        ];
+
+        for (item, expected) in tests {
+            let updated = add_ignore(content, &item, "notes/a.md").unwrap();
+            assert!(updated.contains(expected), "{}", updated);
+            assert!(updated.contains("# Notes"));
+            assert!(Dotfile::from(&updated, Path::new(".")).is_ok());
+        }
+    }
 }
@@ -0,0 +1,11 @@
+/// Global commands to run when syncing. Item specific commands are
+/// specified on each item (`on_change`).
+#[derive(Clone, Debug, Default)]
+pub struct Hooks {
+    // Commands to run before syncing any files.
+    pub pre_sync: Vec<String>,
+    // Commands to run after all files has been synced.
+    pub post_sync: Vec<String>,
+    // Abort the sync if a hook fails, else it is only reported.
+    pub fail_on_error: bool,
+}
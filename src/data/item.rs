@@ -1,15 +1,27 @@
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
-use anyhow::{bail, Result};
+use super::root::Root;
+use crate::path::expand_str;
+use anyhow::{anyhow, bail, Result};
 use glob::Pattern;
 use serde::Deserialize;
-use toml::{self, Value as Toml};
+use toml::Value as Toml;
 
 #[derive(Clone, Debug)]
 pub struct Item {
     pub name: String,
+    // The dotfile section the item is defined in, e.g. home or roots.etc.
+    pub section: String,
     pub files: Vec<PathBuf>,
     pub ignore: Option<Vec<String>>,
+    // Directory in the repository, if it differs from the home layout.
+    pub repo_dir: Option<PathBuf>,
+    // The root that files are relative to, home if not set.
+    pub root: Option<Root>,
+    // Commands to run after files of this item has been written to home.
+    pub on_change: Vec<String>,
+    // Groups the item belongs to, used to select items.
+    pub groups: Vec<String>,
 }
 
 #[macro_export]
@@ -38,11 +50,42 @@ impl Item {
     pub fn new(name: String, files: Vec<String>, ignore: Option<Vec<String>>) -> Self {
         Self {
             name,
+            section: String::from("home"),
             ignore,
             files: files.iter().map(PathBuf::from).collect(),
+            repo_dir: None,
+            root: None,
+            on_change: Vec::new(),
+            groups: Vec::new(),
         }
     }
 
+    pub fn with_section(mut self, section: &str) -> Self {
+        self.section = section.to_string();
+        self
+    }
+
+    pub fn with_root(mut self, root: Root) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    pub fn with_repo_dir(mut self, dir: &str) -> Result<Self> {
+        let path = PathBuf::from(dir);
+        let escapes = path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+        if dir.trim().is_empty() || escapes {
+            bail!(
+                "{}: repo_dir must be a relative path within the repository",
+                self.name
+            );
+        }
+
+        self.repo_dir = Some(path);
+        Ok(self)
+    }
+
     pub fn from_str(name: String, file: String) -> Self {
         Self::new(name, vec![file], None)
     }
@@ -74,21 +117,64 @@ impl Item {
                 Self::from_list(name, files)
             }
             Toml::Table(t) => {
-                let s = toml::to_string(&t)?;
-                let obj: Obj = toml::from_str(&s)?;
-                Self::new(name, obj.files, obj.ignore)
+                let obj: Obj = t.try_into().map_err(|err| anyhow!("{}: {}", name, err))?;
+                let mut item = Self::new(name, obj.files, obj.ignore);
+                item.on_change = obj.on_change.unwrap_or_default();
+                item.groups = obj.groups.unwrap_or_default();
+                match obj.repo_dir {
+                    Some(dir) => item.with_repo_dir(&dir)?,
+                    None => item,
+                }
             }
             _ => bail!("invalid type for {}", name),
         };
         Ok(item)
     }
 
-    pub fn with_suffix(mut self, suffix: &str) -> Self {
-        let root = PathBuf::from(suffix);
-        self.files = self.files.iter().map(|p| root.join(p)).collect();
+    pub fn with_suffix(mut self, suffix: &Path) -> Self {
+        self.files = self.files.iter().map(|p| suffix.join(p)).collect();
         self
     }
 
+    /// Expands `~` and environment variables in the file paths.
+    pub fn expand(mut self) -> Result<Self> {
+        let mut files = Vec::new();
+        for path in &self.files {
+            // Paths from the dotfile are always valid UTF-8.
+            let expanded = match path.to_str() {
+                Some(s) => expand_str(s).map_err(|err| anyhow!("{}: {}", self.name, err))?,
+                None => path.clone(),
+            };
+            files.push(expanded);
+        }
+        self.files = files;
+        Ok(self)
+    }
+
+    /// Turns absolute file paths into paths relative to `root`.
+    /// Fails if a path is outside of `root`.
+    pub fn relative_to(mut self, root: &Path) -> Result<Self> {
+        let mut files = Vec::new();
+        for path in &self.files {
+            if !path.is_absolute() {
+                files.push(path.clone());
+                continue;
+            }
+
+            match path.strip_prefix(root) {
+                Ok(p) => files.push(p.to_path_buf()),
+                Err(_) => bail!(
+                    "{}: {} is not within {}",
+                    self.name,
+                    path.display(),
+                    root.display()
+                ),
+            }
+        }
+        self.files = files;
+        Ok(self)
+    }
+
     pub fn ignore_patterns(&self) -> Result<Option<Vec<Pattern>>> {
         let patterns = match &self.ignore {
             None => None,
@@ -104,10 +190,17 @@ impl Item {
     }
 }
 
+// The keys allowed in the table form of an item.
+pub const ITEM_KEYS: [&str; 5] = ["files", "ignore", "repo_dir", "on_change", "groups"];
+
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 struct Obj {
     ignore: Option<Vec<String>>,
     files: Vec<String>,
+    repo_dir: Option<String>,
+    on_change: Option<Vec<String>>,
+    groups: Option<Vec<String>>,
 }
 
 #[cfg(test)]
@@ -1,7 +1,14 @@
 pub mod entry;
 pub mod file;
+pub mod hooks;
 pub mod item;
+pub mod root;
+pub mod schema;
+pub mod settings;
 
-pub use entry::{Entry, Status};
+pub use entry::{Entry, FileError, Status};
 pub use file::Dotfile;
+pub use hooks::Hooks;
 pub use item::Item;
+pub use root::Root;
+pub use settings::Settings;
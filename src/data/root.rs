@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+
+/// A root is a directory, other than home, that items can be relative to,
+/// e.g. `/etc`. Files under a root are stored in the repository under a
+/// directory with the name of the root.
+#[derive(Clone, Debug)]
+pub struct Root {
+    pub name: String,
+    pub path: PathBuf,
+    // Never write files to the root, i.e. only sync to the repository.
+    pub readonly: bool,
+    // Use sudo when writing files to the root.
+    pub sudo: bool,
+}
+
+impl Root {
+    pub fn new(name: &str, path: PathBuf) -> Self {
+        Self {
+            name: name.to_string(),
+            path,
+            readonly: false,
+            sudo: false,
+        }
+    }
+}
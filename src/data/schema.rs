@@ -0,0 +1,413 @@
+use super::item::ITEM_KEYS;
+use std::fmt;
+use std::ops::Range;
+use toml_edit::{ImDocument, Item as TomlItem, TableLike, Value};
+
+/// The JSON Schema of the dotfile, which can be used by editors for
+/// validation and completion.
+pub const JSON_SCHEMA: &str = include_str!("../../schema/dotfiles.schema.json");
+
+// The keys allowed at the top level of the dotfile.
+const TOP_KEYS: [&str; 8] = [
+    "repository",
+    "pre_sync",
+    "post_sync",
+    "fail_on_hook_error",
+    "home",
+    "config",
+    "roots",
+    "settings",
+];
+
+// The keys allowed in the settings section.
+const SETTINGS_KEYS: [&str; 10] = [
+    "diff_command",
+    "merge_command",
+    "pager",
+    "editor",
+    "backup",
+    "confirm",
+    "default_direction",
+    "log_level",
+    "color",
+    "icons",
+];
+
+// The keys allowed in a root section, besides items.
+const ROOT_KEYS: [&str; 3] = ["path", "readonly", "sudo"];
+
+/// A problem found in the dotfile, at a 1-based line and column.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Validates the content of a dotfile against the schema. All problems
+/// are returned, in order of appearance, rather than only the first one.
+pub fn validate(s: &str) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        source: s,
+        diagnostics: Vec::new(),
+    };
+
+    match ImDocument::parse(s) {
+        Ok(doc) => validator.document(doc.as_table()),
+        Err(err) => {
+            let span = err.span().unwrap_or(0..0);
+            validator.push(Some(span), err.message().to_string());
+        }
+    }
+
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+struct Validator<'a> {
+    source: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn document(&mut self, table: &dyn TableLike) {
+        if !table.contains_key("repository") {
+            self.push(Some(0..0), "missing required key `repository`".to_string());
+        }
+
+        for (key, item) in table.iter() {
+            let key_span = table.key(key).and_then(|k| k.span());
+            match key {
+                "repository" => self.string(key, item),
+                "pre_sync" | "post_sync" => self.strings(key, item, false),
+                "fail_on_hook_error" => self.boolean(key, item),
+                "home" | "config" => match item.as_table_like() {
+                    Some(section) => self.items(key, section, &[]),
+                    None => self.invalid_type(key, item, "table"),
+                },
+                "roots" => match item.as_table_like() {
+                    Some(roots) => self.roots(roots),
+                    None => self.invalid_type(key, item, "table"),
+                },
+                "settings" => match item.as_table_like() {
+                    Some(settings) => self.settings(settings),
+                    None => self.invalid_type(key, item, "table"),
+                },
+                _ => self.unknown_key(key_span, key, key, &TOP_KEYS),
+            }
+        }
+    }
+
+    fn roots(&mut self, roots: &dyn TableLike) {
+        for (name, item) in roots.iter() {
+            let path = format!("roots.{}", name);
+            let root = match item.as_table_like() {
+                Some(root) => root,
+                None => {
+                    self.invalid_type(&path, item, "table");
+                    continue;
+                }
+            };
+
+            for key in ROOT_KEYS {
+                if let Some(value) = root.get(key) {
+                    let path = format!("{}.{}", path, key);
+                    match key {
+                        "path" => self.string(&path, value),
+                        _ => self.boolean(&path, value),
+                    }
+                }
+            }
+            self.items(&path, root, &ROOT_KEYS);
+        }
+    }
+
+    fn settings(&mut self, table: &dyn TableLike) {
+        for (key, value) in table.iter() {
+            let path = format!("settings.{}", key);
+            match key {
+                "backup" | "confirm" => self.boolean(&path, value),
+                "default_direction" => self.one_of(&path, value, &["home", "repo"]),
+                "log_level" => {
+                    self.one_of(&path, value, &["trace", "debug", "info", "warn", "error"])
+                }
+                "color" => self.one_of(&path, value, &["auto", "always", "never"]),
+                "icons" => self.one_of(&path, value, &["nerd", "unicode", "ascii"]),
+                "diff_command" | "merge_command" | "pager" | "editor" => self.string(&path, value),
+                _ => {
+                    let key_span = table.key(key).and_then(|k| k.span());
+                    self.unknown_key(key_span, &path, key, &SETTINGS_KEYS);
+                }
+            }
+        }
+    }
+
+    // Validates each item in the section, except for the reserved keys.
+    fn items(&mut self, section: &str, table: &dyn TableLike, reserved: &[&str]) {
+        for (name, item) in table.iter() {
+            if reserved.contains(&name) {
+                continue;
+            }
+
+            let path = format!("{}.{}", section, name);
+            if let Some(table) = item.as_table_like() {
+                self.item_table(&path, table, item.span());
+                continue;
+            }
+
+            match item.as_value() {
+                Some(Value::String(s)) if s.value().trim().is_empty() => {
+                    self.push(item.span(), format!("`{}` must not be empty", path));
+                }
+                Some(Value::String(_)) => {}
+                Some(Value::Array(_)) => self.strings(&path, item, true),
+                _ => self.invalid_type(&path, item, "string, array or table"),
+            }
+        }
+    }
+
+    fn item_table(&mut self, path: &str, table: &dyn TableLike, span: Option<Range<usize>>) {
+        match table.get("files") {
+            Some(files) => self.strings(&format!("{}.files", path), files, true),
+            None => self.push(span, format!("missing required key `files` in `{}`", path)),
+        }
+
+        for (key, value) in table.iter() {
+            let key_path = format!("{}.{}", path, key);
+            match key {
+                "files" => {}
+                "ignore" | "on_change" | "groups" => self.strings(&key_path, value, false),
+                "repo_dir" => self.string(&key_path, value),
+                _ => {
+                    let key_span = table.key(key).and_then(|k| k.span());
+                    self.unknown_key(key_span, &key_path, key, &ITEM_KEYS);
+                }
+            }
+        }
+    }
+
+    fn string(&mut self, path: &str, item: &TomlItem) {
+        if !matches!(item.as_value(), Some(Value::String(_))) {
+            self.invalid_type(path, item, "string");
+        }
+    }
+
+    // Validates that the item is one of the allowed strings.
+    fn one_of(&mut self, path: &str, item: &TomlItem, allowed: &[&str]) {
+        match item.as_str() {
+            Some(s) if allowed.contains(&s) => {}
+            Some(s) => {
+                let message = format!(
+                    "invalid value for `{}`: expected one of {}, found `{}`",
+                    path,
+                    allowed.join(", "),
+                    s
+                );
+                self.push(item.span(), message);
+            }
+            None => self.invalid_type(path, item, "string"),
+        }
+    }
+
+    fn boolean(&mut self, path: &str, item: &TomlItem) {
+        if !matches!(item.as_value(), Some(Value::Boolean(_))) {
+            self.invalid_type(path, item, "boolean");
+        }
+    }
+
+    fn strings(&mut self, path: &str, item: &TomlItem, non_empty: bool) {
+        let array = match item.as_array() {
+            Some(array) => array,
+            None => {
+                self.invalid_type(path, item, "array of strings");
+                return;
+            }
+        };
+
+        if non_empty && array.is_empty() {
+            self.push(item.span(), format!("`{}` must not be empty", path));
+        }
+
+        for value in array.iter() {
+            if !value.is_str() {
+                let message = format!(
+                    "invalid type in `{}`: expected string, found {}",
+                    path,
+                    value.type_name()
+                );
+                self.push(value.span(), message);
+            }
+        }
+    }
+
+    fn invalid_type(&mut self, path: &str, item: &TomlItem, expected: &str) {
+        let message = format!(
+            "invalid type for `{}`: expected {}, found {}",
+            path,
+            expected,
+            item.type_name()
+        );
+        self.push(item.span(), message);
+    }
+
+    fn unknown_key(&mut self, span: Option<Range<usize>>, path: &str, key: &str, known: &[&str]) {
+        let mut message = format!("unknown key `{}`", path);
+        if let Some(suggestion) = known.iter().find(|k| distance(k, key) <= 2) {
+            message.push_str(&format!(", did you mean `{}`?", suggestion));
+        }
+        self.push(span, message);
+    }
+
+    fn push(&mut self, span: Option<Range<usize>>, message: String) {
+        let offset = span.map(|s| s.start).unwrap_or(0);
+        let (line, column) = position(self.source, offset);
+        self.diagnostics.push(Diagnostic {
+            line,
+            column,
+            message,
+        });
+    }
+}
+
+// Converts a byte offset into a 1-based line and column.
+fn position(s: &str, offset: usize) -> (usize, usize) {
+    let before = &s[..offset.min(s.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = match before.rfind('\n') {
+        Some(i) => before[i + 1..].chars().count() + 1,
+        None => before.chars().count() + 1,
+    };
+    (line, column)
+}
+
+// The Levenshtein distance between a and b, used to suggest keys.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr.push((prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1));
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(s: &str) -> Vec<String> {
+        validate(s).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_valid() {
+        let content = r#"
+repository = "./"
+pre_sync = [ "echo" ]
+fail_on_hook_error = true
+
+[home]
+string = "file"
+list = [ "a", "b" ]
+table = { files = [ "c" ], ignore = [ "*.o" ], repo_dir = "x", on_change = [ "echo" ], groups = [ "a" ] }
+
+[home.standard]
+files = [ "d" ]
+
+[roots.etc]
+path = "/etc"
+readonly = true
+hosts = "hosts"
+
+[settings]
+diff_command = "delta"
+backup = false
+default_direction = "home"
+color = "never"
+icons = "ascii"
+"#;
+        assert!(validate(content).is_empty());
+    }
+
+    #[test]
+    fn test_validate_collects_all_errors() {
+        let content = r#"repository = "./"
+repo = "typo"
+
+[home]
+empty = ""
+number = 1
+glob = { files = [ "a" ], ignroe = [ "*.o" ] }
+nofiles = { ignore = [ "*.o" ] }
+mixed = [ "a", 1 ]
+
+[roots.etc]
+readonly = "yes"
+
+[settings]
+confirm = "no"
+default_direction = "up"
+pagre = "less"
+"#;
+        let expected = vec![
+            "2:1: unknown key `repo`",
+            "5:9: `home.empty` must not be empty",
+            "6:10: invalid type for `home.number`: expected string, array or table, found integer",
+            "7:27: unknown key `home.glob.ignroe`, did you mean `ignore`?",
+            "8:11: missing required key `files` in `home.nofiles`",
+            "9:16: invalid type in `home.mixed`: expected string, found integer",
+            "12:12: invalid type for `roots.etc.readonly`: expected boolean, found string",
+            "15:11: invalid type for `settings.confirm`: expected boolean, found string",
+            "16:21: invalid value for `settings.default_direction`: expected one of home, repo, found `up`",
+            "17:1: unknown key `settings.pagre`, did you mean `pager`?",
+        ];
+        assert_eq!(expected, messages(content));
+    }
+
+    #[test]
+    fn test_validate_syntax_error() {
+        let diagnostics = validate("repository = \"./\"\n[home\n");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(2, diagnostics[0].line);
+    }
+
+    #[test]
+    fn test_validate_missing_repository() {
+        assert_eq!(
+            vec!["1:1: missing required key `repository`"],
+            messages("[home]\na = \"b\"")
+        );
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema: serde_json::Value = serde_json::from_str(JSON_SCHEMA).unwrap();
+        let properties = schema["properties"].as_object().unwrap();
+        for key in TOP_KEYS {
+            assert!(properties.contains_key(key), "{}", key);
+        }
+
+        let settings = schema["properties"]["settings"]["properties"]
+            .as_object()
+            .unwrap();
+        for key in SETTINGS_KEYS {
+            assert!(settings.contains_key(key), "{}", key);
+        }
+
+        let item = &schema["$defs"]["item"]["oneOf"][2]["properties"];
+        for key in ITEM_KEYS {
+            assert!(item.get(key).is_some(), "{}", key);
+        }
+    }
+}
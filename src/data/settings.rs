@@ -0,0 +1,25 @@
+use serde::Deserialize;
+
+/// The optional [settings] section of the dotfile. Each setting can be
+/// overridden by a command line flag or an environment variable.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Settings {
+    // Command used to diff files, e.g. "delta".
+    pub diff_command: Option<String>,
+    // Command used to merge a file in home with the one in the repository.
+    pub merge_command: Option<String>,
+    // Command used to page the output of dotf diff.
+    pub pager: Option<String>,
+    pub editor: Option<String>,
+    // Create backups when copying files to home.
+    pub backup: Option<bool>,
+    // Ask before writing files when syncing.
+    pub confirm: Option<bool>,
+    // Where dotf sync copies files to, home or repo.
+    pub default_direction: Option<String>,
+    pub log_level: Option<String>,
+    // When to use colors: auto, always or never.
+    pub color: Option<String>,
+    // Icons in the output: nerd, unicode or ascii.
+    pub icons: Option<String>,
+}
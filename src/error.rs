@@ -0,0 +1,149 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The failures that users can act on. Other errors are reported as is.
+#[derive(Debug)]
+pub enum DotfError {
+    /// The dotfile could not be parsed or is invalid.
+    Config {
+        path: Option<PathBuf>,
+        cause: String,
+    },
+    /// The repository in the dotfile does not exist.
+    MissingRepository(PathBuf),
+    /// Reading or writing the path failed.
+    Io { path: PathBuf, source: io::Error },
+    /// The glob pattern is invalid.
+    Glob { pattern: String, cause: String },
+    /// An external command, such as git or a hook, could not be run or failed.
+    Command { cmd: String, cause: String },
+    /// The user aborted a prompt.
+    Aborted,
+}
+
+impl DotfError {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        DotfError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// The command could not be run, e.g. because it is not installed.
+    pub fn command(cmd: &str, err: io::Error) -> Self {
+        DotfError::Command {
+            cmd: cmd.to_string(),
+            cause: err.to_string(),
+        }
+    }
+
+    /// The exit code of the process when failing with the error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DotfError::Config { .. } => 3,
+            DotfError::MissingRepository(_) => 4,
+            DotfError::Io { .. } => 5,
+            DotfError::Glob { .. } => 6,
+            DotfError::Command { .. } => 7,
+            DotfError::Aborted => 130,
+        }
+    }
+
+    /// Suggests how to fix common mistakes.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            DotfError::Config { .. } => {
+                Some("run dotf doctor to see all problems of the dotfile".to_string())
+            }
+            DotfError::MissingRepository(path) => Some(format!(
+                "clone or create the repository at {}, or change repository in the dotfile",
+                path.display()
+            )),
+            DotfError::Io { path, source } => match source.kind() {
+                io::ErrorKind::NotFound => Some(format!("check that {} exists", path.display())),
+                io::ErrorKind::PermissionDenied => Some(format!(
+                    "check the permissions of {}, or use a root with sudo = true",
+                    path.display()
+                )),
+                _ => None,
+            },
+            DotfError::Glob { .. } => {
+                Some("escape special characters using brackets, e.g. [[] for [".to_string())
+            }
+            DotfError::Command { cmd, cause } if cause.contains("No such file") => {
+                Some(format!("check that {} is installed and in PATH", cmd))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DotfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DotfError::Config { path, cause } => {
+                write!(f, "invalid dotfile")?;
+                if let Some(path) = path {
+                    write!(f, " {}", path.display())?;
+                }
+                // Diagnostics of the schema are listed on lines of their own.
+                match cause.starts_with('\n') {
+                    true => write!(f, ":{}", cause),
+                    false => write!(f, ": {}", cause),
+                }
+            }
+            DotfError::MissingRepository(path) => {
+                write!(f, "repository does not exist: {}", path.display())
+            }
+            DotfError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            DotfError::Glob { pattern, cause } => {
+                write!(f, "invalid glob pattern {}: {}", pattern, cause)
+            }
+            DotfError::Command { cmd, cause } => write!(f, "{} failed: {}", cmd, cause),
+            DotfError::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+// The cause is part of the message, so it is not a source, or it would be
+// printed twice in the chain of the error.
+impl std::error::Error for DotfError {}
+
+/// Returns the first `DotfError` in the chain of the error, if any.
+pub fn find(err: &anyhow::Error) -> Option<&DotfError> {
+    err.chain().find_map(|e| e.downcast_ref::<DotfError>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_find_with_context() {
+        let err = Err::<(), _>(DotfError::MissingRepository(PathBuf::from("repo")))
+            .context("failed to load dotfile")
+            .unwrap_err();
+
+        let found = find(&err).expect("dotf error");
+        assert_eq!(4, found.exit_code());
+    }
+
+    #[test]
+    fn test_hint_io() {
+        let err = DotfError::io(Path::new("a.txt"), io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(Some("check that a.txt exists".to_string()), err.hint());
+        assert_eq!(None, DotfError::Aborted.hint());
+    }
+
+    #[test]
+    fn test_chain_io() {
+        let err = DotfError::io(Path::new("a.txt"), io::Error::from(io::ErrorKind::NotFound));
+        let err = anyhow::Error::from(err).context("failed to sync");
+        assert_eq!(
+            "failed to sync: a.txt: entity not found",
+            format!("{:#}", err)
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use crate::error::DotfError;
+use anyhow::{bail, Result};
+use glob::{MatchOptions, Pattern};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Same semantics as glob::glob, where each component is matched separately.
+pub(super) const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// File operations used when indexing and syncing files, so that they can
+/// be run against something other than the real filesystem, e.g. in tests.
+pub trait Filesystem: Send + Sync {
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Writes the file, replacing it if it exists. The parent directory
+    /// must exist.
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()>;
+
+    /// Returns None if the path does not exist. Symlinks are followed.
+    fn metadata(&self, path: &Path) -> Option<Metadata>;
+
+    /// Returns all files under the directory, recursively.
+    fn walk(&self, dir: &Path) -> Result<Vec<PathBuf>>;
+
+    /// Returns the paths matching the pattern, or the paths that could not
+    /// be read. Fails if the pattern is invalid.
+    fn glob(&self, pattern: &str) -> Result<Vec<std::result::Result<PathBuf, GlobError>>>;
+
+    fn symlink(&self, target: &Path, link: &Path) -> Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    fn create_dir_all(&self, path: &Path) -> Result<()>;
+
+    fn copy(&self, src: &Path, dst: &Path) -> Result<()> {
+        log::debug!("Copy: {:?} to {:?}", src, dst);
+        let data = self.read(src)?;
+        self.write(dst, &data)
+    }
+
+    /// Returns a path on the real filesystem with the content of the file,
+    /// for external commands such as diff.
+    fn local_path(&self, path: &Path) -> Result<PathBuf> {
+        Ok(path.to_path_buf())
+    }
+
+    fn read_string(&self, path: &Path) -> Result<String> {
+        match String::from_utf8(self.read(path)?) {
+            Ok(s) => Ok(s),
+            Err(_) => bail!("{} is not valid UTF-8", path.display()),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_some()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.metadata(path)
+            .is_some_and(|m| m.kind == FileKind::File)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.metadata(path).is_some_and(|m| m.kind == FileKind::Dir)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    File,
+    Dir,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metadata {
+    pub kind: FileKind,
+    // The size of the file in bytes, zero for directories.
+    pub len: u64,
+}
+
+/// A path that could not be read while matching a glob pattern.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlobError {
+    pub path: PathBuf,
+    pub cause: String,
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.cause)
+    }
+}
+
+/// The filesystem of the operating system.
+#[derive(Default)]
+pub struct RealFs;
+
+impl Filesystem for RealFs {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(fs::read(path).map_err(|err| DotfError::io(path, err))?)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        fs::write(path, data).map_err(|err| DotfError::io(path, err))?;
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> Option<Metadata> {
+        let metadata = fs::metadata(path).ok()?;
+        let kind = match metadata.is_dir() {
+            true => FileKind::Dir,
+            false => FileKind::File,
+        };
+        Some(Metadata {
+            kind,
+            len: metadata.len(),
+        })
+    }
+
+    fn walk(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let entries = fs::read_dir(dir).map_err(|err| DotfError::io(dir, err))?;
+        for entry in entries {
+            let path = entry.map_err(|err| DotfError::io(dir, err))?.path();
+            if path.is_dir() {
+                files.extend(self.walk(&path)?);
+            } else {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    // Unlike glob::glob, which skips them, file names that are not valid
+    // UTF-8 are matched as well.
+    fn glob(&self, pattern: &str) -> Result<Vec<std::result::Result<PathBuf, GlobError>>> {
+        let compiled = Pattern::new(pattern).map_err(|err| DotfError::Glob {
+            pattern: pattern.to_string(),
+            cause: err.to_string(),
+        })?;
+
+        let (base, depth) = glob_base(Path::new(pattern));
+        let mut paths = Vec::new();
+        if depth == Some(0) {
+            if base.exists() {
+                paths.push(Ok(base));
+            }
+            return Ok(paths);
+        }
+        glob_dir(&compiled, &base, depth, &mut paths);
+        Ok(paths)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> Result<()> {
+        std::os::unix::fs::symlink(target, link).map_err(|err| DotfError::io(link, err))?;
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        fs::rename(from, to).map_err(|err| DotfError::io(from, err))?;
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        fs::create_dir_all(path).map_err(|err| DotfError::io(path, err))?;
+        Ok(())
+    }
+
+    fn copy(&self, src: &Path, dst: &Path) -> Result<()> {
+        log::debug!("Copy: {:?} to {:?}", src, dst);
+        // Report the source if it cannot be read, and the destination otherwise.
+        fs::copy(src, dst).map_err(|err| match src.is_file() {
+            true => DotfError::io(dst, err),
+            false => DotfError::io(src, err),
+        })?;
+        Ok(())
+    }
+}
+
+// Returns the leading components of the pattern that contain no glob, and
+// how many components below it the pattern matches, or None if it is
+// recursive, i.e. contains **.
+fn glob_base(pattern: &Path) -> (PathBuf, Option<usize>) {
+    let mut base = PathBuf::new();
+    let mut depth = 0;
+    let mut recursive = false;
+    for component in pattern.components() {
+        let s = component.as_os_str().to_string_lossy();
+        let is_glob = s.contains(['*', '?', '[']);
+        if depth == 0 && !is_glob {
+            base.push(component);
+            continue;
+        }
+        depth += 1;
+        recursive = recursive || s == "**";
+    }
+    (base, (!recursive).then_some(depth))
+}
+
+// Adds the paths below the directory that match the pattern, reading at
+// most depth levels of directories.
+fn glob_dir(
+    pattern: &Pattern,
+    dir: &Path,
+    depth: Option<usize>,
+    paths: &mut Vec<std::result::Result<PathBuf, GlobError>>,
+) {
+    if depth == Some(0) {
+        return;
+    }
+
+    let read_path = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    let entries = match fs::read_dir(read_path) {
+        Ok(entries) => entries,
+        // Directories that do not exist have no matches.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+        Err(err) => {
+            paths.push(Err(GlobError {
+                path: dir.to_path_buf(),
+                cause: err.to_string(),
+            }));
+            return;
+        }
+    };
+
+    let mut children: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| dir.join(entry.file_name()))
+        .collect();
+    children.sort();
+    for path in children {
+        if pattern.matches_with(&path.to_string_lossy(), GLOB_OPTIONS) {
+            paths.push(Ok(path.clone()));
+        }
+        if path.is_dir() {
+            glob_dir(pattern, &path, depth.map(|d| d - 1), paths);
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use super::filesystem::{FileKind, Filesystem, GlobError, Metadata, GLOB_OPTIONS};
+use crate::cmd::CmdRunner;
+use crate::error::DotfError;
+use anyhow::{bail, Result};
+use glob::Pattern;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, DirBuilder, Permissions};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A filesystem where the files of the repository are read from a git
+/// revision, without touching its working tree, which cannot be written.
+/// Other paths, e.g. in home, are passed to the inner filesystem.
+pub struct GitFs {
+    inner: Arc<dyn Filesystem>,
+    repo: PathBuf,
+    rev: String,
+    runner: CmdRunner,
+    // Sizes of the files in the revision, relative to the repository.
+    files: BTreeMap<PathBuf, u64>,
+    // Files with mode 100755 in the revision.
+    executables: BTreeSet<PathBuf>,
+    // Directories in the revision, relative to the repository.
+    dirs: BTreeSet<PathBuf>,
+    // Files are copied here for external commands, and removed on drop.
+    temp_dir: PathBuf,
+}
+
+impl GitFs {
+    /// Lists the files of the revision, using git ls-tree. Fails if the
+    /// revision is not a commit of the repository.
+    pub fn new(inner: Arc<dyn Filesystem>, repo: PathBuf, rev: &str) -> Result<Self> {
+        let runner = CmdRunner::new(repo.clone());
+        let commit = format!("{}^{{commit}}", rev);
+        let verify = runner.capture("git", ["rev-parse", "--verify", "--quiet", &commit])?;
+        if !verify.success() {
+            bail!("unknown revision: {}", rev);
+        }
+
+        // Paths are relative to the repository, even if it is a
+        // subdirectory of the git repository.
+        let listing = runner.capture_bytes("git", ["ls-tree", "-r", "-l", "-z", rev])?;
+        let mut files = BTreeMap::new();
+        let mut executables = BTreeSet::new();
+        let mut dirs = BTreeSet::from([PathBuf::new()]);
+        for line in listing.split(|b| *b == 0).filter(|l| !l.is_empty()) {
+            let (path, size, executable) = match parse_ls_tree(line) {
+                Some(entry) => entry,
+                // Submodules and such have no content to sync.
+                None => continue,
+            };
+            dirs.extend(path.ancestors().skip(1).map(Path::to_path_buf));
+            if executable {
+                executables.insert(path.clone());
+            }
+            files.insert(path, size);
+        }
+
+        // Only readable by the user, since it may contain secrets. Fails if
+        // it already exists, rather than using a directory of someone else.
+        let temp_dir = env::temp_dir().join(format!(
+            "dotf-{}-{}",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&temp_dir)
+            .map_err(|err| DotfError::io(&temp_dir, err))?;
+        Ok(Self {
+            inner,
+            repo,
+            rev: rev.to_string(),
+            runner,
+            files,
+            executables,
+            dirs,
+            temp_dir,
+        })
+    }
+
+    // The path relative to the repository, or None if it is not in it.
+    fn relpath<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        path.strip_prefix(&self.repo).ok()
+    }
+
+    fn read_only(&self, path: &Path) -> anyhow::Error {
+        anyhow::anyhow!(
+            "cannot write {}: the repository is read from revision {}",
+            path.display(),
+            self.rev
+        )
+    }
+}
+
+impl Filesystem for GitFs {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let relpath = match self.relpath(path) {
+            Some(relpath) => relpath,
+            None => return self.inner.read(path),
+        };
+        if !self.files.contains_key(relpath) {
+            bail!("{}: no such file in {}", path.display(), self.rev);
+        }
+
+        let mut object = OsString::from(format!("{}:./", self.rev));
+        object.push(relpath);
+        self.runner
+            .capture_bytes("git", [OsStr::new("show"), &object])
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        match self.relpath(path) {
+            Some(_) => Err(self.read_only(path)),
+            None => self.inner.write(path, data),
+        }
+    }
+
+    fn metadata(&self, path: &Path) -> Option<Metadata> {
+        let relpath = match self.relpath(path) {
+            Some(relpath) => relpath,
+            None => return self.inner.metadata(path),
+        };
+        if let Some(len) = self.files.get(relpath) {
+            return Some(Metadata {
+                kind: FileKind::File,
+                len: *len,
+            });
+        }
+        self.dirs.contains(relpath).then_some(Metadata {
+            kind: FileKind::Dir,
+            len: 0,
+        })
+    }
+
+    fn walk(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let relpath = match self.relpath(dir) {
+            Some(relpath) => relpath,
+            None => return self.inner.walk(dir),
+        };
+        if !self.dirs.contains(relpath) {
+            bail!("{}: no such directory in {}", dir.display(), self.rev);
+        }
+
+        let paths = self
+            .files
+            .keys()
+            .filter(|p| p.starts_with(relpath))
+            .map(|p| self.repo.join(p))
+            .collect();
+        Ok(paths)
+    }
+
+    fn glob(&self, pattern: &str) -> Result<Vec<std::result::Result<PathBuf, GlobError>>> {
+        if self.relpath(Path::new(pattern)).is_none() {
+            return self.inner.glob(pattern);
+        }
+
+        let compiled = Pattern::new(pattern).map_err(|err| DotfError::Glob {
+            pattern: pattern.to_string(),
+            cause: err.to_string(),
+        })?;
+        let mut paths: Vec<PathBuf> = self
+            .files
+            .keys()
+            .chain(self.dirs.iter())
+            .map(|p| self.repo.join(p))
+            .filter(|p| compiled.matches_with(&p.to_string_lossy(), GLOB_OPTIONS))
+            .collect();
+        paths.sort();
+        Ok(paths.into_iter().map(Ok).collect())
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> Result<()> {
+        match self.relpath(link) {
+            Some(_) => Err(self.read_only(link)),
+            None => self.inner.symlink(target, link),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        if self.relpath(from).is_some() || self.relpath(to).is_some() {
+            return Err(self.read_only(from));
+        }
+        self.inner.rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        match self.relpath(path) {
+            Some(_) => Err(self.read_only(path)),
+            None => self.inner.create_dir_all(path),
+        }
+    }
+
+    // Files of the revision are copied from their local path, which has the
+    // mode of the file, so that executables stay executable.
+    fn copy(&self, src: &Path, dst: &Path) -> Result<()> {
+        if self.relpath(dst).is_some() {
+            return Err(self.read_only(dst));
+        }
+        match self.relpath(src) {
+            Some(relpath) if !self.files.contains_key(relpath) => {
+                bail!("{}: no such file in {}", src.display(), self.rev)
+            }
+            Some(_) => self.inner.copy(&self.local_path(src)?, dst),
+            None => self.inner.copy(src, dst),
+        }
+    }
+
+    // Files of the revision are copied to the temporary directory, where
+    // files missing in the revision are missing as well.
+    fn local_path(&self, path: &Path) -> Result<PathBuf> {
+        let relpath = match self.relpath(path) {
+            Some(relpath) => relpath,
+            None => return self.inner.local_path(path),
+        };
+
+        let local = self.temp_dir.join(relpath);
+        if !self.files.contains_key(relpath) {
+            return Ok(local);
+        }
+        if let Some(parent) = local.parent() {
+            fs::create_dir_all(parent).map_err(|err| DotfError::io(parent, err))?;
+        }
+        fs::write(&local, self.read(path)?).map_err(|err| DotfError::io(&local, err))?;
+        if self.executables.contains(relpath) {
+            fs::set_permissions(&local, Permissions::from_mode(0o755))
+                .map_err(|err| DotfError::io(&local, err))?;
+        }
+        Ok(local)
+    }
+}
+
+impl Drop for GitFs {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.temp_dir);
+    }
+}
+
+// Parses a line of git ls-tree -l, i.e. "<mode> <type> <object> <size>\t<path>",
+// into the path, size and whether it is executable, returning None for
+// anything but files.
+fn parse_ls_tree(line: &[u8]) -> Option<(PathBuf, u64, bool)> {
+    let tab = line.iter().position(|b| *b == b'\t')?;
+    let header = std::str::from_utf8(&line[..tab]).ok()?;
+    let fields: Vec<&str> = header.split_whitespace().collect();
+    match fields.as_slice() {
+        [mode, "blob", _, size] => {
+            let path = PathBuf::from(OsStr::from_bytes(&line[tab + 1..]));
+            Some((path, size.parse().ok()?, *mode == "100755"))
+        }
+        _ => None,
+    }
+}
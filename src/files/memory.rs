@@ -0,0 +1,177 @@
+use super::filesystem::{FileKind, Filesystem, GlobError, Metadata, GLOB_OPTIONS};
+use anyhow::{bail, Result};
+use glob::Pattern;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+// The number of symlinks to follow before giving up.
+const MAX_LINKS: usize = 8;
+
+enum Node {
+    File(Vec<u8>),
+    Dir,
+    Symlink(PathBuf),
+}
+
+/// A filesystem that only exists in memory, used to test handlers without
+/// touching the disk. Paths are expected to be absolute.
+#[derive(Default)]
+pub struct MemoryFs {
+    nodes: Mutex<BTreeMap<PathBuf, Node>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, creating its parent directories.
+    pub fn with_file(self, path: impl AsRef<Path>, content: &str) -> Self {
+        self.add_file(path.as_ref(), content.as_bytes());
+        self
+    }
+
+    /// Adds a file, creating its parent directories.
+    pub fn add_file(&self, path: &Path, data: &[u8]) {
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent).unwrap();
+        }
+        self.nodes()
+            .insert(path.to_path_buf(), Node::File(data.to_vec()));
+    }
+
+    fn nodes(&self) -> MutexGuard<'_, BTreeMap<PathBuf, Node>> {
+        self.nodes.lock().unwrap()
+    }
+}
+
+impl Filesystem for MemoryFs {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let nodes = self.nodes();
+        match resolve(&nodes, path) {
+            Some((_, Node::File(data))) => Ok(data.clone()),
+            Some(_) => bail!("{} is a directory", path.display()),
+            None => bail!("{}: no such file or directory", path.display()),
+        }
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let mut nodes = self.nodes();
+        let parent = path.parent().unwrap_or(Path::new("/"));
+        if !matches!(resolve(&nodes, parent), Some((_, Node::Dir))) {
+            bail!("{}: no such directory", parent.display());
+        }
+
+        let path = match resolve(&nodes, path) {
+            Some((_, Node::Dir)) => bail!("{} is a directory", path.display()),
+            Some((target, _)) => target,
+            None => path.to_path_buf(),
+        };
+        nodes.insert(path, Node::File(data.to_vec()));
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> Option<Metadata> {
+        let nodes = self.nodes();
+        match resolve(&nodes, path)? {
+            (_, Node::File(data)) => Some(Metadata {
+                kind: FileKind::File,
+                len: data.len() as u64,
+            }),
+            _ => Some(Metadata {
+                kind: FileKind::Dir,
+                len: 0,
+            }),
+        }
+    }
+
+    fn walk(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        if !self.is_dir(dir) {
+            bail!("{}: no such directory", dir.display());
+        }
+
+        let paths: Vec<PathBuf> = self
+            .nodes()
+            .keys()
+            .filter(|p| p.starts_with(dir) && p.as_path() != dir)
+            .cloned()
+            .collect();
+        Ok(paths.into_iter().filter(|p| self.is_file(p)).collect())
+    }
+
+    fn glob(&self, pattern: &str) -> Result<Vec<std::result::Result<PathBuf, GlobError>>> {
+        let pattern = Pattern::new(pattern)?;
+        let paths = self
+            .nodes()
+            .keys()
+            .filter(|p| pattern.matches_with(&p.to_string_lossy(), GLOB_OPTIONS))
+            .map(|p| Ok(p.clone()))
+            .collect();
+        Ok(paths)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> Result<()> {
+        let mut nodes = self.nodes();
+        if nodes.contains_key(link) {
+            bail!("{} already exists", link.display());
+        }
+        nodes.insert(link.to_path_buf(), Node::Symlink(target.to_path_buf()));
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut nodes = self.nodes();
+        if !nodes.contains_key(from) {
+            bail!("{}: no such file or directory", from.display());
+        }
+
+        // Directories are moved along with everything in them.
+        let moved: Vec<PathBuf> = nodes
+            .keys()
+            .filter(|p| p.starts_with(from))
+            .cloned()
+            .collect();
+        for path in moved {
+            let node = nodes.remove(&path).unwrap();
+            let rest = path.strip_prefix(from).unwrap();
+            let dst = match rest.as_os_str().is_empty() {
+                true => to.to_path_buf(),
+                false => to.join(rest),
+            };
+            nodes.insert(dst, node);
+        }
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        let mut nodes = self.nodes();
+        for dir in path.ancestors() {
+            match nodes.get(dir) {
+                Some(Node::File(_)) => bail!("{} is a file", dir.display()),
+                Some(_) => {}
+                None => {
+                    nodes.insert(dir.to_path_buf(), Node::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Returns the path and node that the path points to, following symlinks.
+fn resolve<'a>(nodes: &'a BTreeMap<PathBuf, Node>, path: &Path) -> Option<(PathBuf, &'a Node)> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        match nodes.get(&path)? {
+            Node::Symlink(target) => {
+                path = match target.is_absolute() {
+                    true => target.clone(),
+                    false => path.parent()?.join(target),
+                };
+            }
+            node => return Some((path, node)),
+        }
+    }
+    None
+}
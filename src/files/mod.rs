@@ -1,3 +1,4 @@
+use crate::error::DotfError;
 use anyhow::Result;
 use data_encoding::HEXLOWER;
 use ring::digest::{Context, SHA256};
@@ -5,9 +6,16 @@ use std::fs;
 use std::io::Read;
 use std::path::Path;
 
+mod filesystem;
+mod git;
+mod memory;
 #[cfg(test)]
 mod tests;
 
+pub use filesystem::{FileKind, Filesystem, GlobError, Metadata, RealFs};
+pub use git::GitFs;
+pub use memory::MemoryFs;
+
 pub fn digest(data: &[u8]) -> Result<String> {
     let mut context = Context::new(&SHA256);
     context.update(data);
@@ -17,20 +25,19 @@ pub fn digest(data: &[u8]) -> Result<String> {
     Ok(s)
 }
 
-pub fn copy(src: &Path, dst: &Path) -> Result<()> {
-    log::debug!("Copy: {:?} to {:?}", src, dst);
-    fs::copy(src, dst)?;
-    Ok(())
+/// Returns the digest of the file content, or None if it does not exist.
+pub fn digest_file(fs: &dyn Filesystem, path: &Path) -> Result<Option<String>> {
+    if !fs.exists(path) {
+        return Ok(None);
+    }
+    let data = fs.read(path)?;
+    Ok(Some(digest(&data)?))
 }
 
 pub fn read_string(path: &Path) -> Result<String> {
     let mut buf = String::new();
-    let mut file = fs::File::open(path)?;
-    file.read_to_string(&mut buf)?;
+    let mut file = fs::File::open(path).map_err(|err| DotfError::io(path, err))?;
+    file.read_to_string(&mut buf)
+        .map_err(|err| DotfError::io(path, err))?;
     Ok(buf)
 }
-
-pub fn create_dirs(path: &Path) -> Result<()> {
-    fs::create_dir_all(path)?;
-    Ok(())
-}
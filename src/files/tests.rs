@@ -1,4 +1,11 @@
-use super::digest;
+use super::{digest, FileKind, Filesystem, GitFs, MemoryFs, RealFs};
+use crate::cmd::CmdRunner;
+use crate::data::Status;
+use crate::testing::{FileSpec, TestContext};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[test]
 fn digest_empty() {
@@ -11,3 +18,135 @@ fn digest_content() {
     let d = digest("content".as_bytes());
     assert!(d.is_ok());
 }
+
+#[test]
+fn memory_write_requires_parent() {
+    let fs = MemoryFs::new().with_file("/home/a.txt", "a");
+
+    assert!(fs.write(Path::new("/home/b.txt"), b"b").is_ok());
+    assert!(fs.write(Path::new("/home/dir/c.txt"), b"c").is_err());
+    assert_eq!("b", fs.read_string(Path::new("/home/b.txt")).unwrap());
+}
+
+#[test]
+fn memory_metadata() {
+    let fs = MemoryFs::new().with_file("/home/dir/a.txt", "abc");
+
+    let file = fs.metadata(Path::new("/home/dir/a.txt")).unwrap();
+    assert_eq!(FileKind::File, file.kind);
+    assert_eq!(3, file.len);
+    assert!(fs.is_dir(Path::new("/home/dir")));
+    assert!(fs.metadata(Path::new("/home/b.txt")).is_none());
+}
+
+#[test]
+fn memory_glob_matches_components() {
+    let fs = MemoryFs::new()
+        .with_file("/home/config/a.yml", "")
+        .with_file("/home/config/.hidden", "")
+        .with_file("/home/config/sub/b.yml", "");
+
+    let matched = |pattern: &str| -> Vec<PathBuf> {
+        fs.glob(pattern)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.unwrap())
+            .filter(|p| fs.is_file(p))
+            .collect()
+    };
+
+    assert_eq!(2, matched("/home/config/*").len());
+    assert_eq!(3, matched("/home/config/**/*").len());
+    assert_eq!(
+        vec![PathBuf::from("/home/config/sub/b.yml")],
+        matched("/home/*/*/*.yml")
+    );
+    assert!(fs.glob("/home/[").is_err());
+}
+
+#[test]
+fn memory_symlink_and_rename() {
+    let fs = MemoryFs::new().with_file("/repo/dir/a.txt", "a");
+    fs.create_dir_all(Path::new("/home")).unwrap();
+
+    fs.symlink(Path::new("/repo/dir/a.txt"), Path::new("/home/a.txt"))
+        .unwrap();
+    assert_eq!("a", fs.read_string(Path::new("/home/a.txt")).unwrap());
+
+    fs.rename(Path::new("/repo/dir"), Path::new("/repo/moved"))
+        .unwrap();
+    assert_eq!(
+        vec![PathBuf::from("/repo/moved/a.txt")],
+        fs.walk(Path::new("/repo")).unwrap()
+    );
+    assert!(!fs.exists(Path::new("/home/a.txt")));
+}
+
+#[test]
+fn git_reads_repository_from_revision() {
+    // Arrange
+    let context = TestContext::new(vec![
+        FileSpec::target("a.txt", Status::MissingHome),
+        FileSpec::target("dir/b.txt", Status::MissingHome),
+    ]);
+    context.setup().unwrap();
+    let git = CmdRunner::new(context.repo_dir.clone());
+    git.run_checked("git", ["init", "-q"]).unwrap();
+    git.run_checked("git", ["add", "."]).unwrap();
+    let commit = ["-c", "user.name=dotf", "-c", "user.email=dotf@localhost"];
+    git.run_checked("git", commit.iter().chain(&["commit", "-q", "-m", "init"]))
+        .unwrap();
+    let a = context.repo_path("a.txt");
+    let committed = fs::read(&a).unwrap();
+    fs::write(&a, "changed").unwrap();
+    fs::remove_file(context.repo_path("dir/b.txt")).unwrap();
+
+    // Act
+    let gitfs = GitFs::new(Arc::new(RealFs), context.repo_dir.clone(), "HEAD").unwrap();
+
+    // Assert
+    assert_eq!(committed, gitfs.read(&a).unwrap());
+    assert!(gitfs.is_file(&context.repo_path("dir/b.txt")));
+    assert!(gitfs.is_dir(&context.repo_path("dir")));
+    assert!(!gitfs.exists(&context.repo_path(".git")));
+    let pattern = context.repo_path("*/*.txt");
+    let globbed: Vec<PathBuf> = gitfs
+        .glob(&pattern.to_string_lossy())
+        .unwrap()
+        .into_iter()
+        .map(|p| p.unwrap())
+        .collect();
+    assert_eq!(vec![context.repo_path("dir/b.txt")], globbed);
+    assert_eq!(committed, fs::read(gitfs.local_path(&a).unwrap()).unwrap());
+
+    assert!(gitfs.write(&a, b"written").is_err());
+    assert_eq!("changed", fs::read_to_string(&a).unwrap());
+    assert!(GitFs::new(Arc::new(RealFs), context.repo_dir.clone(), "nope").is_err());
+}
+
+#[test]
+fn git_copies_executables_with_their_mode() {
+    // Arrange
+    let context = TestContext::new(vec![FileSpec::target("run.sh", Status::MissingHome)]);
+    context.setup().unwrap();
+    let script = context.repo_path("run.sh");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let git = CmdRunner::new(context.repo_dir.clone());
+    git.run_checked("git", ["init", "-q"]).unwrap();
+    git.run_checked("git", ["add", "."]).unwrap();
+    let commit = ["-c", "user.name=dotf", "-c", "user.email=dotf@localhost"];
+    git.run_checked("git", commit.iter().chain(&["commit", "-q", "-m", "init"]))
+        .unwrap();
+    let gitfs = GitFs::new(Arc::new(RealFs), context.repo_dir.clone(), "HEAD").unwrap();
+    let home = context.home_path("run.sh");
+    fs::create_dir_all(&context.home_dir).unwrap();
+
+    // Act
+    gitfs.copy(&script, &home).unwrap();
+
+    // Assert
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(0o755, mode(&home));
+    let local = gitfs.local_path(&script).unwrap();
+    assert_eq!(0o700, mode(local.parent().unwrap()));
+}
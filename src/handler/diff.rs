@@ -1,14 +1,23 @@
-use super::types::{DiffOptions, Only};
+use super::types::{DiffOptions, Only, Selection};
 use crate::data::{Entry, Item};
+use crate::error::DotfError;
+use crate::files::{Filesystem, RealFs};
 use crate::index::Indexer;
-use crate::path_str;
-use anyhow::Result;
+use crate::report::{Event, Reporter, TerminalReporter};
+use anyhow::{bail, Result};
+use std::io::Write;
 use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
 
 pub struct DiffHandler {
     indexer: Indexer,
     items: Vec<Item>,
     options: DiffOptions,
+    // Command to page the output of the diffs with.
+    pager: Option<String>,
+    fs: Arc<dyn Filesystem>,
+    reporter: Arc<dyn Reporter>,
 }
 
 impl DiffHandler {
@@ -24,35 +33,135 @@ impl DiffHandler {
             indexer,
             items,
             options,
+            pager: None,
+            fs: Arc::new(RealFs),
+            reporter: Arc::new(TerminalReporter::new()),
         }
     }
+
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.indexer = self.indexer.with_selection(selection);
+        self
+    }
+
+    pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.indexer = self.indexer.with_fail_fast(fail_fast);
+        self
+    }
+
+    /// Use the filesystem instead of the real one, e.g. to compare against
+    /// a git revision. Files are copied to the real one for the diff command.
+    pub fn with_filesystem(mut self, fs: Arc<dyn Filesystem>) -> Self {
+        self.indexer = self.indexer.with_filesystem(fs.clone());
+        self.fs = fs;
+        self
+    }
+
+    pub fn with_pager(mut self, pager: Option<String>) -> Self {
+        self.pager = pager;
+        self
+    }
+
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Runs the diff command for each file that differs. Files that could
+    /// not be indexed are reported as errors. With a pager, the output of
+    /// the diff commands is paged once all of them have run.
     pub fn diff(&self) -> Result<()> {
-        let entries = self.indexer.index(&self.items)?;
-        let entries: Vec<&Entry> = entries
+        let (indexed, _) = self.indexer.index_deduped(&self.items)?;
+        let indexed: Vec<(&str, Vec<&Entry>)> = indexed
             .iter()
-            .flat_map(|(_name, es)| es)
-            .filter(|e| e.is_diff())
+            .map(|(name, es)| {
+                let es: Vec<&Entry> = es.iter().filter(|e| e.is_diff() || !e.is_ok()).collect();
+                (name.as_str(), es)
+            })
+            .filter(|(_, es)| !es.is_empty())
             .collect();
 
-        if entries.is_empty() {
-            println!("All up to date.");
+        if indexed.is_empty() {
+            self.reporter.report(Event::Info {
+                message: "All up to date.".to_string(),
+            });
             return Ok(());
         }
 
-        for entry in entries {
-            if let Entry::Ok {
-                home_path,
-                repo_path,
-                ..
-            } = entry
-            {
-                let a = path_str!(home_path);
-                let b = path_str!(repo_path);
-
-                let mut cmd = self.options.to_cmd(&a, &b)?;
-                cmd.status()?;
+        let mut paged = Vec::new();
+        for (name, entries) in indexed {
+            self.reporter.report(Event::ItemStarted {
+                item: name.to_string(),
+                count: entries.len(),
+            });
+
+            for entry in entries {
+                match entry {
+                    Entry::Ok {
+                        relpath,
+                        status,
+                        home_path,
+                        repo_path,
+                    } => {
+                        self.reporter.report(Event::Entry {
+                            item: name.to_string(),
+                            relpath: relpath.display().to_string(),
+                            status: status.clone(),
+                        });
+
+                        let home_path = self.fs.local_path(home_path)?;
+                        let repo_path = self.fs.local_path(repo_path)?;
+                        let mut cmd = self.options.to_cmd(&home_path, &repo_path)?;
+                        let program = self.options.program().unwrap_or("diff");
+                        match self.pager {
+                            Some(_) => {
+                                let output = cmd
+                                    .output()
+                                    .map_err(|err| DotfError::command(program, err))?;
+                                paged.extend(output.stdout);
+                            }
+                            None => {
+                                cmd.status()
+                                    .map_err(|err| DotfError::command(program, err))?;
+                            }
+                        }
+                    }
+                    Entry::Err(err) => self.reporter.report(Event::Error {
+                        item: name.to_string(),
+                        path: err.path.display().to_string(),
+                        reason: err.cause.to_string(),
+                    }),
+                }
             }
         }
+
+        if !paged.is_empty() {
+            self.page(&paged)?;
+        }
+        Ok(())
+    }
+
+    // Writes the output to the stdin of the pager.
+    fn page(&self, output: &[u8]) -> Result<()> {
+        let pager = self.pager.as_deref().unwrap_or_default();
+        let mut args = pager.split_whitespace();
+        let program = match args.next() {
+            Some(program) => program,
+            None => bail!("empty pager command"),
+        };
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| DotfError::command(program, err))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // The pager may exit before reading everything, e.g. when quit.
+            let _ = stdin.write_all(output);
+        }
+        child
+            .wait()
+            .map_err(|err| DotfError::command(program, err))?;
         Ok(())
     }
 }
@@ -0,0 +1,431 @@
+use super::types::DiffOptions;
+use crate::cmd::CmdRunner;
+use crate::data::schema::validate;
+use crate::data::{Dotfile, Entry, Item};
+use crate::files;
+use crate::index::{find_overlaps, is_glob, is_glob_path, Indexer};
+use crate::style::{paint, Icon};
+use anyhow::{bail, Result};
+use crossterm::style::Stylize;
+use glob::Pattern as GlobPattern;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Checks the dotfile, the repository and the environment for common problems.
+pub struct DoctorHandler {
+    home: PathBuf,
+    dotfile_path: PathBuf,
+    editor: String,
+    diff_options: DiffOptions,
+}
+
+// Public methods.
+impl DoctorHandler {
+    pub fn new(
+        home: PathBuf,
+        dotfile_path: PathBuf,
+        editor: String,
+        diff_options: DiffOptions,
+    ) -> Self {
+        Self {
+            home,
+            dotfile_path,
+            editor,
+            diff_options,
+        }
+    }
+
+    pub fn doctor(&self) -> Result<()> {
+        let report = self.check()?;
+        report.display();
+
+        if report.errors() > 0 {
+            bail!("found {} problem(s)", report.errors());
+        }
+        Ok(())
+    }
+
+    /// Runs all checks and returns the report.
+    pub fn check(&self) -> Result<Report> {
+        let mut report = Report::default();
+        self.check_binaries(&mut report);
+
+        if let Some(dotfile) = self.check_dotfile(&mut report) {
+            let repository = dotfile.repository();
+            self.check_repository(&repository, &mut report);
+
+            let items = dotfile.items();
+            let indexer = Indexer::new(self.home.clone(), repository, None);
+            // Indexing panics on non-UTF-8 paths, so only continue if all are valid.
+            if self.check_utf8(&indexer, &items, &mut report) {
+                self.check_items(&indexer, &items, &mut report)?;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+// Private methods.
+impl DoctorHandler {
+    fn check_binaries(&self, report: &mut Report) {
+        report.section("binaries");
+
+        let editor = self.editor.split_whitespace().next().unwrap_or_default();
+        let diff = self.diff_options.program().unwrap_or_default();
+        let binaries = [
+            ("git", "git", true),
+            (diff, "diff command", true),
+            (editor, "editor", false),
+        ];
+
+        for (name, description, required) in binaries {
+            match find_executable(name) {
+                Some(path) => report.ok(format!("{} found: {}", description, path.display())),
+                None if required => report.error(
+                    format!("{} not found: {}", description, name),
+                    format!("install {} or add it to PATH", name),
+                ),
+                None => report.warn(
+                    format!("{} not found: {}", description, name),
+                    "set $VISUAL or $EDITOR to an installed editor".to_string(),
+                ),
+            }
+        }
+    }
+
+    fn check_dotfile(&self, report: &mut Report) -> Option<Dotfile> {
+        report.section("dotfile");
+        let path = &self.dotfile_path;
+
+        let content = match files::read_string(path) {
+            Ok(s) => s,
+            Err(err) => {
+                report.error(
+                    format!("failed to read {}: {}", path.display(), err),
+                    "run dotf without arguments to create a new dotfile".to_string(),
+                );
+                return None;
+            }
+        };
+
+        let diagnostics = validate(&content);
+        if !diagnostics.is_empty() {
+            for diagnostic in diagnostics {
+                report.error(
+                    format!("{}:{}", path.display(), diagnostic),
+                    "fix the dotfile using dotf edit".to_string(),
+                );
+            }
+            return None;
+        }
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        match Dotfile::from(&content, dir) {
+            Ok(dotfile) => {
+                report.ok(format!("{} is valid", path.display()));
+                Some(dotfile)
+            }
+            Err(err) => {
+                report.error(
+                    err.to_string(),
+                    "fix the dotfile using dotf edit".to_string(),
+                );
+                None
+            }
+        }
+    }
+
+    fn check_repository(&self, repository: &Path, report: &mut Report) {
+        report.section("repository");
+
+        let repository = match repository.canonicalize() {
+            Ok(path) => path,
+            Err(err) => {
+                report.error(
+                    format!("failed to read {}: {}", repository.display(), err),
+                    format!(
+                        "clone or create the repository at {}, or change repository in the dotfile",
+                        repository.display()
+                    ),
+                );
+                return;
+            }
+        };
+        let runner = CmdRunner::new(repository.clone());
+        let args = vec!["rev-parse".to_string(), "--show-toplevel".to_string()];
+        let toplevel = match runner.capture("git", args) {
+            Ok(output) if output.success() => Some(PathBuf::from(output.stdout().trim())),
+            Ok(_) => None,
+            Err(err) => {
+                log::debug!("Failed to run git: {}", err);
+                None
+            }
+        };
+
+        match toplevel {
+            Some(path) if path == repository => {
+                report.ok(format!("{} is a git repository", repository.display()))
+            }
+            Some(path) => report.error(
+                format!(
+                    "{} is not a git repository, but inside {}",
+                    repository.display(),
+                    path.display()
+                ),
+                format!("run: git init {}", repository.display()),
+            ),
+            None => report.error(
+                format!("{} is not a git repository", repository.display()),
+                format!("run: git init {}", repository.display()),
+            ),
+        }
+    }
+
+    // Returns true if no invalid paths were found. Only the paths matched by
+    // the patterns of the items are checked.
+    fn check_utf8(&self, indexer: &Indexer, items: &[Item], report: &mut Report) -> bool {
+        report.section("paths");
+
+        let mut invalid = BTreeSet::new();
+        for item in items {
+            let base = item.root.as_ref().map_or(&self.home, |root| &root.path);
+            for pattern in &item.files {
+                let path = base.join(pattern);
+                let glob = match GlobPattern::new(&path.to_string_lossy()) {
+                    Ok(glob) => glob,
+                    // Reported when checking the items.
+                    Err(_) => continue,
+                };
+                let matches = |p: &PathBuf| match is_glob_path(pattern) {
+                    true => glob.matches(&p.to_string_lossy()),
+                    false => *p == path,
+                };
+
+                let single = Item {
+                    files: vec![pattern.clone()],
+                    ..item.clone()
+                };
+                for (dir, recursive) in indexer.watch_paths(&[single]) {
+                    let mut found = Vec::new();
+                    find_non_utf8(&dir, recursive, &mut found);
+                    invalid.extend(found.into_iter().filter(matches));
+                }
+            }
+        }
+
+        for path in &invalid {
+            report.error(
+                format!("path is not valid UTF-8: {}", path.to_string_lossy()),
+                "rename the file or ignore it in the item".to_string(),
+            );
+        }
+
+        if invalid.is_empty() {
+            report.ok("all paths are valid UTF-8".to_string());
+        }
+        invalid.is_empty()
+    }
+
+    fn check_items(&self, indexer: &Indexer, items: &[Item], report: &mut Report) -> Result<()> {
+        report.section("items");
+        let before = report.findings();
+
+        let indexed = indexer.index(items)?;
+        for (name, entries) in &indexed {
+            for entry in entries {
+                if let Entry::Err(reason) = entry {
+                    report.error(
+                        format!("{}: {}", name, reason),
+                        format!("update the files of {} using dotf edit", name),
+                    );
+                }
+            }
+        }
+
+        for overlap in find_overlaps(&indexed, items) {
+            report.warn(
+                format!(
+                    "{} is tracked by: {} (synced as part of {})",
+                    overlap.home_path.display(),
+                    overlap.items.join(", "),
+                    overlap.owner,
+                ),
+                "track the file in only one item, e.g. using ignore".to_string(),
+            );
+        }
+
+        for item in items {
+            for pattern in item.files.iter().filter(|p| is_glob(&p.to_string_lossy())) {
+                let single = Item {
+                    files: vec![pattern.clone()],
+                    ..item.clone()
+                };
+                let matched = indexer
+                    .index(&[single])?
+                    .iter()
+                    .flat_map(|(_, entries)| entries)
+                    .any(|entry| entry.is_ok());
+
+                if !matched {
+                    report.warn(
+                        format!(
+                            "{}: {} does not match any files",
+                            item.name,
+                            pattern.display()
+                        ),
+                        "check the pattern, or remove it if it is no longer used".to_string(),
+                    );
+                }
+            }
+        }
+
+        if report.findings() == before {
+            report.ok(format!("{} item(s) look good", items.len()));
+        }
+        Ok(())
+    }
+}
+
+enum Level {
+    Ok,
+    Warning,
+    Error,
+}
+
+struct Finding {
+    level: Level,
+    message: String,
+    fix: Option<String>,
+}
+
+/// The findings of each check, grouped by section.
+#[derive(Default)]
+pub struct Report {
+    sections: Vec<(String, Vec<Finding>)>,
+}
+
+impl Report {
+    pub fn errors(&self) -> usize {
+        self.count(|level| matches!(level, Level::Error))
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(|level| matches!(level, Level::Warning))
+    }
+
+    pub fn display(&self) {
+        for (name, findings) in &self.sections {
+            println!(" {}", name);
+            for finding in findings {
+                let icon = match finding.level {
+                    Level::Ok => paint(Icon::Ok.as_str().green()),
+                    Level::Warning => paint(Icon::Warning.as_str().yellow()),
+                    Level::Error => paint(Icon::Error.as_str().red()),
+                };
+                println!("   {} {}", icon, finding.message);
+                if let Some(fix) = &finding.fix {
+                    println!("     fix: {}", fix);
+                }
+            }
+        }
+
+        println!(
+            "\n{} error(s), {} warning(s)",
+            self.errors(),
+            self.warnings()
+        );
+    }
+
+    #[cfg(test)]
+    pub fn messages(&self) -> Vec<&str> {
+        self.sections
+            .iter()
+            .flat_map(|(_, findings)| findings)
+            .filter(|finding| !matches!(finding.level, Level::Ok))
+            .map(|finding| finding.message.as_str())
+            .collect()
+    }
+
+    fn count(&self, f: impl Fn(&Level) -> bool) -> usize {
+        self.sections
+            .iter()
+            .flat_map(|(_, findings)| findings)
+            .filter(|finding| f(&finding.level))
+            .count()
+    }
+
+    // The number of warnings and errors.
+    fn findings(&self) -> usize {
+        self.errors() + self.warnings()
+    }
+
+    fn section(&mut self, name: &str) {
+        self.sections.push((name.to_string(), Vec::new()));
+    }
+
+    fn ok(&mut self, message: String) {
+        self.push(Level::Ok, message, None);
+    }
+
+    fn warn(&mut self, message: String, fix: String) {
+        self.push(Level::Warning, message, Some(fix));
+    }
+
+    fn error(&mut self, message: String, fix: String) {
+        self.push(Level::Error, message, Some(fix));
+    }
+
+    fn push(&mut self, level: Level, message: String, fix: Option<String>) {
+        if let Some((_, findings)) = self.sections.last_mut() {
+            findings.push(Finding {
+                level,
+                message,
+                fix,
+            });
+        }
+    }
+}
+
+// Looks for an executable in PATH, unless name is a path.
+fn find_executable(name: &str) -> Option<PathBuf> {
+    if name.is_empty() {
+        return None;
+    }
+
+    let path = Path::new(name);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+// Finds the paths in the directory that are not valid UTF-8, including
+// the files in directories that are not.
+fn find_non_utf8(dir: &Path, recursive: bool, invalid: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::debug!("Failed to read {:?}: {}", dir, err);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.to_str().is_none() {
+            invalid.push(path.clone());
+        }
+
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        if recursive && is_dir {
+            find_non_utf8(&path, recursive, invalid);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use super::plan::Target;
+use crate::path::LOCAL_DATA_DIR;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A sync that wrote files, as recorded in the history.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SyncRun {
+    // Seconds since the unix epoch.
+    pub time: u64,
+    // Where the files were written to.
+    pub direction: Target,
+    pub host: String,
+    pub files: Vec<SyncedFile>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SyncedFile {
+    pub item: String,
+    #[serde(with = "relpath")]
+    pub relpath: PathBuf,
+}
+
+impl SyncRun {
+    /// A run of this host, at the current time.
+    pub fn now(direction: Target, files: Vec<SyncedFile>) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            time,
+            direction,
+            host: hostname(),
+            files,
+        }
+    }
+
+    /// The time of the run in UTC, e.g. 2024-05-01 12:30:00 UTC.
+    pub fn time_utc(&self) -> String {
+        let days = (self.time / 86400) as i64;
+        let secs = self.time % 86400;
+        let (year, month, day) = civil_from_days(days);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60
+        )
+    }
+}
+
+/// The sync runs of this machine, stored one JSON object per line.
+#[derive(Clone, Debug)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The history in the local data directory, e.g.
+    /// ~/.local/share/dotf/history.jsonl.
+    pub fn local() -> Self {
+        Self::new(LOCAL_DATA_DIR.join("dotf").join("history.jsonl"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, run: &SyncRun) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(run)?)?;
+        Ok(())
+    }
+
+    /// Returns the recorded runs, oldest first, or none if nothing has been
+    /// recorded yet. Lines that cannot be parsed are skipped.
+    pub fn load(&self) -> Result<Vec<SyncRun>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => bail!("failed to read history {}: {}", self.path.display(), err),
+        };
+
+        let mut runs = Vec::new();
+        for (n, line) in content.lines().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(run) => runs.push(run),
+                Err(err) => log::warn!("Invalid line {} in history: {}", n + 1, err),
+            }
+        }
+        Ok(runs)
+    }
+}
+
+// Relative paths are stored as strings, or as arrays of bytes if they are
+// not valid UTF-8, so that every file name is recorded exactly.
+mod relpath {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::ffi::OsString;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Str(String),
+        Bytes(Vec<u8>),
+    }
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        match path.to_str() {
+            Some(s) => serializer.serialize_str(s),
+            None => serializer.collect_seq(path.as_os_str().as_bytes()),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Str(s) => PathBuf::from(s),
+            Repr::Bytes(bytes) => PathBuf::from(OsString::from_vec(bytes)),
+        })
+    }
+}
+
+// The name of the machine, or unknown if it cannot be determined.
+fn hostname() -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// Converts days since the unix epoch to a date in the Gregorian calendar,
+// see http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_string;
+
+    #[test]
+    fn time_in_utc() {
+        let run = SyncRun {
+            time: 1_714_566_600,
+            direction: Target::Home,
+            host: "laptop".to_string(),
+            files: Vec::new(),
+        };
+        assert_eq!("2024-05-01 12:30:00 UTC", run.time_utc());
+    }
+
+    #[test]
+    fn record_and_load() {
+        // Arrange
+        let dir = PathBuf::from(format!("tmp-{}", random_string(10)));
+        let history = History::new(dir.join("history.jsonl"));
+        let file = SyncedFile {
+            item: "vim".to_string(),
+            relpath: PathBuf::from(".vimrc"),
+        };
+        let run = SyncRun::now(Target::Repo, vec![file]);
+
+        // Act
+        let empty = history.load().unwrap();
+        history.record(&run).unwrap();
+        history.record(&run).unwrap();
+        let runs = history.load().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Assert
+        assert!(empty.is_empty());
+        assert_eq!(vec![run.clone(), run], runs);
+    }
+
+    #[test]
+    fn record_paths_that_are_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        // Arrange
+        let dir = PathBuf::from(format!("tmp-{}", random_string(10)));
+        let history = History::new(dir.join("history.jsonl"));
+        // Both names are displayed as a�.
+        let files = [b"a\xff", b"a\xfe"]
+            .iter()
+            .map(|name| SyncedFile {
+                item: "bin".to_string(),
+                relpath: PathBuf::from(OsStr::from_bytes(*name)),
+            })
+            .collect();
+        let run = SyncRun::now(Target::Home, files);
+
+        // Act
+        history.record(&run).unwrap();
+        let runs = history.load().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Assert
+        assert_eq!(vec![run], runs);
+    }
+}
@@ -0,0 +1,238 @@
+use super::types::Format;
+use crate::data::{Entry, Item};
+use crate::files::RealFs;
+use crate::index::{Gitignore, Indexer, Skipped};
+use crate::style::paint;
+use anyhow::{bail, Result};
+use crossterm::style::Stylize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Lists the items of the dotfile and the files they resolve to.
+pub struct ListHandler {
+    indexer: Indexer,
+    items: Vec<Item>,
+    gitignore: Gitignore,
+}
+
+// Public methods.
+impl ListHandler {
+    pub fn new(home: PathBuf, repository: PathBuf, items: Vec<Item>) -> Self {
+        let gitignore = Gitignore::read(&RealFs, &repository);
+        let indexer = Indexer::new(home, repository, None);
+        Self {
+            indexer,
+            items,
+            gitignore,
+        }
+    }
+
+    /// Prints every item with its patterns and the number of matched files,
+    /// and of those ignored by the .gitignore of the repository.
+    pub fn list(&self, format: Format) -> Result<()> {
+        let items = self.matched()?;
+        if format == Format::Json {
+            println!("{}", list_json(&items));
+            return Ok(());
+        }
+
+        for (item, matched, gitignored) in items {
+            let gitignored = match gitignored {
+                0 => String::new(),
+                n => format!(", {} ignored by .gitignore", n),
+            };
+            println!(
+                " {} ({}): {} file(s){}",
+                paint(item.name.as_str().bold()),
+                item.section,
+                matched,
+                gitignored
+            );
+            let files: Vec<String> = item.files.iter().map(|p| p.display().to_string()).collect();
+            println!("   files: {}", files.join(", "));
+            if let Some(ignore) = item.ignore.as_ref().filter(|v| !v.is_empty()) {
+                println!("   ignore: {}", ignore.join(", "));
+            }
+            if !item.groups.is_empty() {
+                println!("   groups: {}", item.groups.join(", "));
+            }
+            if let Some(dir) = &item.repo_dir {
+                println!("   repo_dir: {}", dir.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints every file of the item, as well as the files that were ignored.
+    pub fn show(&self, name: &str, format: Format) -> Result<()> {
+        let (item, entries, skipped) = self.resolve(name)?;
+        if format == Format::Json {
+            println!("{}", self.show_json_of(item, &entries, &skipped));
+            return Ok(());
+        }
+
+        println!(" {} ({})", paint(item.name.as_str().bold()), item.section);
+        for entry in &entries {
+            println!("   {}", entry);
+            if let Entry::Ok {
+                home_path,
+                repo_path,
+                ..
+            } = entry
+            {
+                println!("     home: {}", home_path.display());
+                println!("     repo: {}", repo_path.display());
+                if let Some(rule) = self.gitignore.rule(repo_path) {
+                    println!("     gitignore: {} (not committed)", rule);
+                }
+                if let Some(size) = size(home_path).or_else(|| size(repo_path)) {
+                    println!("     size: {} B", size);
+                }
+            }
+        }
+
+        if !skipped.is_empty() {
+            println!("\n Ignored");
+            for s in &skipped {
+                println!("   {}: {}", s.relpath.display(), s.rule);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn list_json(&self) -> Result<Value> {
+        Ok(list_json(&self.matched()?))
+    }
+
+    pub fn show_json(&self, name: &str) -> Result<Value> {
+        let (item, entries, skipped) = self.resolve(name)?;
+        Ok(self.show_json_of(item, &entries, &skipped))
+    }
+}
+
+// Private methods.
+impl ListHandler {
+    // Returns the items, sorted by name, with the number of files each
+    // item syncs, and how many of them are ignored by the .gitignore.
+    fn matched(&self) -> Result<Vec<(&Item, usize, usize)>> {
+        let (indexed, _) = self.indexer.index_deduped(&self.items)?;
+        let mut items: Vec<(&Item, usize, usize)> = self
+            .items
+            .iter()
+            .map(|item| {
+                let entries = indexed
+                    .iter()
+                    .find(|(name, _)| *name == item.name)
+                    .map(|(_, entries)| entries.as_slice())
+                    .unwrap_or_default();
+                let matched = entries.iter().filter(|e| e.is_ok()).count();
+                let gitignored = entries
+                    .iter()
+                    .filter(|e| self.gitignore_rule(e).is_some())
+                    .count();
+                (item, matched, gitignored)
+            })
+            .collect();
+        items.sort_by(|(a, _, _), (b, _, _)| a.name.cmp(&b.name));
+        Ok(items)
+    }
+
+    fn gitignore_rule(&self, entry: &Entry) -> Option<String> {
+        match entry {
+            Entry::Ok { repo_path, .. } => self.gitignore.rule(repo_path),
+            Entry::Err(_) => None,
+        }
+    }
+
+    fn show_json_of(&self, item: &Item, entries: &[Entry], skipped: &[Skipped]) -> Value {
+        let files: Vec<Value> = entries
+            .iter()
+            .map(|entry| file_json(entry, self.gitignore_rule(entry)))
+            .collect();
+        show_json(item, files, skipped)
+    }
+
+    // Returns the item with its files, after removing files that are synced
+    // as part of other items, and the ignored files.
+    fn resolve(&self, name: &str) -> Result<(&Item, Vec<Entry>, Vec<Skipped>)> {
+        let item = match self.items.iter().find(|item| item.name == name) {
+            Some(item) => item,
+            None => bail!("no item named {}", name),
+        };
+
+        let (indexed, _) = self.indexer.index_deduped(&self.items)?;
+        let entries = indexed
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, entries)| entries)
+            .unwrap_or_default();
+        let (_, skipped) = self.indexer.index_item(item)?;
+        Ok((item, entries, skipped))
+    }
+}
+
+fn list_json(items: &[(&Item, usize, usize)]) -> Value {
+    let items: Vec<Value> = items
+        .iter()
+        .map(|(item, matched, gitignored)| {
+            let files: Vec<String> = item.files.iter().map(|p| p.display().to_string()).collect();
+            json!({
+                "name": item.name,
+                "section": item.section,
+                "files": files,
+                "ignore": item.ignore.clone().unwrap_or_default(),
+                "groups": item.groups,
+                "repo_dir": item.repo_dir.as_ref().map(|p| p.to_string_lossy()),
+                "root": item.root.as_ref().map(|r| r.path.to_string_lossy()),
+                "matched": matched,
+                "gitignored": gitignored,
+            })
+        })
+        .collect();
+    json!({ "items": items })
+}
+
+fn show_json(item: &Item, files: Vec<Value>, skipped: &[Skipped]) -> Value {
+    let skipped: Vec<Value> = skipped
+        .iter()
+        .map(|s| {
+            json!({
+                "relpath": s.relpath.to_string_lossy(),
+                "home_path": s.home_path.to_string_lossy(),
+                "rule": { "source": s.rule.source(), "pattern": s.rule.pattern() },
+            })
+        })
+        .collect();
+
+    json!({
+        "name": item.name,
+        "section": item.section,
+        "files": files,
+        "skipped": skipped,
+    })
+}
+
+// The rule is the line of the .gitignore that ignores the file, if any.
+fn file_json(entry: &Entry, gitignore: Option<String>) -> Value {
+    match entry {
+        Entry::Ok {
+            relpath,
+            status,
+            home_path,
+            repo_path,
+        } => json!({
+            "relpath": relpath.to_string_lossy(),
+            "status": status.name(),
+            "home_path": home_path.to_string_lossy(),
+            "repo_path": repo_path.to_string_lossy(),
+            "size": size(home_path).or_else(|| size(repo_path)),
+            "gitignore": gitignore,
+        }),
+        Entry::Err(err) => json!({ "path": err.path.to_string_lossy(), "error": err.cause }),
+    }
+}
+
+fn size(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|m| m.len())
+}
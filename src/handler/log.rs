@@ -0,0 +1,189 @@
+use super::history::{History, SyncRun};
+use crate::cmd::CmdRunner;
+use crate::data::{Entry, Item};
+use crate::error::DotfError;
+use crate::index::Indexer;
+use crate::style::{self, paint};
+use anyhow::{bail, Result};
+use crossterm::style::Stylize;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Shows what changed in tracked files and when: the git history of their
+/// repository paths, and the syncs of this machine that wrote them.
+pub struct LogHandler {
+    indexer: Indexer,
+    items: Vec<Item>,
+    repository: PathBuf,
+    runner: CmdRunner,
+    history: History,
+    // Show the diff of each commit.
+    patch: bool,
+}
+
+// A tracked file, as (item, relpath, repo path).
+type Tracked = (String, PathBuf, PathBuf);
+
+// Public methods.
+impl LogHandler {
+    pub fn new(home: PathBuf, repository: PathBuf, items: Vec<Item>, history: History) -> Self {
+        let runner = CmdRunner::new(repository.clone());
+        let indexer = Indexer::new(home, repository.clone(), None);
+        Self {
+            indexer,
+            items,
+            repository,
+            runner,
+            history,
+            patch: false,
+        }
+    }
+
+    pub fn with_patch(mut self, patch: bool) -> Self {
+        self.patch = patch;
+        self
+    }
+
+    /// Prints the history of the item or tracked file, given as a path in
+    /// home or the repository, or of all tracked files.
+    pub fn log(&self, target: Option<&str>) -> Result<()> {
+        let tracked = self.tracked(target)?;
+        if tracked.is_empty() {
+            println!("No tracked files.");
+            return Ok(());
+        }
+
+        let log = self.git(&tracked, style::colors())?;
+        match log.trim().is_empty() {
+            true => println!("No commits."),
+            false => print!("{}", log),
+        }
+
+        let runs = self.filter_runs(&tracked)?;
+        println!("\n{}", paint("Syncs on this machine".bold()));
+        if runs.is_empty() {
+            println!("  none recorded in {}", self.history.path().display());
+        }
+        for run in runs {
+            let count = run
+                .files
+                .iter()
+                .filter(|f| is_tracked(&tracked, &f.item, &f.relpath))
+                .count();
+            println!(
+                "  {}  to {}  {}  {} file(s)",
+                run.time_utc(),
+                run.direction,
+                run.host,
+                count
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the output of git log for the item or tracked file.
+    pub fn git_log(&self, target: Option<&str>) -> Result<String> {
+        self.git(&self.tracked(target)?, false)
+    }
+
+    /// Returns the recorded syncs, oldest first, that wrote the item or
+    /// tracked file.
+    pub fn runs(&self, target: Option<&str>) -> Result<Vec<SyncRun>> {
+        self.filter_runs(&self.tracked(target)?)
+    }
+}
+
+// Private methods.
+impl LogHandler {
+    // Returns the files of the item or file, or all files without a target.
+    fn tracked(&self, target: Option<&str>) -> Result<Vec<Tracked>> {
+        let (indexed, _) = self.indexer.index_deduped(&self.items)?;
+        let mut tracked = Vec::new();
+        for (name, entries) in indexed {
+            for entry in entries {
+                if let Entry::Ok {
+                    relpath,
+                    home_path,
+                    repo_path,
+                    ..
+                } = entry
+                {
+                    let file = [home_path.as_path(), repo_path.as_path()];
+                    if target.is_none_or(|target| matches(target, &name, &relpath, &file)) {
+                        tracked.push((name.to_string(), relpath, repo_path));
+                    }
+                }
+            }
+        }
+
+        if let Some(target) = target.filter(|_| tracked.is_empty()) {
+            // Items without files have an empty history.
+            if !self.items.iter().any(|item| item.name == target) {
+                bail!("{} is neither an item nor a tracked file", target);
+            }
+        }
+        Ok(tracked)
+    }
+
+    fn git(&self, tracked: &[Tracked], color: bool) -> Result<String> {
+        // Without paths, git log shows every commit.
+        if tracked.is_empty() {
+            return Ok(String::new());
+        }
+
+        let mut args: Vec<OsString> = vec!["log".into(), "--date=iso".into()];
+        if self.patch {
+            args.push("--patch".into());
+        }
+        if color {
+            args.push("--color=always".into());
+        }
+        args.push("--".into());
+        for (_, _, repo_path) in tracked {
+            let path = repo_path
+                .strip_prefix(&self.repository)
+                .unwrap_or(repo_path);
+            args.push(path.into());
+        }
+
+        let output = self.runner.capture("git", args)?;
+        if !output.success() {
+            return Err(DotfError::Command {
+                cmd: "git".to_string(),
+                cause: output.stderr().trim().to_string(),
+            }
+            .into());
+        }
+        Ok(output.stdout().to_string())
+    }
+
+    fn filter_runs(&self, tracked: &[Tracked]) -> Result<Vec<SyncRun>> {
+        let runs = self.history.load()?;
+        Ok(runs
+            .into_iter()
+            .filter(|run| {
+                run.files
+                    .iter()
+                    .any(|f| is_tracked(tracked, &f.item, &f.relpath))
+            })
+            .collect())
+    }
+}
+
+// Returns true if the target is the item of the file, or the file itself,
+// given relative to the item or as a path in home or the repository.
+fn matches(target: &str, item: &str, relpath: &Path, paths: &[&Path]) -> bool {
+    if target == item || Path::new(target) == relpath {
+        return true;
+    }
+    let path = match env::current_dir() {
+        Ok(cwd) => cwd.join(target),
+        Err(_) => PathBuf::from(target),
+    };
+    paths.contains(&path.as_path())
+}
+
+fn is_tracked(tracked: &[Tracked], item: &str, relpath: &Path) -> bool {
+    tracked.iter().any(|(i, r, _)| i == item && r == relpath)
+}
@@ -1,12 +1,24 @@
 pub mod diff;
+pub mod doctor;
+pub mod history;
+pub mod list;
+pub mod log;
+pub mod plan;
 pub mod status;
 pub mod sync;
 pub mod types;
+pub mod watch;
 
 #[cfg(test)]
 mod tests;
 
-pub use self::types::{DiffOptions, Only};
+pub use self::types::{ConflictPolicy, DiffOptions, Format, Only, Selection};
 pub use diff::DiffHandler;
+pub use doctor::DoctorHandler;
+pub use history::{History, SyncRun, SyncedFile};
+pub use list::ListHandler;
+pub use log::LogHandler;
+pub use plan::{Action, ActionKind, ConflictError, Plan, StalePlanError, SyncSummary, Target};
 pub use status::StatusHandler;
 pub use sync::{SyncHandler, SyncOptions};
+pub use watch::{WatchHandler, WatchOptions};
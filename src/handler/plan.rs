@@ -0,0 +1,352 @@
+use crate::error::DotfError;
+use crate::files::{self, Filesystem};
+use crate::path::try_strip_home_prefix;
+use crate::style::paint;
+use anyhow::{bail, Result};
+use crossterm::style::Stylize;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where files are written to.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Home,
+    Repo,
+}
+
+impl Target {
+    pub fn is_home(&self) -> bool {
+        match self {
+            Target::Home => true,
+            Target::Repo => false,
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "home" => Ok(Target::Home),
+            "repo" => Ok(Target::Repo),
+            _ => bail!("invalid direction: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Home => write!(f, "home"),
+            Target::Repo => write!(f, "repo"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    // Copy a file that does not exist at the destination.
+    Create,
+    // Copy a file over an existing, different, file.
+    Overwrite,
+    // Copy the existing file at the destination before overwriting it.
+    Backup,
+    Skip,
+    // Create the parent directory of a destination.
+    Mkdir,
+}
+
+impl ActionKind {
+    pub fn name(&self) -> &str {
+        match self {
+            ActionKind::Create => "create",
+            ActionKind::Overwrite => "overwrite",
+            ActionKind::Backup => "backup",
+            ActionKind::Skip => "skip",
+            ActionKind::Mkdir => "mkdir",
+        }
+    }
+}
+
+/// A single file operation of a sync.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    pub kind: ActionKind,
+    pub item: String,
+    pub relpath: PathBuf,
+    pub target: Target,
+    // Not set for mkdir and skip.
+    pub src: Option<PathBuf>,
+    pub dst: PathBuf,
+    pub reason: String,
+    // Run the operation using sudo.
+    #[serde(default)]
+    pub sudo: bool,
+    // SHA-256 of the source and destination when the plan was made, or None
+    // if the file did not exist. Used to detect changes to saved plans, and
+    // only recorded for plans that are saved or applied.
+    #[serde(default)]
+    pub src_digest: Option<String>,
+    #[serde(default)]
+    pub dst_digest: Option<String>,
+}
+
+impl Action {
+    /// Returns true if the action writes the file of the entry.
+    pub fn is_write(&self) -> bool {
+        matches!(self.kind, ActionKind::Create | ActionKind::Overwrite)
+    }
+
+    /// Returns true if the source or destination no longer matches the
+    /// recorded digests.
+    pub fn is_stale(&self, fs: &dyn Filesystem) -> Result<bool> {
+        if !self.has_content() {
+            return Ok(false);
+        }
+
+        let (src, dst) = self.digests(fs)?;
+        Ok(src != self.src_digest || dst != self.dst_digest)
+    }
+
+    /// Records the digests of the source and destination.
+    pub fn record_digests(&mut self, fs: &dyn Filesystem) -> Result<()> {
+        if self.has_content() {
+            (self.src_digest, self.dst_digest) = self.digests(fs)?;
+        }
+        Ok(())
+    }
+
+    // Returns false for actions that do not read or write files.
+    fn has_content(&self) -> bool {
+        !matches!(self.kind, ActionKind::Skip | ActionKind::Mkdir)
+    }
+
+    fn digests(&self, fs: &dyn Filesystem) -> Result<(Option<String>, Option<String>)> {
+        let src = match &self.src {
+            Some(src) => files::digest_file(fs, src)?,
+            None => None,
+        };
+        Ok((src, files::digest_file(fs, &self.dst)?))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<9} {} {}",
+            self.kind.name(),
+            display_path(&self.dst),
+            paint(format!("({})", self.reason).dark_grey())
+        )
+    }
+}
+
+/// An item or file that could not be synced.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlanError {
+    pub item: String,
+    // The relative path of the file, or the pattern of the item.
+    #[serde(default)]
+    pub path: String,
+    pub reason: String,
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.item, self.path, self.reason)
+    }
+}
+
+/// Files that differ between home and repository, when conflicts should
+/// fail the sync rather than being resolved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConflictError {
+    pub relpaths: Vec<String>,
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} file(s) differ between home and repository:\n  {}\nuse --on-conflict to resolve them",
+            self.relpaths.len(),
+            self.relpaths.join("\n  ")
+        )
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+/// Files that changed since a saved plan was made, as item and relative path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StalePlanError {
+    pub files: Vec<(String, String)>,
+}
+
+impl fmt::Display for StalePlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files: Vec<String> = self
+            .files
+            .iter()
+            .map(|(item, relpath)| format!("{} ({})", relpath, item))
+            .collect();
+        write!(
+            f,
+            "{} file(s) changed since the plan was made:\n  {}\ncreate a new plan using dotf sync --plan-out",
+            files.len(),
+            files.join("\n  ")
+        )
+    }
+}
+
+impl std::error::Error for StalePlanError {}
+
+// The version of the saved plan format.
+const PLAN_VERSION: u32 = 1;
+
+// A plan as written to disk by sync --plan-out.
+#[derive(Deserialize, Serialize)]
+struct SavedPlan {
+    version: u32,
+    #[serde(flatten)]
+    plan: Plan,
+}
+
+/// The actions of a sync, in the order they are executed, grouped by item.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+    pub errors: Vec<PlanError>,
+    // The number of files that are already up to date.
+    pub unchanged: usize,
+}
+
+impl Plan {
+    /// Reads a plan saved using save.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => bail!("failed to read plan {}: {}", path.display(), err),
+        };
+        let saved: SavedPlan = match serde_json::from_str(&content) {
+            Ok(saved) => saved,
+            Err(err) => bail!("invalid plan {}: {}", path.display(), err),
+        };
+        if saved.version != PLAN_VERSION {
+            bail!(
+                "unsupported plan version {} in {}, expected {}",
+                saved.version,
+                path.display(),
+                PLAN_VERSION
+            );
+        }
+        Ok(saved.plan)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let saved = SavedPlan {
+            version: PLAN_VERSION,
+            plan: self.clone(),
+        };
+        fs::write(path, serde_json::to_string_pretty(&saved)?)
+            .map_err(|err| DotfError::io(path, err))?;
+        Ok(())
+    }
+
+    pub fn push(&mut self, action: Action) {
+        self.actions.push(action);
+    }
+
+    /// Records the digests of the files of every action, so that changes
+    /// made before the plan is applied are detected.
+    pub fn record_digests(&mut self, fs: &dyn Filesystem) -> Result<()> {
+        for action in &mut self.actions {
+            action.record_digests(fs)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the actions whose files changed since the plan was made.
+    pub fn stale(&self, fs: &dyn Filesystem) -> Result<Vec<&Action>> {
+        let mut stale = Vec::new();
+        for action in &self.actions {
+            if action.is_stale(fs)? {
+                stale.push(action);
+            }
+        }
+        Ok(stale)
+    }
+
+    /// Returns the actions grouped by item name, in order of appearance.
+    pub fn by_item(&self) -> Vec<(&str, Vec<&Action>)> {
+        let mut groups: Vec<(&str, Vec<&Action>)> = Vec::new();
+        for action in &self.actions {
+            match groups.iter_mut().find(|(name, _)| *name == action.item) {
+                Some((_, actions)) => actions.push(action),
+                None => groups.push((&action.item, vec![action])),
+            }
+        }
+        groups
+    }
+
+    pub fn summary(&self) -> SyncSummary {
+        let count = |kind: ActionKind| self.actions.iter().filter(|a| a.kind == kind).count();
+        SyncSummary {
+            written: count(ActionKind::Create) + count(ActionKind::Overwrite),
+            skipped: count(ActionKind::Skip),
+            backed_up: count(ActionKind::Backup),
+            errors: self.errors.len(),
+            unchanged: self.unchanged,
+        }
+    }
+}
+
+/// The number of files by outcome of a sync.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SyncSummary {
+    pub written: usize,
+    pub skipped: usize,
+    pub backed_up: usize,
+    pub errors: usize,
+    pub unchanged: usize,
+}
+
+impl SyncSummary {
+    /// Counts an action that failed as an error.
+    pub fn fail(&mut self, kind: ActionKind) {
+        match kind {
+            ActionKind::Create | ActionKind::Overwrite => self.written -= 1,
+            ActionKind::Backup => self.backed_up -= 1,
+            ActionKind::Skip => self.skipped -= 1,
+            ActionKind::Mkdir => {}
+        }
+        self.errors += 1;
+    }
+}
+
+impl fmt::Display for SyncSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} written, {} skipped, {} backed up, {} errors ({} unchanged)",
+            self.written, self.skipped, self.backed_up, self.errors, self.unchanged
+        )
+    }
+}
+
+// Paths in home are displayed relative to ~.
+fn display_path(path: &Path) -> String {
+    let stripped = try_strip_home_prefix(path);
+    if stripped == path {
+        path.display().to_string()
+    } else {
+        format!("~/{}", stripped.display())
+    }
+}
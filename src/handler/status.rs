@@ -1,27 +1,57 @@
-use super::types::Only;
+use super::types::{Format, Only, Selection};
 use crate::data::Entry;
 use crate::data::{Item, Status};
-use crate::index::Indexer;
+use crate::files::Filesystem;
+use crate::index::{Indexed, Indexer, Overlap};
+use crate::report::{Event, Reporter, TerminalReporter};
 use anyhow::Result;
-use crossterm::style::Stylize;
+use serde_json::{json, Value};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 pub struct StatusHandler {
     indexer: Indexer,
     items: Vec<Item>,
+    reporter: Arc<dyn Reporter>,
 }
 
 // Public methods.
 impl StatusHandler {
     pub fn new(home: PathBuf, repository: PathBuf, items: Vec<Item>, only: Option<Only>) -> Self {
         let indexer = Indexer::new(home, repository, only);
-        Self { indexer, items }
+        Self {
+            indexer,
+            items,
+            reporter: Arc::new(TerminalReporter::new()),
+        }
+    }
+
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.indexer = self.indexer.with_selection(selection);
+        self
+    }
+
+    pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.indexer = self.indexer.with_fail_fast(fail_fast);
+        self
+    }
+
+    /// Use the filesystem instead of the real one, e.g. to compare against
+    /// a git revision.
+    pub fn with_filesystem(mut self, fs: Arc<dyn Filesystem>) -> Self {
+        self.indexer = self.indexer.with_filesystem(fs);
+        self
     }
 
-    pub fn status(&self, brief: bool) -> Result<()> {
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    pub fn status(&self, brief: bool, format: Format) -> Result<()> {
         log::debug!("Showing status with brief={}", brief);
 
-        let mut indexed = self.indexer.index(&self.items)?;
+        let (mut indexed, overlaps) = self.indexer.index_deduped(&self.items)?;
         indexed.sort_by(|(_, a), (_, b)| a.len().partial_cmp(&b.len()).unwrap());
 
         if brief {
@@ -35,35 +65,117 @@ impl StatusHandler {
                     filtered.push((name, entries));
                 }
             }
-            self.display(&filtered);
-        } else {
-            self.display(&indexed);
-            println!(
-                "\n{} ok | {} diff | {} invalid | {} missing home | {} missing repository",
-                Status::Ok,
-                Status::Diff,
-                "".red(),
-                Status::MissingHome,
-                Status::MissingRepo,
-            );
+            indexed = filtered;
+        }
+
+        if format == Format::Json {
+            self.reporter.report(Event::Document {
+                value: to_json(&indexed, &overlaps),
+            });
+            return Ok(());
+        }
+
+        self.report(&indexed);
+        for overlap in overlaps {
+            self.reporter.report(Event::Overlap {
+                relpath: overlap.relpath.display().to_string(),
+                items: overlap.items,
+                owner: overlap.owner,
+            });
+        }
+        if !brief {
+            self.reporter.report(summary(&indexed));
         }
         Ok(())
     }
+}
 
-    fn display(&self, indexed: &[(String, Vec<Entry>)]) {
+// Private methods.
+impl StatusHandler {
+    fn report(&self, indexed: &[(String, Vec<Entry>)]) {
         for (name, entries) in indexed {
             if entries.is_empty() {
                 continue;
             }
 
-            if entries.len() == 1 {
-                println!(" {}: {}", name, entries.first().unwrap());
-            } else {
-                println!("\n {}", name);
-                for entry in entries {
-                    println!("   {}", entry);
-                }
+            self.reporter.report(Event::ItemStarted {
+                item: name.to_string(),
+                count: entries.len(),
+            });
+            for entry in entries {
+                let event = match entry {
+                    Entry::Ok {
+                        relpath, status, ..
+                    } => Event::Entry {
+                        item: name.to_string(),
+                        relpath: relpath.display().to_string(),
+                        status: status.clone(),
+                    },
+                    Entry::Err(err) => Event::Error {
+                        item: name.to_string(),
+                        path: err.path.display().to_string(),
+                        reason: err.cause.to_string(),
+                    },
+                };
+                self.reporter.report(event);
             }
         }
     }
 }
+
+fn summary(indexed: &[(String, Vec<Entry>)]) -> Event {
+    let entries = || indexed.iter().flat_map(|(_, entries)| entries);
+    let count = |status: Status| {
+        entries()
+            .filter(|entry| matches!(entry, Entry::Ok { status: s, .. } if *s == status))
+            .count()
+    };
+    Event::StatusSummary {
+        ok: count(Status::Ok),
+        diff: count(Status::Diff),
+        invalid: entries().filter(|entry| !entry.is_ok()).count(),
+        missing_home: count(Status::MissingHome),
+        missing_repo: count(Status::MissingRepo),
+    }
+}
+
+fn to_json(indexed: &Indexed, overlaps: &[Overlap]) -> Value {
+    let items: Vec<Value> = indexed
+        .iter()
+        .map(|(name, entries)| {
+            let entries: Vec<Value> = entries.iter().map(entry_json).collect();
+            json!({ "name": name, "entries": entries })
+        })
+        .collect();
+
+    let overlaps: Vec<Value> = overlaps
+        .iter()
+        .map(|overlap| {
+            json!({
+                "relpath": overlap.relpath.to_string_lossy(),
+                "home_path": overlap.home_path.to_string_lossy(),
+                "owner": overlap.owner,
+                "items": overlap.items,
+            })
+        })
+        .collect();
+
+    json!({ "items": items, "overlaps": overlaps })
+}
+
+fn entry_json(entry: &Entry) -> Value {
+    match entry {
+        Entry::Ok {
+            relpath,
+            status,
+            home_path,
+            repo_path,
+        } => json!({
+            "relpath": relpath.to_string_lossy(),
+            "status": status.name(),
+            "home_path": home_path.to_string_lossy(),
+            "repo_path": repo_path.to_string_lossy(),
+        }),
+        Entry::Err(err) => json!({ "path": err.path.to_string_lossy(), "error": err.cause }),
+    }
+}
@@ -1,15 +1,26 @@
-use super::types::{DiffOptions, Only};
+use super::history::{History, SyncRun, SyncedFile};
+use super::plan::{
+    Action, ActionKind, ConflictError, Plan, PlanError, StalePlanError, SyncSummary, Target,
+};
+use super::types::{ConflictPolicy, DiffOptions, Format, Only, Selection};
 use crate::cmd::CmdRunner;
-use crate::data::{Entry, Item, Status};
-use crate::files;
-use crate::index::Indexer;
-use crate::path_str;
+use crate::data::file::add_ignore;
+use crate::data::{Entry, Hooks, Item, Status};
+use crate::error::DotfError;
+use crate::files::{self, Filesystem, RealFs};
+use crate::index::{Indexed, Indexer};
 use crate::prompt::Prompt;
+use crate::report::{Event, Reporter, TerminalReporter};
+use crate::style::paint;
 use anyhow::{bail, Result};
 use crossterm::style::Stylize;
-use inquire::MultiSelect;
+use glob::Pattern as GlobPattern;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct SyncOptions {
@@ -25,20 +36,58 @@ pub struct SyncOptions {
     pub git_commit: Option<String>,
     // Run git push after committing.
     pub git_push: bool,
+    // Review each file that is not up to date.
     pub interactive: bool,
+    // Never prompt the user, e.g. when there is no terminal.
+    pub non_interactive: bool,
+    // How to handle files that differ, instead of asking the user.
+    // Defaults to fail when non-interactive and confirm is set.
+    pub on_conflict: Option<ConflictPolicy>,
+    // Global hooks, such as pre_sync and post_sync.
+    pub hooks: Hooks,
+    // Run hooks, global as well as item specific.
+    pub run_hooks: bool,
+    // Report the output of hooks, instead of letting them write to stdout.
+    pub capture_hooks: bool,
+    // Used to open files when reviewing files.
+    pub editor: Option<String>,
+    // Used to merge files that differ when reviewing files.
+    pub merge_command: Option<String>,
+    // The dotfile to update when ignoring files while reviewing.
+    pub dotfile_path: Option<PathBuf>,
+    // Print the plan and summary as JSON.
+    pub format: Format,
+    // Save the plan to the file, instead of executing it.
+    pub plan_out: Option<PathBuf>,
+    // Stop at the first file that cannot be synced.
+    pub fail_fast: bool,
+    // Records runs that wrote files, shown by dotf log.
+    pub history: Option<History>,
 }
 
 impl Default for SyncOptions {
     fn default() -> Self {
         Self {
             interactive: false,
+            non_interactive: false,
+            on_conflict: None,
             dryrun: false,
             confirm: true,
             backup: true,
             show_diff: false,
+            format: Format::Text,
+            plan_out: None,
+            fail_fast: false,
+            history: None,
             diff_options: DiffOptions::default(),
             git_commit: None,
             git_push: false,
+            hooks: Hooks::default(),
+            run_hooks: true,
+            capture_hooks: false,
+            editor: None,
+            merge_command: None,
+            dotfile_path: None,
         }
     }
 }
@@ -49,6 +98,10 @@ pub struct SyncHandler {
     items: Vec<Item>,
     options: SyncOptions,
     runner: CmdRunner,
+    // Runs hooks in the home directory.
+    hook_runner: CmdRunner,
+    fs: Arc<dyn Filesystem>,
+    reporter: Arc<dyn Reporter>,
 }
 
 // Public methods.
@@ -62,23 +115,45 @@ impl SyncHandler {
         only: Option<Only>,
     ) -> Self {
         let runner = CmdRunner::new(repository.clone());
-        let indexer = Indexer::new(home, repository, only);
+        let hook_runner = CmdRunner::new(home.clone());
+        let indexer = Indexer::new(home, repository, only).with_fail_fast(options.fail_fast);
         Self {
             options,
             prompt,
             indexer,
             items,
             runner,
+            hook_runner,
+            fs: Arc::new(RealFs),
+            reporter: Arc::new(TerminalReporter::new()),
         }
     }
 
-    pub fn copy_to_home(&self) -> Result<()> {
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.indexer = self.indexer.with_selection(selection);
+        self
+    }
+
+    /// Use the filesystem, for indexing as well as writing files, instead of
+    /// the real one. Commands such as sudo and hooks still use the real one.
+    pub fn with_filesystem(mut self, fs: Arc<dyn Filesystem>) -> Self {
+        self.indexer = self.indexer.with_filesystem(fs.clone());
+        self.fs = fs;
+        self
+    }
+
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    pub fn copy_to_home(&self) -> Result<SyncSummary> {
         self.copy(Target::Home)
     }
 
-    pub fn copy_to_repo(&self) -> Result<()> {
-        self.copy(Target::Repo)?;
-        if let Some(msg) = &self.options.git_commit {
+    pub fn copy_to_repo(&self) -> Result<SyncSummary> {
+        let summary = self.copy(Target::Repo)?;
+        if let Some(msg) = self.options.git_commit.as_ref().filter(|_| !self.dryrun()) {
             log::info!("Creating git commit with message: {msg}");
             self.runner.run("git", to_strings(&["add", "."]))?;
             self.runner
@@ -89,73 +164,369 @@ impl SyncHandler {
                 self.runner.run("git", to_strings(&["push"]))?;
             }
         }
-        Ok(())
+        Ok(summary)
     }
 
-    fn copy(&self, target: Target) -> Result<()> {
-        let map = self.indexer.index(&self.items)?;
-        let entries: Vec<&Entry> = map
+    /// Executes a plan saved using sync --plan-out. Fails without changing
+    /// any files if an action does not match the files of the dotfile, or a
+    /// file changed since the plan was made.
+    pub fn apply(&self, plan: &Plan) -> Result<SyncSummary> {
+        // The plan file may have been edited, so it is only trusted to do
+        // what a sync could have planned.
+        let indexed = self.indexer.index(&self.items)?;
+        let invalid: Vec<String> = plan
+            .actions
             .iter()
-            .flat_map(|(_name, es)| es)
-            .filter(|entry| entry.is_ok())
+            .filter_map(|action| {
+                let reason = self.check_action(action, &indexed)?;
+                Some(format!(
+                    "{} {} ({}): {}",
+                    action.kind.name(),
+                    action.relpath.display(),
+                    action.item,
+                    reason
+                ))
+            })
             .collect();
+        if !invalid.is_empty() {
+            bail!(
+                "the plan does not match the dotfile:\n  {}",
+                invalid.join("\n  ")
+            );
+        }
 
-        let entries = if self.options.interactive {
-            let files: Vec<String> = entries
+        let stale = plan.stale(self.fs.as_ref())?;
+        if !stale.is_empty() {
+            // Backups and writes of the same file are reported once.
+            let mut files: Vec<(String, String)> = stale
                 .iter()
-                .filter_map(|e| match e {
-                    Entry::Ok {
-                        relpath, status, ..
-                    } => match status {
-                        Status::Ok => None,
-                        Status::Diff => Some(relpath.to_string()),
-                        Status::MissingHome if target.is_home() => Some(relpath.to_string()),
-                        Status::MissingRepo if !target.is_home() => Some(relpath.to_string()),
-                        _ => None,
-                    },
-                    Entry::Err(_) => None,
+                .map(|action| {
+                    (
+                        action.item.to_string(),
+                        action.relpath.display().to_string(),
+                    )
                 })
                 .collect();
-            let selected = MultiSelect::new("Select entries to sync", files).prompt()?;
+            files.dedup();
+            return Err(StalePlanError { files }.into());
+        }
+        self.execute(plan)
+    }
 
-            let mut matched = Vec::new();
-            for entry in entries {
-                let relpath = entry.get_relpath().to_string();
-                if selected.contains(&relpath) {
-                    matched.push(entry);
+    fn copy(&self, target: Target) -> Result<SyncSummary> {
+        let mut plan = self.plan(target)?;
+        let summary = self.execute(&plan)?;
+        if let Some(path) = &self.options.plan_out {
+            plan.record_digests(self.fs.as_ref())?;
+            plan.save(path)?;
+            let path = path.display();
+            self.report(Event::Info {
+                message: format!("Saved plan to {path}, apply it using: dotf apply {path}"),
+            });
+        }
+        Ok(summary)
+    }
+
+    /// Decides what to do with each file, asking the user if needed, without
+    /// changing any files.
+    pub fn plan(&self, target: Target) -> Result<Plan> {
+        let (map, _) = self.indexer.index_deduped(&self.items)?;
+        let mut plan = Plan::default();
+        let mut entries: Vec<(&str, &Entry)> = Vec::new();
+        for (name, es) in &map {
+            for entry in es {
+                match entry {
+                    Entry::Ok { .. } => entries.push((name, entry)),
+                    Entry::Err(err) => plan.errors.push(PlanError {
+                        item: name.to_string(),
+                        path: err.path.display().to_string(),
+                        reason: err.cause.to_string(),
+                    }),
                 }
             }
-            matched
+        }
+
+        // The target of each entry, or None if it should be skipped.
+        let entries: Vec<(&str, &Entry, Option<Target>)> = if self.options.interactive {
+            if self.options.non_interactive {
+                bail!("cannot review files interactively without a terminal");
+            }
+            self.review(entries)?
         } else {
             entries
+                .into_iter()
+                .map(|(name, entry)| (name, entry, Some(target)))
+                .collect()
         };
 
-        for entry in entries {
-            match entry {
-                Entry::Ok {
+        if self.conflict_policy() == Some(ConflictPolicy::Fail) {
+            let relpaths: Vec<String> = entries
+                .iter()
+                .filter(|(name, entry, _)| entry.is_diff() && self.writable(&target, name))
+                .map(|(_, entry, _)| entry.get_relpath().display().to_string())
+                .collect();
+            if !relpaths.is_empty() {
+                return Err(ConflictError { relpaths }.into());
+            }
+        }
+
+        let mut dirs = Vec::new();
+        for (name, entry, chosen) in entries {
+            if let Entry::Ok {
+                relpath,
+                status,
+                home_path,
+                repo_path,
+            } = entry
+            {
+                let file = PlannedFile {
+                    item: name,
                     relpath,
                     status,
                     home_path,
                     repo_path,
-                } => self.make_copy(&target, relpath, status, home_path, repo_path)?,
-                Entry::Err(reason) => bail!("invalid entry: {}", reason),
+                };
+                match chosen {
+                    Some(target) => self.plan_file(&mut plan, &mut dirs, &file, target)?,
+                    None => plan.push(file.action(ActionKind::Skip, target, "skipped in review")),
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    // Executes the actions of the plan, or only reports them on dry runs.
+    fn execute(&self, plan: &Plan) -> Result<SyncSummary> {
+        let dryrun = self.dryrun();
+        self.run_hooks("pre_sync", &self.options.hooks.pre_sync)?;
+
+        if dryrun {
+            self.report(Event::Info {
+                message: "Dry run, no files are changed.".to_string(),
+            });
+        }
+
+        // Items with errors but no actions are reported last.
+        let mut groups = plan.by_item();
+        for error in &plan.errors {
+            if !groups.iter().any(|(item, _)| *item == error.item) {
+                groups.push((&error.item, Vec::new()));
             }
         }
 
+        let mut summary = plan.summary();
+        let mut errors: Vec<PlanError> = Vec::new();
+        let mut changed: Vec<&str> = Vec::new();
+        let mut written: Vec<&Action> = Vec::new();
+        for (item, actions) in groups {
+            let item_errors: Vec<&PlanError> =
+                plan.errors.iter().filter(|e| e.item == item).collect();
+            self.report(Event::ItemStarted {
+                item: item.to_string(),
+                count: actions.len() + item_errors.len(),
+            });
+
+            for action in actions {
+                // Do not overwrite files that failed to be backed up.
+                let failed = errors
+                    .iter()
+                    .any(|e| e.item == action.item && Path::new(&e.path) == action.relpath);
+                if failed && action.is_write() {
+                    summary.written -= 1;
+                    summary.skipped += 1;
+                    continue;
+                }
+
+                let result = match dryrun || action.kind == ActionKind::Skip {
+                    true => Ok(()),
+                    false => self.perform(action),
+                };
+                match result {
+                    Ok(()) if action.kind == ActionKind::Skip => self.report(Event::Skipped {
+                        action: action.clone(),
+                    }),
+                    Ok(()) => {
+                        if action.is_write() && action.target.is_home() && !changed.contains(&item)
+                        {
+                            changed.push(item);
+                        }
+                        if action.is_write() && !dryrun {
+                            written.push(action);
+                        }
+                        self.report(Event::Copied {
+                            action: action.clone(),
+                            dryrun,
+                        });
+                    }
+                    Err(err) if self.options.fail_fast => return Err(err),
+                    Err(err) => {
+                        summary.fail(action.kind);
+                        let error = PlanError {
+                            item: action.item.to_string(),
+                            path: action.relpath.display().to_string(),
+                            reason: format!("failed to {}: {}", action.kind.name(), err),
+                        };
+                        self.report_error(&error);
+                        errors.push(error);
+                    }
+                }
+            }
+
+            for error in item_errors {
+                self.report_error(error);
+            }
+        }
+
+        self.record(&written);
+        for name in changed {
+            if let Some(item) = self.item(name) {
+                self.run_hooks(name, &item.on_change)?;
+            }
+        }
+        self.run_hooks("post_sync", &self.options.hooks.post_sync)?;
+
+        if self.options.format == Format::Json {
+            let mut value = serde_json::to_value(plan)?;
+            let mut all = plan.errors.clone();
+            all.extend(errors);
+            value["errors"] = serde_json::to_value(&all)?;
+            value["summary"] = serde_json::to_value(&summary)?;
+            self.reporter.report(Event::Document { value });
+        } else {
+            self.report(Event::SyncSummary {
+                summary: summary.clone(),
+            });
+        }
+        Ok(summary)
+    }
+
+    // Adds the run to the history, if any files were written.
+    // Failing to do so does not fail the sync.
+    fn record(&self, written: &[&Action]) {
+        let (history, first) = match (&self.options.history, written.first()) {
+            (Some(history), Some(first)) => (history, first),
+            _ => return,
+        };
+        let files = written
+            .iter()
+            .map(|action| SyncedFile {
+                item: action.item.to_string(),
+                relpath: action.relpath.clone(),
+            })
+            .collect();
+        // Plans are made for one direction, apart from reviewed files.
+        let run = SyncRun::now(first.target, files);
+        if let Err(err) = history.record(&run) {
+            log::warn!(
+                "Failed to record sync in {}: {}",
+                history.path().display(),
+                err
+            );
+        }
+    }
+
+    // Reports the event, unless the plan is printed as a JSON document.
+    fn report(&self, event: Event) {
+        if self.options.format != Format::Json {
+            self.reporter.report(event);
+        }
+    }
+
+    fn report_error(&self, error: &PlanError) {
+        self.report(Event::Error {
+            item: error.item.to_string(),
+            path: error.path.to_string(),
+            reason: error.reason.to_string(),
+        });
+    }
+
+    // Files are not changed on dry runs and when only saving the plan.
+    fn dryrun(&self) -> bool {
+        self.options.dryrun || self.options.plan_out.is_some()
+    }
+
+    fn item(&self, name: &str) -> Option<&Item> {
+        self.items.iter().find(|item| item.name == name)
+    }
+
+    // Returns false if files of the item may not be written to the target.
+    fn writable(&self, target: &Target, name: &str) -> bool {
+        let root = self.item(name).and_then(|item| item.root.as_ref());
+        !(target.is_home() && root.is_some_and(|root| root.readonly))
+    }
+
+    fn conflict_policy(&self) -> Option<ConflictPolicy> {
+        // Conflicts are resolved by the user when reviewing files.
+        if self.options.interactive {
+            return None;
+        }
+
+        match self.options.on_conflict {
+            Some(policy) => Some(policy),
+            // Fail rather than asking, unless confirmation is disabled.
+            None if self.options.non_interactive && self.options.confirm => {
+                Some(ConflictPolicy::Fail)
+            }
+            None => None,
+        }
+    }
+
+    // Runs the commands using sh in the home directory. The result of each
+    // command is added to results. Fails on the first error if the hooks are
+    // configured to do so.
+    fn run_hooks(&self, name: &str, cmds: &[String]) -> Result<()> {
+        if !self.options.run_hooks || self.dryrun() {
+            return Ok(());
+        }
+
+        for cmd in cmds {
+            log::info!("Running {} hook: {}", name, cmd);
+            let args = to_strings(&["-c", cmd]);
+            let (output, error) = match self.options.capture_hooks {
+                true => match self.hook_runner.capture("sh", args) {
+                    Ok(out) if out.success() => (Some(out.stdout().to_string()), None),
+                    Ok(out) => {
+                        let error = match out.stderr().trim() {
+                            "" => "exited with a non-zero status".to_string(),
+                            stderr => stderr.to_string(),
+                        };
+                        (Some(out.stdout().to_string()), Some(error))
+                    }
+                    Err(err) => (None, Some(err.to_string())),
+                },
+                false => {
+                    let result = self.hook_runner.run_checked("sh", args);
+                    (None, result.err().map(|err| err.to_string()))
+                }
+            };
+
+            let failed = error.is_some();
+            self.report(Event::Hook {
+                name: name.to_string(),
+                cmd: cmd.to_string(),
+                output,
+                error,
+            });
+
+            if failed && self.options.hooks.fail_on_error {
+                bail!("{} hook failed: {}", name, cmd);
+            }
+        }
         Ok(())
     }
 
-    fn make_copy(
+    // Adds the actions needed to sync the file to the target.
+    fn plan_file(
         &self,
-        target: &Target,
-        relpath: &str,
-        status: &Status,
-        home_path: &Path,
-        repo_path: &Path,
+        plan: &mut Plan,
+        dirs: &mut Vec<PathBuf>,
+        file: &PlannedFile,
+        target: Target,
     ) -> Result<()> {
-        match status {
+        match file.status {
             Status::Ok => {
-                log::info!("{} ok", relpath);
+                log::info!("{} ok", file.relpath.display());
+                plan.unchanged += 1;
                 return Ok(());
             }
             Status::MissingHome if !target.is_home() => return Ok(()),
@@ -163,85 +534,393 @@ impl SyncHandler {
             _ => {}
         }
 
-        let exec = !self.options.dryrun;
+        let root = self.item(file.item).and_then(|item| item.root.as_ref());
+        if let Some(root) = root.filter(|root| root.readonly && target.is_home()) {
+            let reason = format!("root {} is read-only", root.name);
+            plan.push(file.action(ActionKind::Skip, target, &reason));
+            return Ok(());
+        }
 
-        let (display_name, src, dst) = match target {
-            Target::Home => {
-                let s = format!("~/{}", relpath);
-                (s, repo_path, home_path)
-            }
-            Target::Repo => {
-                let s = path_str!(repo_path);
-                (s, home_path, repo_path)
+        let conflict = matches!(file.status, Status::Diff);
+        let policy = self.conflict_policy().filter(|_| conflict);
+        let overwrite = match policy {
+            None => None,
+            Some(ConflictPolicy::Skip) => Some(false),
+            Some(ConflictPolicy::Ours) => Some(!target.is_home()),
+            Some(ConflictPolicy::Theirs) => Some(target.is_home()),
+            Some(ConflictPolicy::Fail) => {
+                bail!(
+                    "{} differs between home and repository",
+                    file.relpath.display()
+                )
             }
         };
+        if overwrite == Some(false) {
+            let reason = "differs, kept by conflict policy";
+            plan.push(file.action(ActionKind::Skip, target, reason));
+            return Ok(());
+        }
 
-        let src_str = path_str!(src);
-        let dst_str = path_str!(dst);
+        let (src, dst) = file.paths(target);
 
-        if self.options.confirm {
-            if self.options.show_diff && matches!(status, Status::Diff) {
-                let mut cmd = self.options.diff_options.to_cmd(&src_str, &dst_str)?;
-                cmd.status()?;
+        // Resolved conflicts, new files when non-interactive and reviewed
+        // files are written without asking.
+        let ask = overwrite.is_none() && !self.options.non_interactive && !self.options.interactive;
+        if self.options.confirm && ask {
+            if self.options.show_diff && conflict {
+                let (src, dst) = self.local_paths(src, dst)?;
+                let mut cmd = self.options.diff_options.to_cmd(&src, &dst)?;
+                cmd.status().map_err(|err| self.diff_error(err))?;
             }
 
-            let msg = format!("Write {}?", &display_name.blue());
+            let display_name = match (target, root) {
+                (Target::Home, None) => format!("~/{}", file.relpath.display()),
+                _ => dst.display().to_string(),
+            };
+            let msg = format!("Write {}?", paint(display_name.as_str().blue()));
             if !self.prompt.confirm(&msg, false)? {
-                log::info!("Skipping {}", src_str);
+                plan.push(file.action(ActionKind::Skip, target, "declined"));
                 return Ok(());
             }
         }
 
+        let sudo = target.is_home() && root.is_some_and(|root| root.sudo);
         let dir = match dst.parent() {
-            Some(parent) => parent,
-            None => bail!("failed to get parent directory of {}", dst_str),
+            Some(parent) => parent.to_path_buf(),
+            None => bail!("failed to get parent directory of {}", dst.display()),
+        };
+        if !self.fs.exists(&dir) && !dirs.contains(&dir) {
+            plan.push(Action {
+                dst: dir.clone(),
+                sudo,
+                ..file.action(ActionKind::Mkdir, target, "directory does not exist")
+            });
+            dirs.push(dir);
+        }
+
+        if target.is_home() && self.fs.exists(dst) && self.options.backup {
+            plan.push(Action {
+                src: Some(dst.to_path_buf()),
+                dst: backup_path(dst),
+                sudo,
+                ..file.action(ActionKind::Backup, target, "file is overwritten")
+            });
+        }
+
+        let (kind, reason) = match (conflict, target) {
+            (true, _) if overwrite.is_some() => (
+                ActionKind::Overwrite,
+                "differs, resolved by conflict policy",
+            ),
+            (true, _) => (ActionKind::Overwrite, "differs"),
+            (false, Target::Home) => (ActionKind::Create, "missing in home"),
+            (false, Target::Repo) => (ActionKind::Create, "missing in repository"),
+        };
+        plan.push(Action {
+            src: Some(src.to_path_buf()),
+            sudo,
+            ..file.action(kind, target, reason)
+        });
+        Ok(())
+    }
+
+    // Returns why the action cannot be applied, or None if it only does what
+    // a sync of the indexed files would.
+    fn check_action(&self, action: &Action, indexed: &Indexed) -> Option<String> {
+        let paths = indexed
+            .iter()
+            .filter(|(name, _)| *name == action.item)
+            .flat_map(|(_, entries)| entries)
+            .find_map(|entry| match entry {
+                Entry::Ok {
+                    relpath,
+                    home_path,
+                    repo_path,
+                    ..
+                } if *relpath == action.relpath => Some((home_path, repo_path)),
+                _ => None,
+            });
+        let (home_path, repo_path) = match paths {
+            Some(paths) => paths,
+            None => return Some("not a file of the item".to_string()),
+        };
+        let (src, dst) = match action.target {
+            Target::Home => (repo_path, home_path),
+            Target::Repo => (home_path, repo_path),
         };
 
-        if exec {
-            if !dir.exists() {
-                log::info!("Creating directory: {:?}", dir);
-                files::create_dirs(dir)?;
+        let root = self.item(&action.item).and_then(|item| item.root.as_ref());
+        let sudo = action.target.is_home() && root.is_some_and(|root| root.sudo);
+        if action.sudo != sudo {
+            return Some("sudo does not match the root of the item".to_string());
+        }
+        if action.kind != ActionKind::Skip && !self.writable(&action.target, &action.item) {
+            return Some("the root of the item is read-only".to_string());
+        }
+
+        let expected = match action.kind {
+            ActionKind::Skip => return None,
+            ActionKind::Mkdir => (None, dst.parent().map(Path::to_path_buf)),
+            ActionKind::Backup if action.target.is_home() => {
+                (Some(dst.clone()), Some(backup_path(dst)))
             }
+            ActionKind::Backup => return Some("only files in home are backed up".to_string()),
+            ActionKind::Create | ActionKind::Overwrite => (Some(src.clone()), Some(dst.clone())),
+        };
+        match (action.src.clone(), Some(action.dst.clone())) == expected {
+            true => None,
+            false => Some("paths do not match the file of the item".to_string()),
+        }
+    }
 
-            if target.is_home() && dst.exists() && self.options.backup {
-                let filename = path_str!(dst.file_name().unwrap());
-                let filename = format!(".{filename}.backup");
+    fn perform(&self, action: &Action) -> Result<()> {
+        let dst = &action.dst;
+        match (action.kind, &action.src) {
+            (ActionKind::Skip, _) => {}
+            (ActionKind::Mkdir, _) => {
+                log::info!("Creating directory: {:?}", dst);
+                if action.sudo {
+                    self.sudo(&["mkdir".as_ref(), "-p".as_ref(), dst.as_os_str()])?;
+                } else {
+                    self.fs.create_dir_all(dst)?;
+                }
+            }
+            (_, Some(src)) => {
+                log::debug!("Copying {:?} to {:?}", src, dst);
+                if action.sudo {
+                    let src = self.fs.local_path(src)?;
+                    self.sudo(&["cp".as_ref(), src.as_os_str(), dst.as_os_str()])?;
+                } else {
+                    self.fs.copy(src, dst)?;
+                }
+            }
+            (kind, None) => bail!(
+                "{} of {} has no source",
+                kind.name(),
+                action.relpath.display()
+            ),
+        }
+        Ok(())
+    }
 
-                let mut backup = PathBuf::from(&dst);
-                backup.set_file_name(filename);
+    // Asks the user what to do with each file that is not up to date.
+    // Returns the files with the chosen target, or None if skipped.
+    fn review<'a>(
+        &self,
+        entries: Vec<(&'a str, &'a Entry)>,
+    ) -> Result<Vec<(&'a str, &'a Entry, Option<Target>)>> {
+        let mut reviewed = Vec::new();
+        'entries: for (name, entry) in entries {
+            let (relpath, status, home_path, repo_path) = match entry {
+                Entry::Ok {
+                    relpath,
+                    status,
+                    home_path,
+                    repo_path,
+                } if !matches!(status, Status::Ok) => (relpath, status, home_path, repo_path),
+                _ => continue,
+            };
 
-                files::copy(dst, &backup)?;
-                log::debug!("Created backup of {}", dst_str);
+            let choices = self.choices(name, relpath, status);
+            let labels: Vec<String> = choices.iter().map(|a| a.to_string()).collect();
+            let msg = format!("{} {} ({})", status, relpath.display(), name);
+            loop {
+                let target = match choices[self.prompt.select(&msg, &labels)?] {
+                    Choice::ViewDiff => {
+                        let (home_path, repo_path) = self.local_paths(home_path, repo_path)?;
+                        let mut cmd = self.options.diff_options.to_cmd(&home_path, &repo_path)?;
+                        cmd.status().map_err(|err| self.diff_error(err))?;
+                        continue;
+                    }
+                    Choice::Edit => {
+                        let editor = self.options.editor.as_deref().unwrap_or_default();
+                        let (home_path, repo_path) = self.local_paths(home_path, repo_path)?;
+                        self.open(editor, &home_path, &repo_path)?;
+                        continue;
+                    }
+                    Choice::Merge => {
+                        let merge = self.options.merge_command.as_deref().unwrap_or_default();
+                        let (home_path, repo_path) = self.local_paths(home_path, repo_path)?;
+                        self.open(merge, &home_path, &repo_path)?;
+                        continue;
+                    }
+                    Choice::CopyToHome => Some(Target::Home),
+                    Choice::CopyToRepo => Some(Target::Repo),
+                    Choice::Skip => None,
+                    Choice::Ignore => {
+                        self.ignore(name, relpath)?;
+                        None
+                    }
+                    Choice::Quit => break 'entries,
+                };
+                reviewed.push((name, entry, target));
+                break;
             }
+        }
+        Ok(reviewed)
+    }
+
+    // The paths of the files on the real filesystem, for external commands.
+    fn local_paths(&self, home_path: &Path, repo_path: &Path) -> Result<(PathBuf, PathBuf)> {
+        Ok((
+            self.fs.local_path(home_path)?,
+            self.fs.local_path(repo_path)?,
+        ))
+    }
+
+    // The choices the user has for a file with the status.
+    fn choices(&self, name: &str, relpath: &Path, status: &Status) -> Vec<Choice> {
+        let diff = matches!(status, Status::Diff);
+        let in_home = !matches!(status, Status::MissingHome);
+        let in_repo = !matches!(status, Status::MissingRepo);
+        // Ignore patterns only apply to files matched by a glob.
+        let globbed = self
+            .item(name)
+            .is_some_and(|item| !item.files.iter().any(|f| f == relpath));
 
-            files::copy(src, dst)?;
+        let mut actions = Vec::new();
+        if diff {
+            actions.push(Choice::ViewDiff);
         }
+        if in_repo && self.writable(&Target::Home, name) {
+            actions.push(Choice::CopyToHome);
+        }
+        if in_home {
+            actions.push(Choice::CopyToRepo);
+        }
+        actions.push(Choice::Skip);
+        if diff && self.options.merge_command.is_some() {
+            actions.push(Choice::Merge);
+        }
+        if diff && self.options.editor.is_some() {
+            actions.push(Choice::Edit);
+        }
+        if globbed && self.options.dotfile_path.is_some() {
+            actions.push(Choice::Ignore);
+        }
+        actions.push(Choice::Quit);
+        actions
+    }
+
+    // Runs the command, e.g. the editor, with both versions of the file.
+    fn open(&self, cmd: &str, home_path: &Path, repo_path: &Path) -> Result<()> {
+        let mut args = cmd.split_whitespace();
+        let program = match args.next() {
+            Some(program) => program,
+            None => bail!("empty command"),
+        };
+
+        Command::new(program)
+            .args(args)
+            .arg(home_path)
+            .arg(repo_path)
+            .status()
+            .map_err(|err| DotfError::command(program, err))?;
+        Ok(())
+    }
 
-        println!("  {} {}", "".green(), &relpath);
+    fn diff_error(&self, err: std::io::Error) -> DotfError {
+        let program = self.options.diff_options.program().unwrap_or("diff");
+        DotfError::command(program, err)
+    }
+
+    // Adds the file to the ignore patterns of the item in the dotfile.
+    fn ignore(&self, name: &str, relpath: &Path) -> Result<()> {
+        let (path, item) = match (&self.options.dotfile_path, self.item(name)) {
+            (Some(path), Some(item)) => (path, item),
+            _ => bail!(
+                "failed to ignore {}: unknown dotfile or item",
+                relpath.display()
+            ),
+        };
+
+        // Patterns in the dotfile are strings, so the file can only be
+        // ignored if its path is valid UTF-8.
+        let relpath = match relpath.to_str() {
+            Some(s) => s,
+            None => bail!("cannot ignore {}: not valid UTF-8", relpath.display()),
+        };
+        let content = files::read_string(path)?;
+        let content = add_ignore(&content, item, &GlobPattern::escape(relpath))?;
+        fs::write(path, content).map_err(|err| DotfError::io(path, err))?;
+        self.report(Event::Info {
+            message: format!("  {} ignored in {}", relpath, name),
+        });
         Ok(())
     }
+
+    fn sudo(&self, args: &[&OsStr]) -> Result<()> {
+        self.runner.run_checked("sudo", args)
+    }
 }
 
-enum Target {
-    Home,
-    Repo,
+// The backup of a file in home, e.g. ~/init.vim is backed up as ~/.init.vim.backup.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut filename = OsString::from(".");
+    filename.push(path.file_name().unwrap_or_default());
+    filename.push(".backup");
+    path.with_file_name(filename)
 }
 
-impl Target {
-    fn is_home(&self) -> bool {
-        match self {
-            Target::Home => true,
-            Target::Repo => false,
+// A file of an entry, used to create actions.
+struct PlannedFile<'a> {
+    item: &'a str,
+    relpath: &'a Path,
+    status: &'a Status,
+    home_path: &'a Path,
+    repo_path: &'a Path,
+}
+
+impl PlannedFile<'_> {
+    // Returns the source and destination when syncing to the target.
+    fn paths(&self, target: Target) -> (&Path, &Path) {
+        match target {
+            Target::Home => (self.repo_path, self.home_path),
+            Target::Repo => (self.home_path, self.repo_path),
+        }
+    }
+
+    fn action(&self, kind: ActionKind, target: Target, reason: &str) -> Action {
+        let (_, dst) = self.paths(target);
+        Action {
+            kind,
+            item: self.item.to_string(),
+            relpath: self.relpath.to_path_buf(),
+            target,
+            src: None,
+            dst: dst.to_path_buf(),
+            reason: reason.to_string(),
+            sudo: false,
+            src_digest: None,
+            dst_digest: None,
         }
     }
 }
 
-impl fmt::Display for Target {
+enum Choice {
+    ViewDiff,
+    CopyToHome,
+    CopyToRepo,
+    Skip,
+    Edit,
+    Merge,
+    Ignore,
+    Quit,
+}
+
+impl fmt::Display for Choice {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Target::Home => write!(f, "home"),
-            Target::Repo => write!(f, "repo"),
-        }
+        let s = match self {
+            Choice::ViewDiff => "View diff",
+            Choice::CopyToHome => "Copy to home",
+            Choice::CopyToRepo => "Copy to repository",
+            Choice::Skip => "Skip",
+            Choice::Edit => "Open both in editor",
+            Choice::Merge => "Merge",
+            Choice::Ignore => "Ignore permanently",
+            Choice::Quit => "Quit",
+        };
+        write!(f, "{}", s)
     }
 }
 
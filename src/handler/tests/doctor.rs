@@ -0,0 +1,112 @@
+use crate::handler::{DiffOptions, DoctorHandler};
+use crate::testing::{create_with_path, TestContext};
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+
+fn check(context: &TestContext, items: &str) -> Vec<String> {
+    check_dotfile(context, &format!("repository = \"repo\"\n{}", items))
+}
+
+fn check_dotfile(context: &TestContext, content: &str) -> Vec<String> {
+    let dotfile_path = context.temp_dir.join("dotfiles.toml");
+    create_with_path(&dotfile_path, content).unwrap();
+
+    let handler = DoctorHandler::new(
+        context.home_dir.clone(),
+        dotfile_path,
+        "nano".to_string(),
+        DiffOptions::default(),
+    );
+    let report = handler.check().unwrap();
+    report.messages().iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn reports_unknown_keys() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+
+    // Act
+    let messages = check(
+        &context,
+        "[home]\nvim = { files = [\"init.vim\"], ignroe = [\"*.o\"] }",
+    );
+
+    // Assert
+    assert!(messages.iter().any(|m| m
+        .ends_with("dotfiles.toml:3:31: unknown key `home.vim.ignroe`, did you mean `ignore`?")));
+}
+
+#[test]
+fn reports_item_problems() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+
+    // Act
+    let messages = check(
+        &context,
+        r#"
+[home]
+a = "config/*"
+b = "config/spaceship.yml"
+missing = "missing.txt"
+dir = "deepglob"
+nothing = "*.nothing"
+"#,
+    );
+
+    // Assert
+    let contains = |s: &str| messages.iter().any(|m| m.contains(s));
+    assert!(contains(
+        "spaceship.yml is tracked by: b, a (synced as part of b)"
+    ));
+    assert!(contains(
+        "missing: missing.txt: does not exists in either home or repository"
+    ));
+    assert!(contains("dir: deepglob: is a directory, use glob pattern"));
+    assert!(contains("nothing: *.nothing does not match any files"));
+    assert!(contains("is not a git repository"));
+}
+
+#[test]
+fn reports_missing_repository() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+
+    // Act
+    let messages = check_dotfile(
+        &context,
+        "repository = \"missing\"\n[home]\nvim = \"init.vim\"",
+    );
+
+    // Assert
+    assert!(messages
+        .iter()
+        .any(|m| m.starts_with("repository does not exist")));
+}
+
+#[test]
+fn only_reports_non_utf8_paths_of_items() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+    for name in [&b"config/\xff.yml"[..], b"unrelated-\xff"] {
+        let path = context.home_dir.join(OsStr::from_bytes(name));
+        fs::write(path, "invalid").unwrap();
+    }
+
+    // Act
+    let messages = check(&context, "[home]\nconf = \"config/*\"\nvim = \"init.vim\"");
+
+    // Assert
+    let invalid: Vec<&String> = messages
+        .iter()
+        .filter(|m| m.starts_with("path is not valid UTF-8"))
+        .collect();
+    assert_eq!(1, invalid.len());
+    assert!(invalid[0].ends_with("config/\u{fffd}.yml"));
+}
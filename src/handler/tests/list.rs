@@ -0,0 +1,89 @@
+use crate::data::Item;
+use crate::handler::ListHandler;
+use crate::testing::{create_with_path, TestContext};
+
+fn setup() -> (TestContext, ListHandler) {
+    let items = vec![
+        Item::simple_new("vim", "init.vim"),
+        Item::simple_new("conf", "config/*"),
+        Item::object_new("deep", &["deepglob/**/*"], Some(&["*.out"])),
+    ];
+
+    let context = TestContext::default();
+    context.setup().unwrap();
+
+    let handler = ListHandler::new(context.home_dir.clone(), context.repo_dir.clone(), items);
+    (context, handler)
+}
+
+#[test]
+fn list_items() {
+    // Arrange
+    let (_context, handler) = setup();
+
+    // Act
+    let value = handler.list_json().unwrap();
+
+    // Assert
+    let items = value["items"].as_array().unwrap();
+    let names: Vec<&str> = items.iter().map(|i| i["name"].as_str().unwrap()).collect();
+    assert_eq!(vec!["conf", "deep", "vim"], names);
+    assert_eq!(4, items[1]["matched"]);
+    assert_eq!("home", items[1]["section"]);
+    assert_eq!("*.out", items[1]["ignore"][0]);
+}
+
+#[test]
+fn show_item() {
+    // Arrange
+    let (_context, handler) = setup();
+
+    // Act
+    let value = handler.show_json("deep").unwrap();
+
+    // Assert
+    assert_eq!(4, value["files"].as_array().unwrap().len());
+    assert_eq!(10, value["files"][0]["size"]);
+
+    let skipped = value["skipped"].as_array().unwrap();
+    let rule = |relpath: &str| {
+        skipped
+            .iter()
+            .find(|s| s["relpath"] == relpath)
+            .map(|s| s["rule"]["source"].as_str().unwrap())
+    };
+    assert_eq!(Some("item"), rule("deepglob/test.out"));
+    assert_eq!(Some("builtin"), rule("deepglob/.git/config"));
+}
+
+#[test]
+fn show_files_ignored_by_gitignore() {
+    // Arrange
+    let (context, _) = setup();
+    create_with_path(&context.repo_path(".gitignore"), "*.json\n").unwrap();
+    let items = vec![Item::simple_new("deep", "deepglob/**/*")];
+    let handler = ListHandler::new(context.home_dir.clone(), context.repo_dir.clone(), items);
+
+    // Act
+    let list = handler.list_json().unwrap();
+    let show = handler.show_json("deep").unwrap();
+
+    // Assert
+    assert_eq!(2, list["items"][0]["gitignored"]);
+    let files = show["files"].as_array().unwrap();
+    let gitignore = |relpath: &str| {
+        files
+            .iter()
+            .find(|f| f["relpath"] == relpath)
+            .map(|f| f["gitignore"].clone())
+            .unwrap()
+    };
+    assert_eq!("*.json", gitignore("deepglob/src/package.json"));
+    assert!(gitignore("deepglob/src/file.js").is_null());
+}
+
+#[test]
+fn show_unknown_item() {
+    let (_context, handler) = setup();
+    assert!(handler.show_json("nope").is_err());
+}
@@ -0,0 +1,100 @@
+use crate::cmd::CmdRunner;
+use crate::data::{Item, Status};
+use crate::handler::{History, LogHandler, SyncRun, SyncedFile, Target};
+use crate::testing::{create_with_path, FileSpec, TestContext};
+use std::path::PathBuf;
+
+fn setup() -> (TestContext, LogHandler) {
+    let context = TestContext::new(vec![
+        FileSpec::target("init.vim", Status::Ok),
+        FileSpec::target("tmux.conf", Status::Ok),
+    ]);
+    context.setup().unwrap();
+
+    // One commit per file.
+    let git = CmdRunner::new(context.repo_dir.clone());
+    let user = ["-c", "user.name=dotf", "-c", "user.email=dotf@localhost"];
+    git.run_checked("git", ["init", "-q"]).unwrap();
+    for (file, msg) in [("init.vim", "Add vim"), ("tmux.conf", "Add tmux")] {
+        git.run_checked("git", ["add", file]).unwrap();
+        git.run_checked("git", user.iter().chain(&["commit", "-q", "-m", msg]))
+            .unwrap();
+    }
+
+    let history = History::new(context.temp_dir.join("history.jsonl"));
+    let synced = |item: &str, relpath: &str| SyncedFile {
+        item: item.to_string(),
+        relpath: PathBuf::from(relpath),
+    };
+    history
+        .record(&SyncRun::now(Target::Home, vec![synced("vim", "init.vim")]))
+        .unwrap();
+    history
+        .record(&SyncRun::now(
+            Target::Repo,
+            vec![synced("tmux", "tmux.conf")],
+        ))
+        .unwrap();
+
+    let items = vec![
+        Item::simple_new("vim", "init.vim"),
+        Item::simple_new("tmux", "tmux.conf"),
+        Item::simple_new("empty", "missing.txt"),
+    ];
+    let handler = LogHandler::new(
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        history,
+    );
+    (context, handler)
+}
+
+#[test]
+fn log_of_item() {
+    // Arrange
+    let (_context, handler) = setup();
+
+    // Act
+    let log = handler.git_log(Some("vim")).unwrap();
+    let runs = handler.runs(Some("vim")).unwrap();
+
+    // Assert
+    assert!(log.contains("Add vim"));
+    assert!(!log.contains("Add tmux"));
+    assert_eq!(1, runs.len());
+    assert_eq!(Target::Home, runs[0].direction);
+}
+
+#[test]
+fn log_of_file_and_all_files() {
+    // Arrange
+    let (context, handler) = setup();
+    create_with_path(&context.repo_path("untracked.txt"), "").unwrap();
+
+    // Act
+    let file = handler.git_log(Some("tmux.conf")).unwrap();
+    let all = handler.git_log(None).unwrap();
+
+    // Assert
+    assert!(file.contains("Add tmux") && !file.contains("Add vim"));
+    assert!(all.contains("Add tmux") && all.contains("Add vim"));
+    assert_eq!(2, handler.runs(None).unwrap().len());
+}
+
+#[test]
+fn log_of_unknown_target() {
+    // Arrange
+    let (_context, handler) = setup();
+
+    // Act
+    let err = handler.git_log(Some("nvim")).expect_err("not tracked");
+    let empty = handler.git_log(Some("empty")).unwrap();
+
+    // Assert
+    assert_eq!(
+        "nvim is neither an item nor a tracked file",
+        err.to_string()
+    );
+    assert!(empty.is_empty());
+}
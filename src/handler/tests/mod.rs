@@ -1 +1,6 @@
+mod doctor;
+mod list;
+mod log;
+mod status;
 mod sync;
+mod watch;
@@ -0,0 +1,81 @@
+use crate::data::{Item, Status};
+use crate::handler::{Format, StatusHandler};
+use crate::report::{CaptureReporter, Event};
+use crate::testing::TestContext;
+use std::sync::Arc;
+
+fn setup(brief: bool, format: Format) -> (TestContext, Vec<Event>) {
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let items = vec![
+        Item::simple_new("diff", "diffed.txt"),
+        Item::simple_new("vim", "init.vim"),
+        Item::simple_new("env", "env.toml"),
+        Item::simple_new("missing", "missing.txt"),
+    ];
+    let reporter = Arc::new(CaptureReporter::new());
+    let handler = StatusHandler::new(
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        None,
+    )
+    .with_filesystem(fs)
+    .with_reporter(reporter.clone());
+
+    handler.status(brief, format).unwrap();
+    (context, reporter.events())
+}
+
+#[test]
+fn status_reports_entries_and_summary() {
+    // Act
+    let (_context, events) = setup(false, Format::Text);
+
+    // Assert
+    assert!(events.contains(&Event::Entry {
+        item: "diff".to_string(),
+        relpath: "diffed.txt".to_string(),
+        status: Status::Diff,
+    }));
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, Event::Error { item, .. } if item == "missing")));
+    assert_eq!(
+        Some(&Event::StatusSummary {
+            ok: 1,
+            diff: 1,
+            invalid: 1,
+            missing_home: 1,
+            missing_repo: 0,
+        }),
+        events.last()
+    );
+}
+
+#[test]
+fn status_brief_skips_ok_entries_and_summary() {
+    // Act
+    let (_context, events) = setup(true, Format::Text);
+
+    // Assert
+    assert!(!events.iter().any(|e| matches!(
+        e,
+        Event::ItemStarted { item, .. } if item == "vim"
+    )));
+    assert!(!events
+        .iter()
+        .any(|e| matches!(e, Event::StatusSummary { .. })));
+}
+
+#[test]
+fn status_reports_json_as_document() {
+    // Act
+    let (_context, events) = setup(false, Format::Json);
+
+    // Assert
+    match events.as_slice() {
+        [Event::Document { value }] => assert_eq!("diff", value["items"][0]["name"]),
+        events => panic!("expected a single document, got {:?}", events),
+    }
+}
@@ -1,9 +1,17 @@
-use crate::data::Item;
-use crate::handler::DiffOptions;
-use crate::handler::{SyncHandler, SyncOptions};
-use crate::testing::{PromptMock, TestContext};
+use crate::data::{Hooks, Item, Root};
+use crate::error;
+use crate::files::{Filesystem, MemoryFs};
+use crate::handler::{
+    Action, ActionKind, ConflictPolicy, History, Plan, SyncHandler, SyncOptions, Target,
+};
+use crate::handler::{DiffOptions, Format};
+use crate::report::{CaptureReporter, Event};
+use crate::testing::{create_with_path, PromptMock, TestContext};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-fn setup() -> (TestContext, SyncHandler) {
+fn setup() -> (TestContext, Arc<MemoryFs>, SyncHandler) {
     let items = vec![
         Item::simple_new("diff", "diffed.txt"),
         Item::simple_new("tmux", "tmux.conf"),
@@ -14,10 +22,12 @@ fn setup() -> (TestContext, SyncHandler) {
     ];
 
     let context = TestContext::default();
-    context.setup().unwrap();
+    let fs = Arc::new(context.setup_memory());
 
     let options = SyncOptions {
         interactive: false,
+        non_interactive: false,
+        on_conflict: None,
         dryrun: false,
         confirm: false,
         backup: true,
@@ -25,35 +35,46 @@ fn setup() -> (TestContext, SyncHandler) {
         diff_options: DiffOptions::default(),
         git_commit: None,
         git_push: false,
+        hooks: Hooks::default(),
+        run_hooks: true,
+        capture_hooks: false,
+        editor: None,
+        merge_command: None,
+        dotfile_path: None,
+        format: Format::Text,
+        plan_out: None,
+        fail_fast: false,
+        history: None,
     };
 
     let handler = SyncHandler::new(
-        Box::new(PromptMock {}),
+        Box::new(PromptMock::default()),
         context.home_dir.clone(),
         context.repo_dir.clone(),
         items,
         options,
         None,
-    );
+    )
+    .with_filesystem(fs.clone());
 
-    (context, handler)
+    (context, fs, handler)
 }
 
 #[test]
 fn copy_to_repo() {
     // Arrange
-    let (context, handler) = setup();
+    let (context, fs, handler) = setup();
     let tmuxconf = context.repo_path("tmux.conf");
-    assert!(!tmuxconf.exists());
+    assert!(!fs.exists(&tmuxconf));
     let spaceship = context.repo_path("config/spaceship.yml");
-    assert!(!spaceship.exists());
+    assert!(!fs.exists(&spaceship));
 
     // Act
     let result = handler.copy_to_repo();
 
     // Assert
     assert!(result.is_ok());
-    assert!(spaceship.exists());
+    assert!(fs.exists(&spaceship));
 
     let paths = [
         (true, "tmux.conf"),
@@ -65,24 +86,676 @@ fn copy_to_repo() {
     ];
     for (exists, path) in paths {
         let p = context.repo_path(path);
-        assert_eq!(exists, p.exists());
+        assert_eq!(exists, fs.exists(&p));
     }
 }
 
 #[test]
 fn copy_to_home() {
     // Arrange
-    let (context, handler) = setup();
+    let (context, fs, handler) = setup();
     let envfile = context.home_path("env.toml");
     let diffedbackup = context.home_path(".diffed.txt.backup");
-    assert!(!envfile.exists());
-    assert!(!diffedbackup.exists());
+    assert!(!fs.exists(&envfile));
+    assert!(!fs.exists(&diffedbackup));
 
     // Act
     let result = handler.copy_to_home();
 
     // Assert
     assert!(result.is_ok());
-    assert!(envfile.exists());
-    assert!(diffedbackup.exists());
+    assert!(fs.exists(&envfile));
+    assert!(fs.exists(&diffedbackup));
+}
+
+#[test]
+fn copy_to_home_dryrun() {
+    // Arrange
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let items = vec![
+        Item::simple_new("diff", "diffed.txt"),
+        Item::simple_new("vim", "init.vim"),
+        Item::simple_new("env", "env.toml"),
+    ];
+    let options = SyncOptions {
+        dryrun: true,
+        confirm: false,
+        ..Default::default()
+    };
+    let handler = SyncHandler::new(
+        Box::new(PromptMock::default()),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    )
+    .with_filesystem(fs.clone());
+
+    // Act
+    let summary = handler.copy_to_home().unwrap();
+
+    // Assert
+    assert!(!fs.exists(&context.home_path("env.toml")));
+    assert!(!fs.exists(&context.home_path(".diffed.txt.backup")));
+    assert_eq!(2, summary.written);
+    assert_eq!(1, summary.backed_up);
+    assert_eq!(1, summary.unchanged);
+    assert_eq!(0, summary.skipped);
+}
+
+#[test]
+fn copy_records_history() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+    let items = vec![
+        Item::simple_new("vim", "init.vim"),
+        Item::simple_new("env", "env.toml"),
+    ];
+    let history = History::new(context.temp_dir.join("history.jsonl"));
+    let sync = |dryrun: bool| {
+        let options = SyncOptions {
+            dryrun,
+            confirm: false,
+            history: Some(history.clone()),
+            ..Default::default()
+        };
+        let handler = SyncHandler::new(
+            Box::new(PromptMock::default()),
+            context.home_dir.clone(),
+            context.repo_dir.clone(),
+            items.clone(),
+            options,
+            None,
+        );
+        handler.copy_to_home().unwrap();
+    };
+
+    // Act
+    sync(true);
+    sync(false);
+    // Nothing is written once up to date.
+    sync(false);
+
+    // Assert
+    let runs = history.load().unwrap();
+    assert_eq!(1, runs.len());
+    assert_eq!(Target::Home, runs[0].direction);
+    let files: Vec<(&str, &Path)> = runs[0]
+        .files
+        .iter()
+        .map(|f| (f.item.as_str(), f.relpath.as_path()))
+        .collect();
+    assert_eq!(vec![("env", Path::new("env.toml"))], files);
+}
+
+#[test]
+fn copy_to_repo_with_repo_dir() {
+    // Arrange
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let items = vec![Item::simple_new("tmux", "tmux.conf")
+        .with_repo_dir("shell")
+        .unwrap()];
+    let options = SyncOptions {
+        confirm: false,
+        ..Default::default()
+    };
+    let handler = SyncHandler::new(
+        Box::new(PromptMock::default()),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    )
+    .with_filesystem(fs.clone());
+
+    // Act
+    let result = handler.copy_to_repo();
+
+    // Assert
+    assert!(result.is_ok());
+    assert!(fs.exists(&context.repo_path("shell/tmux.conf")));
+    assert!(!fs.exists(&context.repo_path("tmux.conf")));
+}
+
+#[test]
+fn copy_to_home_skips_readonly_root() {
+    // Arrange
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let root_dir = context.temp_dir.join("etc");
+    fs.add_file(&context.repo_path("etc/hosts"), b"127.0.0.1");
+
+    let mut root = Root::new("etc", root_dir.clone());
+    root.readonly = true;
+    let items = vec![Item::simple_new("etc.hosts", "hosts").with_root(root)];
+    let options = SyncOptions {
+        confirm: false,
+        ..Default::default()
+    };
+    let handler = SyncHandler::new(
+        Box::new(PromptMock::default()),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    )
+    .with_filesystem(fs.clone());
+
+    // Act
+    let result = handler.copy_to_home();
+
+    // Assert
+    assert!(result.is_ok());
+    assert!(!fs.exists(&root_dir.join("hosts")));
+}
+
+// Hooks are run by the shell, so the files are created on disk.
+fn setup_hooks(items: Vec<Item>, hooks: Hooks) -> (TestContext, SyncHandler) {
+    let context = TestContext::default();
+    context.setup().unwrap();
+    let options = SyncOptions {
+        confirm: false,
+        hooks,
+        ..Default::default()
+    };
+    let handler = SyncHandler::new(
+        Box::new(PromptMock::default()),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    );
+    (context, handler)
+}
+
+#[test]
+fn copy_to_home_runs_hooks() {
+    // Arrange
+    let mut env = Item::simple_new("env", "env.toml");
+    env.on_change = vec!["touch env-hook".to_string()];
+    let mut vim = Item::simple_new("vim", "init.vim");
+    vim.on_change = vec!["touch vim-hook".to_string()];
+    let hooks = Hooks {
+        pre_sync: vec!["touch pre-hook".to_string()],
+        post_sync: vec!["touch post-hook".to_string()],
+        fail_on_error: false,
+    };
+    let (context, handler) = setup_hooks(vec![env, vim], hooks);
+
+    // Act
+    let result = handler.copy_to_home();
+
+    // Assert
+    assert!(result.is_ok());
+    assert!(context.home_path("pre-hook").exists());
+    assert!(context.home_path("env-hook").exists());
+    assert!(context.home_path("post-hook").exists());
+    // init.vim is already up to date
+    assert!(!context.home_path("vim-hook").exists());
+}
+
+#[test]
+fn copy_to_home_reports_failing_hook() {
+    // Arrange
+    let hooks = Hooks {
+        post_sync: vec!["false".to_string()],
+        ..Default::default()
+    };
+    let items = vec![Item::simple_new("env", "env.toml")];
+    let (_context, handler) = setup_hooks(items.clone(), hooks.clone());
+    let (_fail_context, fail_handler) = setup_hooks(
+        items,
+        Hooks {
+            fail_on_error: true,
+            ..hooks
+        },
+    );
+
+    // Act & Assert
+    assert!(handler.copy_to_home().is_ok());
+    assert!(fail_handler.copy_to_home().is_err());
+}
+
+fn setup_conflicts(
+    on_conflict: Option<ConflictPolicy>,
+) -> (TestContext, Arc<MemoryFs>, SyncHandler) {
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let items = vec![
+        Item::simple_new("diff", "diffed.txt"),
+        Item::simple_new("tmux", "tmux.conf"),
+    ];
+    let options = SyncOptions {
+        non_interactive: true,
+        on_conflict,
+        ..Default::default()
+    };
+    let handler = SyncHandler::new(
+        Box::new(PromptMock::default()),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    )
+    .with_filesystem(fs.clone());
+    (context, fs, handler)
+}
+
+#[test]
+fn copy_non_interactive_fails_on_conflict() {
+    // Arrange
+    let (context, fs, handler) = setup_conflicts(None);
+
+    // Act
+    let result = handler.copy_to_repo();
+
+    // Assert
+    assert!(result.is_err());
+    assert!(!fs.exists(&context.repo_path("tmux.conf")));
+}
+
+#[test]
+fn copy_non_interactive_with_conflict_policy() {
+    let tests = [
+        (ConflictPolicy::Skip, false),
+        (ConflictPolicy::Ours, true),
+        (ConflictPolicy::Theirs, false),
+    ];
+
+    for (policy, overwritten) in tests {
+        // Arrange
+        let (context, fs, handler) = setup_conflicts(Some(policy));
+        let home = fs.read(&context.home_path("diffed.txt")).unwrap();
+
+        // Act
+        let summary = handler.copy_to_repo().unwrap();
+
+        // Assert
+        let repo = fs.read(&context.repo_path("diffed.txt")).unwrap();
+        assert_eq!(overwritten, home == repo, "{:?}", policy);
+        assert!(fs.exists(&context.repo_path("tmux.conf")));
+        assert_eq!(if overwritten { 2 } else { 1 }, summary.written);
+        assert_eq!(if overwritten { 0 } else { 1 }, summary.skipped);
+    }
+}
+
+#[test]
+fn copy_interactive_review() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+    let dotfile_path = context.temp_dir.join("dotfiles.toml");
+    create_with_path(
+        &dotfile_path,
+        "repository = \"repo\"\n[home]\nconf = \"config/*\"\n",
+    )
+    .unwrap();
+
+    let items = vec![
+        Item::simple_new("conf", "config/*"),
+        Item::simple_new("diff", "diffed.txt"),
+        Item::simple_new("env", "env.toml"),
+        Item::simple_new("tmux", "tmux.conf"),
+    ];
+    let prompt =
+        PromptMock::with_selections(&["Ignore permanently", "Copy to home", "Skip", "Quit"]);
+    let options = SyncOptions {
+        interactive: true,
+        dotfile_path: Some(dotfile_path.clone()),
+        ..Default::default()
+    };
+    let handler = SyncHandler::new(
+        Box::new(prompt),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    );
+
+    // Act
+    let summary = handler.copy_to_repo().unwrap();
+
+    // Assert
+    let home = fs::read_to_string(context.home_path("diffed.txt")).unwrap();
+    let repo = fs::read_to_string(context.repo_path("diffed.txt")).unwrap();
+    assert_eq!(home, repo);
+    assert!(!context.home_path("env.toml").exists());
+    assert!(!context.repo_path("tmux.conf").exists());
+    assert!(!context.repo_path("config/spaceship.yml").exists());
+    assert_eq!(1, summary.written);
+    assert_eq!(2, summary.skipped);
+
+    let dotfile = fs::read_to_string(&dotfile_path).unwrap();
+    assert!(dotfile.contains(r#"ignore = ["config/spaceship.yml"]"#));
+}
+
+// Plans are saved to and loaded from disk.
+fn plan_handler(context: &TestContext, plan_out: Option<PathBuf>) -> SyncHandler {
+    let items = vec![
+        Item::simple_new("diff", "diffed.txt"),
+        Item::simple_new("env", "env.toml"),
+    ];
+    let options = SyncOptions {
+        confirm: false,
+        plan_out,
+        ..Default::default()
+    };
+    SyncHandler::new(
+        Box::new(PromptMock::default()),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    )
+}
+
+#[test]
+fn apply_saved_plan() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+    let path = context.temp_dir.join("plan.json");
+    plan_handler(&context, Some(path.clone()))
+        .copy_to_home()
+        .unwrap();
+    assert!(!context.home_path("env.toml").exists());
+    let plan = Plan::load(&path).unwrap();
+
+    // Act
+    let summary = plan_handler(&context, None).apply(&plan).unwrap();
+
+    // Assert
+    assert_eq!(2, summary.written);
+    assert_eq!(1, summary.backed_up);
+    assert!(context.home_path("env.toml").exists());
+    assert!(context.home_path(".diffed.txt.backup").exists());
+    let home = fs::read_to_string(context.home_path("diffed.txt")).unwrap();
+    let repo = fs::read_to_string(context.repo_path("diffed.txt")).unwrap();
+    assert_eq!(home, repo);
+}
+
+#[test]
+fn apply_refuses_tampered_plans() {
+    // Arrange
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let handler = plan_handler(&context, None).with_filesystem(fs.clone());
+    let plan = handler.plan(Target::Home).unwrap();
+    let env = plan
+        .actions
+        .iter()
+        .position(|a| a.item == "env" && a.kind == ActionKind::Create)
+        .unwrap();
+    let tampers: [fn(&mut Action, &TestContext); 4] = [
+        |a, cx| a.dst = cx.home_path(".ssh/authorized_keys"),
+        |a, cx| a.src = Some(cx.home_path("secret.txt")),
+        |a, _| a.sudo = true,
+        |a, _| a.relpath = PathBuf::from("other.toml"),
+    ];
+
+    for tamper in tampers {
+        let mut tampered = plan.clone();
+        tamper(&mut tampered.actions[env], &context);
+
+        // Act
+        let result = handler.apply(&tampered);
+
+        // Assert
+        let err = result.expect_err("apply should fail");
+        assert!(err.to_string().contains("does not match the dotfile"));
+        assert!(!fs.exists(&context.home_path("env.toml")));
+    }
+}
+
+#[test]
+fn apply_refuses_writing_readonly_roots() {
+    // Arrange
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let root_dir = context.temp_dir.join("etc");
+    fs.add_file(&context.repo_path("etc/hosts"), b"127.0.0.1");
+    let handler = |readonly: bool| {
+        let mut root = Root::new("etc", root_dir.clone());
+        root.readonly = readonly;
+        let items = vec![Item::simple_new("etc.hosts", "hosts").with_root(root)];
+        let options = SyncOptions {
+            confirm: false,
+            ..Default::default()
+        };
+        SyncHandler::new(
+            Box::new(PromptMock::default()),
+            context.home_dir.clone(),
+            context.repo_dir.clone(),
+            items,
+            options,
+            None,
+        )
+        .with_filesystem(fs.clone())
+    };
+    let plan = handler(false).plan(Target::Home).unwrap();
+
+    // Act
+    let result = handler(true).apply(&plan);
+
+    // Assert
+    let err = result.expect_err("apply should fail");
+    assert!(err.to_string().contains("read-only"));
+    assert!(!fs.exists(&root_dir.join("hosts")));
+}
+
+#[test]
+fn save_plan_reports_path() {
+    let path = PathBuf::from("missing/dir/plan.json");
+    let err = Plan::default().save(&path).expect_err("save should fail");
+    let err = error::find(&err).expect("dotf error");
+    assert_eq!(5, err.exit_code());
+    assert!(err.to_string().starts_with("missing/dir/plan.json: "));
+}
+
+#[test]
+fn digests_are_only_recorded_for_saved_plans() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+    let path = context.temp_dir.join("plan.json");
+    plan_handler(&context, Some(path.clone()))
+        .copy_to_home()
+        .unwrap();
+
+    // Act
+    let plan = plan_handler(&context, None).plan(Target::Home).unwrap();
+    let saved = Plan::load(&path).unwrap();
+
+    // Assert
+    assert!(plan.actions.iter().all(|a| a.src_digest.is_none()));
+    assert!(saved.actions.iter().any(|a| a.src_digest.is_some()));
+}
+
+#[test]
+fn apply_refuses_changed_files() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+    let path = context.temp_dir.join("plan.json");
+    plan_handler(&context, Some(path.clone()))
+        .copy_to_home()
+        .unwrap();
+    let plan = Plan::load(&path).unwrap();
+    fs::write(context.repo_path("env.toml"), "changed = true").unwrap();
+
+    // Act
+    let result = plan_handler(&context, None).apply(&plan);
+
+    // Assert
+    let err = result.expect_err("apply should fail");
+    assert!(err.to_string().contains("env.toml (env)"));
+    assert!(!context.home_path("env.toml").exists());
+    assert!(!context.home_path(".diffed.txt.backup").exists());
+}
+
+#[test]
+fn copy_reports_errors_and_continues() {
+    for fail_fast in [false, true] {
+        // Arrange
+        let context = TestContext::default();
+        let fs = Arc::new(context.setup_memory());
+        let items = vec![
+            Item::simple_new("missing", "missing.txt"),
+            Item::simple_new("tmux", "tmux.conf"),
+        ];
+        let options = SyncOptions {
+            confirm: false,
+            fail_fast,
+            ..Default::default()
+        };
+        let handler = SyncHandler::new(
+            Box::new(PromptMock::default()),
+            context.home_dir.clone(),
+            context.repo_dir.clone(),
+            items,
+            options,
+            None,
+        )
+        .with_filesystem(fs.clone());
+
+        // Act
+        let result = handler.copy_to_repo();
+
+        // Assert
+        assert_eq!(!fail_fast, fs.exists(&context.repo_path("tmux.conf")));
+        if fail_fast {
+            assert!(result.is_err());
+        } else {
+            let summary = result.unwrap();
+            assert_eq!(1, summary.written);
+            assert_eq!(1, summary.errors);
+        }
+    }
+}
+
+#[test]
+fn copy_to_home_in_memory() {
+    // Arrange
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let items = vec![
+        Item::simple_new("diff", "diffed.txt"),
+        Item::simple_new("env", "env.toml"),
+        Item::simple_new("conf", "config/*"),
+    ];
+    let options = SyncOptions {
+        confirm: false,
+        ..Default::default()
+    };
+    let handler = SyncHandler::new(
+        Box::new(PromptMock::default()),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    )
+    .with_filesystem(fs.clone());
+
+    // Act
+    let summary = handler.copy_to_home().unwrap();
+
+    // Assert
+    assert!(!context.temp_dir.exists());
+    assert_eq!(2, summary.written);
+    assert_eq!(1, summary.backed_up);
+    assert!(fs.exists(&context.home_path("env.toml")));
+    assert!(fs.exists(&context.home_path(".diffed.txt.backup")));
+    assert_eq!(
+        fs.read(&context.repo_path("diffed.txt")).unwrap(),
+        fs.read(&context.home_path("diffed.txt")).unwrap()
+    );
+}
+
+#[test]
+fn copy_to_home_reports_events() {
+    // Arrange
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let items = vec![
+        Item::simple_new("diff", "diffed.txt"),
+        Item::simple_new("missing", "missing.txt"),
+    ];
+    let options = SyncOptions {
+        confirm: false,
+        ..Default::default()
+    };
+    let reporter = Arc::new(CaptureReporter::new());
+    let handler = SyncHandler::new(
+        Box::new(PromptMock::default()),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    )
+    .with_filesystem(fs)
+    .with_reporter(reporter.clone());
+
+    // Act
+    let summary = handler.copy_to_home().unwrap();
+
+    // Assert
+    let events = reporter.events();
+    assert_eq!(
+        vec![ActionKind::Backup, ActionKind::Overwrite],
+        reporter.actions()
+    );
+    assert!(events.contains(&Event::ItemStarted {
+        item: "missing".to_string(),
+        count: 1,
+    }));
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, Event::Error { item, .. } if item == "missing")));
+    assert_eq!(Some(&Event::SyncSummary { summary }), events.last());
+}
+
+#[test]
+fn copy_reports_json_as_document() {
+    // Arrange
+    let context = TestContext::default();
+    let fs = Arc::new(context.setup_memory());
+    let items = vec![Item::simple_new("diff", "diffed.txt")];
+    let options = SyncOptions {
+        confirm: false,
+        dryrun: true,
+        format: Format::Json,
+        ..Default::default()
+    };
+    let reporter = Arc::new(CaptureReporter::new());
+    let handler = SyncHandler::new(
+        Box::new(PromptMock::default()),
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        options,
+        None,
+    )
+    .with_filesystem(fs)
+    .with_reporter(reporter.clone());
+
+    // Act
+    handler.copy_to_home().unwrap();
+
+    // Assert
+    match reporter.events().as_slice() {
+        [Event::Document { value }] => assert_eq!(1, value["summary"]["written"]),
+        events => panic!("expected a single document, got {:?}", events),
+    }
 }
@@ -0,0 +1,40 @@
+use crate::data::Item;
+use crate::handler::{WatchHandler, WatchOptions};
+use crate::testing::TestContext;
+use std::collections::HashSet;
+
+#[test]
+fn changed_entries_only_includes_changed_paths() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+    let items = vec![
+        Item::simple_new("diff", "diffed.txt"),
+        Item::simple_new("tmux", "tmux.conf"),
+        Item::simple_new("vim", "init.vim"),
+        Item::simple_new("env", "env.toml"),
+    ];
+    let handler = WatchHandler::new(
+        context.home_dir.clone(),
+        context.repo_dir.clone(),
+        items,
+        WatchOptions::default(),
+    );
+
+    let paths: HashSet<_> = [
+        context.home_path("diffed.txt"),
+        // Up to date
+        context.home_path("init.vim"),
+        // Only exists in the repository
+        context.home_path("env.toml"),
+    ]
+    .into_iter()
+    .collect();
+
+    // Act
+    let entries = handler.changed_entries(&paths).unwrap();
+
+    // Assert
+    assert_eq!(1, entries.len());
+    assert_eq!("diffed.txt", entries[0].get_relpath());
+}
@@ -1,8 +1,13 @@
+use crate::data::Item;
+use crate::error::DotfError;
 use anyhow::{bail, Result};
 use glob::Pattern as GlobPattern;
 use regex::Regex;
+use std::path::Path;
 use std::process::Command;
+use std::str::FromStr;
 
+#[derive(Clone)]
 pub enum Pattern {
     Glob(GlobPattern),
     Regex(Regex),
@@ -17,6 +22,7 @@ impl Pattern {
     }
 }
 
+#[derive(Clone)]
 pub struct Only {
     pub patterns: Vec<Pattern>,
 }
@@ -25,7 +31,10 @@ impl Only {
     pub fn from_glob(patterns: &Vec<String>) -> Result<Self> {
         let mut ps = Vec::new();
         for p in patterns {
-            let g = GlobPattern::new(p)?;
+            let g = GlobPattern::new(p).map_err(|err| DotfError::Glob {
+                pattern: p.to_string(),
+                cause: err.to_string(),
+            })?;
             ps.push(Pattern::Glob(g));
         }
         Ok(Self { patterns: ps })
@@ -41,6 +50,105 @@ impl Only {
     }
 }
 
+/// Selects items by name or group. All items are selected if neither
+/// names nor groups are given.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    pub items: Vec<String>,
+    pub groups: Vec<String>,
+    // Names of items or groups to leave out.
+    pub exclude: Vec<String>,
+}
+
+impl Selection {
+    pub fn includes(&self, item: &Item) -> bool {
+        let excluded = self
+            .exclude
+            .iter()
+            .any(|name| *name == item.name || item.groups.contains(name));
+        if excluded {
+            return false;
+        }
+
+        if self.items.is_empty() && self.groups.is_empty() {
+            return true;
+        }
+        self.items.contains(&item.name) || item.groups.iter().any(|g| self.groups.contains(g))
+    }
+
+    /// Fails if any of the names or groups does not exist in items.
+    pub fn validate(&self, items: &[Item]) -> Result<()> {
+        for name in &self.items {
+            if !items.iter().any(|item| item.name == *name) {
+                bail!("unknown item: {}", name);
+            }
+        }
+
+        for group in &self.groups {
+            if !items.iter().any(|item| item.groups.contains(group)) {
+                bail!("unknown group: {}", group);
+            }
+        }
+
+        for name in &self.exclude {
+            if !items
+                .iter()
+                .any(|item| item.name == *name || item.groups.contains(name))
+            {
+                bail!("unknown item or group: {}", name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How to handle files that differ between home and repository when
+/// syncing. Ours is the home directory and theirs is the repository.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConflictPolicy {
+    Skip,
+    Ours,
+    Theirs,
+    Fail,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(ConflictPolicy::Skip),
+            "ours" => Ok(ConflictPolicy::Ours),
+            "theirs" => Ok(ConflictPolicy::Theirs),
+            "fail" => Ok(ConflictPolicy::Fail),
+            _ => bail!("invalid conflict policy: {}", s),
+        }
+    }
+}
+
+/// The output format of commands that supports machine readable output.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    // One JSON object per event.
+    JsonLines,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::JsonLines),
+            _ => bail!("invalid format: {}", s),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DiffOptions {
     cmd: Vec<String>,
@@ -51,8 +159,13 @@ impl DiffOptions {
         Self { cmd }
     }
 
-    pub fn to_cmd(&self, a: &str, b: &str) -> Result<Command> {
-        let root = match self.cmd.get(0) {
+    /// The program used for diffing, e.g. diff.
+    pub fn program(&self) -> Option<&str> {
+        self.cmd.first().map(String::as_str)
+    }
+
+    pub fn to_cmd(&self, a: &Path, b: &Path) -> Result<Command> {
+        let root = match self.cmd.first() {
             Some(r) => r,
             None => bail!("empty diff command"),
         };
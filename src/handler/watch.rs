@@ -0,0 +1,259 @@
+use super::sync::{SyncHandler, SyncOptions};
+use super::types::Only;
+use crate::cmd::CmdRunner;
+use crate::data::{Entry, Item, Status};
+use crate::files::{Filesystem, RealFs};
+use crate::index::Indexer;
+use crate::prompt::StdinPrompt;
+use crate::style::{paint, Icon};
+use anyhow::Result;
+use crossterm::style::Stylize;
+use glob::Pattern as GlobPattern;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct WatchOptions {
+    // Copy changed files to the repository, else only print them.
+    pub copy: bool,
+    // Wait this long for more changes before handling them.
+    pub debounce: Duration,
+    // Create a git commit of copied files with this interval.
+    pub commit_interval: Option<Duration>,
+    pub commit_message: String,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            copy: false,
+            debounce: Duration::from_millis(500),
+            commit_interval: None,
+            commit_message: String::from("Update dotfiles"),
+        }
+    }
+}
+
+pub struct WatchHandler {
+    home: PathBuf,
+    repository: PathBuf,
+    indexer: Indexer,
+    items: Vec<Item>,
+    options: WatchOptions,
+    runner: CmdRunner,
+}
+
+// Public methods.
+impl WatchHandler {
+    pub fn new(
+        home: PathBuf,
+        repository: PathBuf,
+        items: Vec<Item>,
+        options: WatchOptions,
+    ) -> Self {
+        let runner = CmdRunner::new(repository.clone());
+        let indexer = Indexer::new(home.clone(), repository.clone(), None);
+        Self {
+            home,
+            repository,
+            indexer,
+            items,
+            options,
+            runner,
+        }
+    }
+
+    /// Watches the files in home until interrupted.
+    pub fn watch(&self) -> Result<()> {
+        let (tx, rx) = channel();
+        let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())?;
+        let mut watched: HashMap<PathBuf, bool> = HashMap::new();
+        self.arm(&mut watcher, &mut watched)?;
+
+        println!("Watching for changes, press Ctrl-C to stop.");
+
+        let tick = Duration::from_secs(1);
+        let mut pending: HashSet<PathBuf> = HashSet::new();
+        let mut last_change = Instant::now();
+        let mut last_commit = Instant::now();
+        // Repository files written since the last commit.
+        let mut uncommitted: HashSet<PathBuf> = HashSet::new();
+
+        loop {
+            match rx.recv_timeout(tick.min(self.options.debounce)) {
+                Ok(Ok(event)) => {
+                    if matches!(event.kind, EventKind::Create(_)) {
+                        // Files may have been created before the new
+                        // directories were watched.
+                        for dir in self.arm(&mut watcher, &mut watched)? {
+                            pending.extend(RealFs.walk(&dir).unwrap_or_default());
+                        }
+                    }
+                    if is_change(&event) {
+                        pending.extend(event.paths);
+                        last_change = Instant::now();
+                    }
+                }
+                Ok(Err(err)) => log::warn!("Watch error: {}", err),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if !pending.is_empty() && last_change.elapsed() >= self.options.debounce {
+                let paths = std::mem::take(&mut pending);
+                match self.handle(&paths) {
+                    Ok(written) => uncommitted.extend(written),
+                    Err(err) => eprintln!("error: {}", err),
+                }
+            }
+
+            if let Some(interval) = self.options.commit_interval {
+                if !uncommitted.is_empty() && last_commit.elapsed() >= interval {
+                    match self.commit(&uncommitted) {
+                        Ok(()) => uncommitted.clear(),
+                        Err(err) => eprintln!("error: failed to commit: {}", err),
+                    }
+                    last_commit = Instant::now();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the entries that differ from the repository and that has
+    /// any of the paths as home path.
+    pub fn changed_entries(&self, paths: &HashSet<PathBuf>) -> Result<Vec<Entry>> {
+        let (indexed, _) = self.indexer.index_deduped(&self.items)?;
+        let entries = indexed
+            .into_iter()
+            .flat_map(|(_, entries)| entries)
+            .filter(|entry| match entry {
+                Entry::Ok {
+                    status, home_path, ..
+                } => {
+                    matches!(status, Status::Diff | Status::MissingRepo)
+                        && paths.contains(home_path)
+                }
+                Entry::Err(_) => false,
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+
+// Private methods.
+impl WatchHandler {
+    // Watches the directories of the items that are not watched yet, and
+    // returns them. Directories are watched through their nearest existing
+    // ancestor until they are created.
+    fn arm(
+        &self,
+        watcher: &mut RecommendedWatcher,
+        watched: &mut HashMap<PathBuf, bool>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut armed = Vec::new();
+        for (path, recursive) in self.indexer.watch_paths(&self.items) {
+            let was_recursive = watched.get(&path).copied();
+            if was_recursive.is_some_and(|r| r || !recursive) {
+                continue;
+            }
+
+            let mode = if recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            log::info!("Watching {:?} ({:?})", path, mode);
+            if was_recursive.is_some() {
+                watcher.unwatch(&path)?;
+            }
+            watcher.watch(&path, mode)?;
+            watched.insert(path.clone(), recursive);
+            armed.push(path);
+        }
+        Ok(armed)
+    }
+
+    // Handles a batch of changed paths. Returns the repository files that
+    // were written.
+    fn handle(&self, paths: &HashSet<PathBuf>) -> Result<Vec<PathBuf>> {
+        let entries = self.changed_entries(paths)?;
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if !self.options.copy {
+            for entry in &entries {
+                println!(
+                    "  {} {} changed",
+                    Status::Diff,
+                    entry.get_relpath().display()
+                );
+            }
+            return Ok(Vec::new());
+        }
+
+        let patterns: Vec<String> = entries
+            .iter()
+            .map(|entry| GlobPattern::escape(&entry.get_relpath().to_string_lossy()))
+            .collect();
+        let only = Only::from_glob(&patterns)?;
+
+        let options = SyncOptions {
+            confirm: false,
+            run_hooks: false,
+            ..Default::default()
+        };
+        let handler = SyncHandler::new(
+            Box::new(StdinPrompt {}),
+            self.home.clone(),
+            self.repository.clone(),
+            self.items.clone(),
+            options,
+            Some(only),
+        );
+        handler.copy_to_repo()?;
+
+        let written = entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Entry::Ok { repo_path, .. } => Some(repo_path),
+                Entry::Err(_) => None,
+            })
+            .collect();
+        Ok(written)
+    }
+
+    // Commits only the files, leaving other changes in the repository,
+    // staged or not, as they are.
+    fn commit(&self, paths: &HashSet<PathBuf>) -> Result<()> {
+        let msg = &self.options.commit_message;
+        log::info!("Creating git commit with message: {msg}");
+        let mut paths: Vec<&PathBuf> = paths.iter().collect();
+        paths.sort();
+
+        let mut add: Vec<OsString> = vec!["add".into(), "--".into()];
+        add.extend(paths.iter().map(|p| p.into()));
+        self.runner.run_checked("git", add)?;
+
+        let mut commit: Vec<OsString> = vec!["commit".into(), "-m".into(), msg.into(), "--".into()];
+        commit.extend(paths.iter().map(|p| p.into()));
+        self.runner.run_checked("git", commit)?;
+        println!("  {} committed changes", paint(Icon::Done.as_str().green()));
+        Ok(())
+    }
+}
+
+// Editors may save files by writing to a temporary file and renaming it,
+// so renames are treated as changes as well.
+fn is_change(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any
+    )
+}
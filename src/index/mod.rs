@@ -1,38 +1,46 @@
 use crate::data::{Entry, Item, Status};
-use crate::handler::Only;
+use crate::files::{self, Filesystem, GlobError, RealFs};
+use crate::handler::{Only, Selection};
 use crate::path::try_strip_prefix;
-use crate::{files, path_str};
-use anyhow::Result;
+use anyhow::{bail, Result};
 use glob::Pattern as GlobPattern;
+use ignore::gitignore::{Gitignore as GitignoreMatcher, GitignoreBuilder};
+use ignore::Match;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[cfg(test)]
 mod tests;
 
+/// Entries grouped by item name.
+pub type Indexed = Vec<(String, Vec<Entry>)>;
+
+#[derive(Clone)]
 pub struct Indexer {
     // The path to the users home directory.
     home: PathBuf,
-    home_str: String,
     // The path to the repository to sync files to.
     repo: PathBuf,
-    repo_str: String,
     ignore_patterns: Vec<GlobPattern>,
     only: Option<Only>,
+    selection: Selection,
+    fs: Arc<dyn Filesystem>,
+    // Fail on the first file that cannot be indexed, instead of returning
+    // it as an error entry.
+    fail_fast: bool,
+    // Compare the content of files found in both home and repository.
+    compare: bool,
 }
 
 impl Indexer {
     pub fn new(home: PathBuf, repo: PathBuf, only: Option<Only>) -> Self {
-        let home_str = path_str!(home);
-        let repo_str = path_str!(repo);
+        let fs: Arc<dyn Filesystem> = Arc::new(RealFs);
 
         Self {
             home,
-            home_str,
             repo,
-            repo_str,
-            // TODO: read these from files
-            //   - .gitignore
-            //   - .ignore
             ignore_patterns: vec![
                 GlobPattern::new("*/.git/*").unwrap(),
                 GlobPattern::new("*/node_modules/*").unwrap(),
@@ -44,79 +52,236 @@ impl Indexer {
                 GlobPattern::new("*/.venv/*").unwrap(),
             ],
             only,
+            selection: Selection::default(),
+            fail_fast: false,
+            compare: true,
+            fs,
         }
     }
 
-    pub fn index(&self, items: &[Item]) -> Result<Vec<(String, Vec<Entry>)>> {
-        let mut entries: Vec<(String, Vec<Entry>)> = Vec::new();
+    /// Use the filesystem instead of the real one.
+    pub fn with_filesystem(mut self, fs: Arc<dyn Filesystem>) -> Self {
+        self.fs = fs;
+        self
+    }
+
+    /// Only include the items in the selection. Files are still deduplicated
+    /// against all items, so that a file is synced by the same item
+    /// regardless of the selection.
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    pub fn index(&self, items: &[Item]) -> Result<Indexed> {
+        self.selection.validate(items)?;
+        let indexed = self.index_all(items)?;
+        Ok(self.select(indexed, items))
+    }
 
+    /// Returns the relative paths of the files of the items, without reading
+    /// them, e.g. for completion. Files that cannot be indexed are left out.
+    pub fn relpaths(&self, items: &[Item]) -> Vec<PathBuf> {
+        let indexer = self.paths_only();
+        let mut relpaths = Vec::new();
         for item in items {
-            let t = self.process_item(item)?;
-
-            // TODO: refactor
-            let mut filtered = Vec::new();
-            if let Some(only) = &self.only {
-                for entry in t {
-                    if let Entry::Ok { relpath, .. } = &entry {
-                        for pattern in &only.patterns {
-                            if pattern.matches(relpath) {
-                                filtered.push(entry);
-                                break;
-                            }
-                        }
-                    }
-                }
-            } else {
-                filtered = t;
+            if let Ok((entries, _)) = indexer.index_item(item) {
+                let ok = entries.iter().filter(|entry| entry.is_ok());
+                relpaths.extend(ok.map(|entry| entry.get_relpath().to_path_buf()));
             }
+        }
+        relpaths
+    }
+
+    // Items that are not selected are only indexed to deduplicate files and
+    // detect collisions, so their files are not read and their errors are
+    // not fatal.
+    fn index_all(&self, items: &[Item]) -> Result<Indexed> {
+        let mut entries: Indexed = Vec::new();
+        let unselected = self.paths_only();
 
+        for item in items {
+            let (filtered, _) = match self.selection.includes(item) {
+                true => self.index_item(item)?,
+                false => unselected.index_item(item)?,
+            };
             entries.push((item.name.clone(), filtered));
         }
 
         entries.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+
+        // Files of different items may collide as well, e.g. with roots.
+        let entries = reject_collisions(entries, &self.repo);
+        for item in items.iter().filter(|item| self.selection.includes(item)) {
+            if let Some((name, entries)) = entries.iter().find(|(name, _)| *name == item.name) {
+                self.check_fail_fast(name, entries)?;
+            }
+        }
         Ok(entries)
     }
 
-    fn process_item(&self, item: &Item) -> Result<Vec<Entry>> {
+    // An indexer that only finds the files of items, without comparing them.
+    fn paths_only(&self) -> Self {
+        Self {
+            compare: false,
+            fail_fast: false,
+            ..self.clone()
+        }
+    }
+
+    /// Indexes a single item. The files that matched a pattern of the item,
+    /// but were excluded by an ignore rule, are returned as well.
+    pub fn index_item(&self, item: &Item) -> Result<(Vec<Entry>, Vec<Skipped>)> {
+        let mut skipped = Vec::new();
+        let t = self.process_item(item, &mut skipped)?;
+        let indexed = reject_collisions(vec![(item.name.clone(), t)], &self.repo);
+        let t: Vec<Entry> = indexed
+            .into_iter()
+            .flat_map(|(_, entries)| entries)
+            .collect();
+        self.check_fail_fast(&item.name, &t)?;
+
+        // TODO: refactor
+        let mut filtered = Vec::new();
+        if let Some(only) = &self.only {
+            for entry in t {
+                match &entry {
+                    Entry::Ok { relpath, .. } => {
+                        let relpath = relpath.to_string_lossy();
+                        if only.patterns.iter().any(|p| p.matches(&relpath)) {
+                            filtered.push(entry);
+                        }
+                    }
+                    // Errors are not tied to a path, so keep them visible.
+                    Entry::Err(_) => filtered.push(entry),
+                }
+            }
+        } else {
+            filtered = t;
+        }
+
+        Ok((filtered, skipped))
+    }
+
+    // Fails with the first error of the item if fail_fast is set.
+    fn check_fail_fast(&self, name: &str, entries: &[Entry]) -> Result<()> {
+        if self.fail_fast {
+            if let Some(Entry::Err(err)) = entries.iter().find(|entry| !entry.is_ok()) {
+                bail!("{}: {}", name, err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the directories in home that needs to be watched in order to
+    /// detect changes to the files of the items, and if they should be
+    /// watched recursively. Directories that do not exist are replaced by
+    /// their nearest existing ancestor.
+    pub fn watch_paths(&self, items: &[Item]) -> Vec<(PathBuf, bool)> {
+        let mut paths: Vec<(PathBuf, bool)> = Vec::new();
+        for item in items {
+            for path in &item.files {
+                let mapping = self.mapping(item, path);
+                let base = static_base(path);
+                let dir = mapping.home_path(&base);
+
+                // Only the last component may be a glob for a non-recursive watch,
+                // e.g. notes/*.md. Plain files are watched through their directory
+                // in order to detect editors saving by renaming files.
+                let depth = path.components().count() - base.components().count();
+                let recursive = is_glob_path(path) && depth > 1;
+
+                // Until the directory is created, its nearest existing
+                // ancestor is watched to detect when it is.
+                let (dir, recursive) = match self.fs.is_dir(&dir) {
+                    true => (dir, recursive),
+                    false => match dir.ancestors().find(|p| self.fs.is_dir(p)) {
+                        Some(ancestor) => (ancestor.to_path_buf(), false),
+                        None => continue,
+                    },
+                };
+
+                match paths.iter_mut().find(|(p, _)| *p == dir) {
+                    Some((_, r)) => *r = *r || recursive,
+                    None => paths.push((dir, recursive)),
+                }
+            }
+        }
+        paths
+    }
+
+    /// Like `index`, but files tracked by multiple items are only included
+    /// in one of them. The overlapping files are returned as well.
+    pub fn index_deduped(&self, items: &[Item]) -> Result<(Indexed, Vec<Overlap>)> {
+        self.selection.validate(items)?;
+        let indexed = self.index_all(items)?;
+        let overlaps = find_overlaps(&indexed, items);
+        let indexed = self.select(dedup(indexed, &overlaps), items);
+
+        let overlaps = overlaps
+            .into_iter()
+            .filter(|o| indexed.iter().any(|(name, _)| o.items.contains(name)))
+            .collect();
+        Ok((indexed, overlaps))
+    }
+
+    fn select(&self, indexed: Indexed, items: &[Item]) -> Indexed {
+        indexed
+            .into_iter()
+            .filter(|(name, _)| {
+                items
+                    .iter()
+                    .find(|item| item.name == *name)
+                    .is_some_and(|item| self.selection.includes(item))
+            })
+            .collect()
+    }
+
+    fn process_item(&self, item: &Item, skipped: &mut Vec<Skipped>) -> Result<Vec<Entry>> {
         log::debug!("Processing item: {:?}", item);
 
-        let ps = match item.ignore_patterns()? {
-            None => vec![],
-            Some(ps) => ps,
+        let ps = match item.ignore_patterns() {
+            Ok(ps) => ps.unwrap_or_default(),
+            Err(err) => {
+                let entry = Entry::new_err("ignore", format!("invalid pattern: {}", err));
+                return Ok(vec![entry]);
+            }
         };
 
         let mut entries = Vec::new();
-        for path in &item.files {
-            let filepath = path_str!(path);
-            let home_path = self.home.join(&filepath);
-            let repo_path = self.repo.join(&filepath);
+        for filepath in &item.files {
+            let mapping = self.mapping(item, filepath);
+            let home_path = mapping.home_path(filepath);
+            let repo_path = mapping.repo_path(filepath);
 
-            if is_glob(&filepath) {
-                let es = self.process_glob(&filepath, &ps)?;
+            if is_glob_path(filepath) {
+                let es = self.process_glob(filepath, &mapping, &ps, skipped)?;
                 entries.extend(es);
                 continue;
             }
 
-            if !(home_path.exists() || repo_path.exists()) {
-                let entry =
-                    Entry::new_err("does not exists in either home or repository".to_string());
-                return Ok(vec![entry]);
+            if !(self.fs.exists(&home_path) || self.fs.exists(&repo_path)) {
+                let cause = "does not exists in either home or repository".to_string();
+                entries.push(Entry::new_err(filepath, cause));
+                continue;
             }
 
-            if home_path.is_dir() || repo_path.is_dir() {
-                let fixed = match filepath.strip_suffix('/') {
-                    Some(s) => format!("{}/*", s),
-                    None => format!("{}/*", filepath),
-                };
-
-                let entry = Entry::new_err(format!(
-                    "use glob pattern (fix: change {} to {})",
-                    filepath, fixed,
-                ));
-                return Ok(vec![entry]);
+            if self.fs.is_dir(&home_path) || self.fs.is_dir(&repo_path) {
+                let cause = format!(
+                    "is a directory, use glob pattern (fix: change {} to {})",
+                    filepath.display(),
+                    filepath.join("*").display(),
+                );
+                entries.push(Entry::new_err(filepath, cause));
+                continue;
             }
 
-            if let Some(entry) = self.make_entry(&filepath, home_path, repo_path)? {
+            if let Some(entry) = self.make_entry(filepath, home_path, repo_path) {
                 entries.push(entry);
             }
         }
@@ -126,77 +291,98 @@ impl Indexer {
 
     fn process_glob(
         &self,
-        globpattern: &str,
+        globpattern: &Path,
+        mapping: &Mapping,
         ignore_patterns: &[GlobPattern],
+        skipped: &mut Vec<Skipped>,
     ) -> Result<Vec<Entry>> {
         let mut entries = Vec::new();
 
-        let home_glob_path = self.home.join(globpattern);
-        let repo_glob_path = self.repo.join(globpattern);
-
-        let home_str = path_str!(home_glob_path);
-        let repo_str = path_str!(repo_glob_path);
+        // Glob patterns are strings, so the directories they are joined onto
+        // must be valid UTF-8. The files matched by them need not be.
+        let home_glob_path = mapping.home_path(globpattern);
+        let repo_glob_path = mapping.repo_path(globpattern);
+        let (home_str, repo_str) = match (home_glob_path.to_str(), repo_glob_path.to_str()) {
+            (Some(h), Some(r)) => (h, r),
+            _ => {
+                let cause = "directory of the pattern is not valid UTF-8".to_string();
+                return Ok(vec![Entry::new_err(globpattern, cause)]);
+            }
+        };
 
-        let home_glob = glob::glob(&home_str);
-        let repo_glob = glob::glob(&repo_str);
+        let home_glob = self.fs.glob(home_str);
+        let repo_glob = self.fs.glob(repo_str);
 
         if home_glob.is_err() || repo_glob.is_err() {
-            let entry = Entry::new_err(format!("invalid glob pattern: {}", globpattern));
+            let entry = Entry::new_err(globpattern, "invalid glob pattern".to_string());
             return Ok(vec![entry]);
         }
 
-        let mut home_files: Vec<String> = Vec::new();
-        for p in home_glob.unwrap().flatten() {
-            if p.is_file() {
-                let s = try_strip_prefix(&self.home_str, &path_str!(&p));
-                if should_ignore(&s, &self.ignore_patterns) {
+        // Paths that could not be read, e.g. due to permissions, are
+        // reported relative to the glob they failed in, and the other files
+        // of the pattern are still included.
+        let mut glob_error = |relpath: PathBuf, err: GlobError| {
+            entries.push(Entry::new_err(relpath, err.cause));
+        };
+
+        let mut home_files: Vec<PathBuf> = Vec::new();
+        for p in home_glob.unwrap() {
+            let p = match p {
+                Ok(p) => p,
+                Err(err) => {
+                    glob_error(mapping.home_relpath(&err.path), err);
                     continue;
                 }
-
-                if !ignore_patterns.is_empty() && should_ignore(&s, ignore_patterns) {
+            };
+            if self.fs.is_file(&p) {
+                let s = mapping.home_relpath(&p);
+                if self.skip(&s, mapping, ignore_patterns, skipped) {
                     continue;
                 }
 
-                log::debug!("Adding home file: {s}");
-                home_files.push(s.to_string());
+                log::debug!("Adding home file: {}", s.display());
+                home_files.push(s);
             }
         }
 
-        let mut repo_files: Vec<String> = Vec::new();
-        for p in repo_glob.unwrap().flatten() {
-            if p.is_file() {
-                let s = try_strip_prefix(&self.repo_str, &path_str!(&p));
-                if should_ignore(&s, &self.ignore_patterns) {
+        let mut repo_files: Vec<PathBuf> = Vec::new();
+        for p in repo_glob.unwrap() {
+            let p = match p {
+                Ok(p) => p,
+                Err(err) => {
+                    glob_error(mapping.repo_relpath(&err.path), err);
                     continue;
                 }
-
-                if !ignore_patterns.is_empty() && should_ignore(&s, ignore_patterns) {
+            };
+            if self.fs.is_file(&p) {
+                let s = mapping.repo_relpath(&p);
+                if self.skip(&s, mapping, ignore_patterns, skipped) {
                     continue;
                 }
 
-                log::debug!("Adding repo file: {s}");
-                repo_files.push(s.to_string());
+                log::debug!("Adding repo file: {}", s.display());
+                repo_files.push(s);
             }
         }
 
-        let both: Vec<&String> = home_files
+        let both: Vec<&PathBuf> = home_files
             .iter()
             .filter(|s| repo_files.contains(s))
             .collect();
 
-        let home_only: Vec<&String> = home_files
+        let home_only: Vec<&PathBuf> = home_files
             .iter()
             .filter(|s| !repo_files.contains(s))
             .collect();
 
-        let repo_only: Vec<&String> = repo_files
+        let repo_only: Vec<&PathBuf> = repo_files
             .iter()
             .filter(|s| !home_files.contains(s))
             .collect();
 
-        let mut add_entry = |path: &str, status: Option<Status>| -> Result<()> {
-            let h = self.home.join(path);
-            let r = self.repo.join(path);
+        let mut add_entry = |path: &Path, status: Option<Status>| -> Result<()> {
+            let h = mapping.home_path(path);
+            let r = mapping.repo_path(path);
 
             match status {
                 Some(status) => {
@@ -204,7 +390,7 @@ impl Indexer {
                     entries.push(entry);
                 }
                 None => {
-                    if let Some(entry) = self.make_entry(path, h, r)? {
+                    if let Some(entry) = self.make_entry(path, h, r) {
                         entries.push(entry);
                     }
                 }
@@ -227,32 +413,402 @@ impl Indexer {
         Ok(entries)
     }
 
-    fn make_entry(
+    // Returns true if the file should be ignored, in which case it is added
+    // to skipped along with the rule that excluded it.
+    fn skip(
         &self,
-        filepath: &str,
-        home_path: PathBuf,
-        repo_path: PathBuf,
-    ) -> Result<Option<Entry>> {
+        relpath: &Path,
+        mapping: &Mapping,
+        item_patterns: &[GlobPattern],
+        skipped: &mut Vec<Skipped>,
+    ) -> bool {
+        let rule = match self.ignore_rule(relpath, item_patterns) {
+            Some(rule) => rule,
+            None => return false,
+        };
+
+        log::debug!("Ignoring {}: {}", relpath.display(), rule);
+        if !skipped.iter().any(|s| s.relpath == relpath) {
+            skipped.push(Skipped {
+                relpath: relpath.to_path_buf(),
+                home_path: mapping.home_path(relpath),
+                rule,
+            });
+        }
+        true
+    }
+
+    fn ignore_rule(&self, relpath: &Path, item_patterns: &[GlobPattern]) -> Option<IgnoreRule> {
+        if let Some(p) = find_ignore(relpath, &self.ignore_patterns) {
+            return Some(IgnoreRule::Builtin(p.as_str().to_string()));
+        }
+
+        find_ignore(relpath, item_patterns).map(|p| IgnoreRule::Item(p.as_str().to_string()))
+    }
+
+    // Items under a root are resolved against the root path, and stored
+    // under a directory with the root's name in the repository.
+    fn mapping(&self, item: &Item, pattern: &Path) -> Mapping {
+        let (home, repo) = match &item.root {
+            Some(root) => (root.path.clone(), self.repo.join(&root.name)),
+            None => (self.home.clone(), self.repo.clone()),
+        };
+        Mapping::new(home, repo, pattern, item.repo_dir.as_deref())
+    }
+
+    // Returns an error entry if the files could not be compared.
+    fn make_entry(&self, filepath: &Path, home_path: PathBuf, repo_path: PathBuf) -> Option<Entry> {
         if home_path.ends_with("backup") {
-            return Ok(None);
+            return None;
+        }
+
+        // Without comparing, files in both home and repository are Ok.
+        let status = match self.compare {
+            true => get_status(self.fs.as_ref(), &home_path, &repo_path),
+            false => Ok(Status::Ok),
+        };
+        let entry = status.and_then(|status| Entry::new(filepath, status, home_path, repo_path));
+        match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => Some(Entry::new_err(filepath, err.to_string())),
+        }
+    }
+}
+
+/// The rule that excluded a file from an item, with the pattern that matched.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IgnoreRule {
+    // Patterns that are always ignored, e.g. .git directories.
+    Builtin(String),
+    // The ignore patterns of the item.
+    Item(String),
+}
+
+impl IgnoreRule {
+    pub fn source(&self) -> &str {
+        match self {
+            IgnoreRule::Builtin(_) => "builtin",
+            IgnoreRule::Item(_) => "item",
+        }
+    }
+
+    pub fn pattern(&self) -> &str {
+        match self {
+            IgnoreRule::Builtin(p) | IgnoreRule::Item(p) => p,
+        }
+    }
+}
+
+impl fmt::Display for IgnoreRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} rule {}", self.source(), self.pattern())
+    }
+}
+
+/// A file that matched a pattern of an item but was ignored.
+#[derive(Debug)]
+pub struct Skipped {
+    pub relpath: PathBuf,
+    pub home_path: PathBuf,
+    pub rule: IgnoreRule,
+}
+
+/// A file that is tracked by more than one item.
+#[derive(Debug)]
+pub struct Overlap {
+    pub relpath: PathBuf,
+    pub home_path: PathBuf,
+    // The item that the file belongs to after deduplication.
+    pub owner: String,
+    // All items that track the file, including the owner.
+    pub items: Vec<String>,
+}
+
+/// The .gitignore in the root of the repository. Files it ignores are still
+/// synced, but are not committed, which `dotf show` and `dotf list` report.
+pub struct Gitignore {
+    repo: PathBuf,
+    matcher: GitignoreMatcher,
+}
+
+impl Gitignore {
+    /// Reads the .gitignore of the repository, which is empty if there is
+    /// none. Invalid lines are skipped.
+    pub fn read(fs: &dyn Filesystem, repo: &Path) -> Self {
+        let mut builder = GitignoreBuilder::new(repo);
+        if let Ok(s) = fs.read_string(&repo.join(".gitignore")) {
+            for line in s.lines() {
+                if let Err(err) = builder.add_line(None, line) {
+                    log::warn!("Invalid pattern in .gitignore: {}: {}", line, err);
+                }
+            }
+        }
+        let matcher = builder.build().unwrap_or_else(|err| {
+            log::warn!("Failed to read .gitignore: {}", err);
+            GitignoreMatcher::empty()
+        });
+
+        Self {
+            repo: repo.to_path_buf(),
+            matcher,
+        }
+    }
+
+    /// Returns the line of the .gitignore that ignores the file in the
+    /// repository, if any.
+    pub fn rule(&self, repo_path: &Path) -> Option<String> {
+        let relpath = repo_path.strip_prefix(&self.repo).ok()?;
+        match self.matcher.matched_path_or_any_parents(relpath, false) {
+            Match::Ignore(glob) => Some(glob.original().to_string()),
+            Match::Whitelist(_) | Match::None => None,
+        }
+    }
+}
+
+/// Finds the files that are tracked by multiple items.
+///
+/// The owner of an overlapping file is decided by how specific the matching
+/// patterns are: an explicit path takes precedence over a glob, and a glob
+/// with a longer literal directory prefix over a shorter one, e.g.
+/// `notes/todo.md` > `notes/*` > `*`. Ties are broken by item name.
+pub fn find_overlaps(indexed: &Indexed, items: &[Item]) -> Vec<Overlap> {
+    // Files in the order they were found, and their index by home path.
+    let mut claimed: Vec<(&PathBuf, &Path, Vec<&str>)> = Vec::new();
+    let mut by_home_path: HashMap<&PathBuf, usize> = HashMap::new();
+    for (name, entries) in indexed {
+        for entry in entries {
+            if let Entry::Ok {
+                relpath, home_path, ..
+            } = entry
+            {
+                match by_home_path.get(home_path) {
+                    Some(&i) => {
+                        let names = &mut claimed[i].2;
+                        // Entries of an item are grouped, so only the last
+                        // name may be the same.
+                        if names.last() != Some(&name.as_str()) {
+                            names.push(name);
+                        }
+                    }
+                    None => {
+                        by_home_path.insert(home_path, claimed.len());
+                        claimed.push((home_path, relpath, vec![name]));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut overlaps = Vec::new();
+    for (home_path, relpath, mut names) in claimed {
+        if names.len() < 2 {
+            continue;
+        }
+
+        names.sort_by_key(|name| {
+            let item = items.iter().find(|item| item.name == *name);
+            let score = item.map(|item| specificity(item, relpath)).unwrap_or(0);
+            (std::cmp::Reverse(score), name.to_string())
+        });
+
+        overlaps.push(Overlap {
+            relpath: relpath.to_path_buf(),
+            home_path: home_path.clone(),
+            owner: names[0].to_string(),
+            items: names.iter().map(|s| s.to_string()).collect(),
+        });
+    }
+    overlaps
+}
+
+/// Removes the overlapping files from every item except the owner, as well
+/// as files that are included multiple times by the same item.
+pub fn dedup(indexed: Indexed, overlaps: &[Overlap]) -> Indexed {
+    let owners: HashMap<&PathBuf, &str> = overlaps
+        .iter()
+        .map(|o| (&o.home_path, o.owner.as_str()))
+        .collect();
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    indexed
+        .into_iter()
+        .map(|(name, entries)| {
+            let entries = entries
+                .into_iter()
+                .filter(|entry| match entry {
+                    Entry::Ok { home_path, .. } => {
+                        let owned = owners.get(home_path).is_none_or(|owner| *owner == name);
+                        owned && seen.insert(home_path.clone())
+                    }
+                    Entry::Err(_) => true,
+                })
+                .collect();
+            (name, entries)
+        })
+        .collect()
+}
+
+// Replaces files that are mapped to the same repository file with errors,
+// since neither can be synced without overwriting the other. E.g. a/x and
+// b/x given the patterns a/* and b/* with the same repo_dir, or etc/hosts
+// in home and hosts in the root etc.
+fn reject_collisions(indexed: Indexed, repo: &Path) -> Indexed {
+    let mut by_repo_path: HashMap<PathBuf, Vec<(PathBuf, String)>> = HashMap::new();
+    for (name, entries) in &indexed {
+        for entry in entries {
+            if let Entry::Ok {
+                relpath,
+                home_path,
+                repo_path,
+                ..
+            } = entry
+            {
+                let files = by_repo_path.entry(repo_path.clone()).or_default();
+                if !files.iter().any(|(p, _)| p == home_path) {
+                    let file = format!("{} of {}", relpath.display(), name);
+                    files.push((home_path.clone(), file));
+                }
+            }
+        }
+    }
+
+    let reject = |entry: Entry| -> Entry {
+        let (relpath, home_path, repo_path) = match &entry {
+            Entry::Ok {
+                relpath,
+                home_path,
+                repo_path,
+                ..
+            } => (relpath, home_path, repo_path),
+            Entry::Err(_) => return entry,
+        };
+        let others: Vec<&str> = by_repo_path[repo_path]
+            .iter()
+            .filter(|(p, _)| p != home_path)
+            .map(|(_, file)| file.as_str())
+            .collect();
+        if others.is_empty() {
+            return entry;
         }
 
-        let status = get_status(&home_path, &repo_path)?;
-        let entry = Entry::new(filepath, status, home_path, repo_path)?;
-        Ok(Some(entry))
+        let cause = format!(
+            "maps to the same repository file, {}, as {}",
+            try_strip_prefix(repo, repo_path).display(),
+            others.join(", ")
+        );
+        Entry::new_err(relpath, cause)
+    };
+
+    indexed
+        .into_iter()
+        .map(|(name, entries)| (name, entries.into_iter().map(reject).collect()))
+        .collect()
+}
+
+// How specific the most specific pattern of the item matching relpath is.
+fn specificity(item: &Item, relpath: &Path) -> usize {
+    item.files
+        .iter()
+        .filter_map(|pattern| {
+            if !is_glob_path(pattern) {
+                return (pattern == relpath).then_some(usize::MAX);
+            }
+
+            let matches =
+                GlobPattern::new(&pattern.to_string_lossy()).is_ok_and(|p| matches(&p, relpath));
+            matches.then(|| static_base(pattern).components().count())
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Maps paths of a single file pattern between home and repository.
+/// By default the repository mirrors home. When an item has a `repo_dir`,
+/// the static (non-glob) directory prefix of the pattern is replaced by it,
+/// e.g. `.config/nvim/init.lua` -> `editors/nvim/init.lua` given the pattern
+/// `.config/nvim/**/*` and `repo_dir = "editors/nvim"`.
+struct Mapping {
+    // Absolute directories that relative paths are joined onto.
+    home: PathBuf,
+    repo: PathBuf,
+    home_base: PathBuf,
+    repo_base: PathBuf,
+}
+
+impl Mapping {
+    fn new(home: PathBuf, repo: PathBuf, pattern: &Path, repo_dir: Option<&Path>) -> Self {
+        let (home_base, repo_base) = match repo_dir {
+            None => (PathBuf::new(), PathBuf::new()),
+            Some(dir) => (static_base(pattern), dir.to_path_buf()),
+        };
+        Self {
+            home,
+            repo,
+            home_base,
+            repo_base,
+        }
+    }
+
+    fn home_path(&self, relpath: &Path) -> PathBuf {
+        self.home.join(relpath)
+    }
+
+    fn repo_path(&self, relpath: &Path) -> PathBuf {
+        self.repo.join(self.to_repo(relpath))
+    }
+
+    // The relative path of a file found in home.
+    fn home_relpath(&self, path: &Path) -> PathBuf {
+        try_strip_prefix(&self.home, path)
+    }
+
+    // The relative (home) path of a file found in the repository.
+    fn repo_relpath(&self, path: &Path) -> PathBuf {
+        self.to_home(&try_strip_prefix(&self.repo, path))
+    }
+
+    fn to_repo(&self, path: &Path) -> PathBuf {
+        map_base(path, &self.home_base, &self.repo_base)
+    }
+
+    fn to_home(&self, path: &Path) -> PathBuf {
+        map_base(path, &self.repo_base, &self.home_base)
+    }
+}
+
+fn map_base(path: &Path, from: &Path, to: &Path) -> PathBuf {
+    match path.strip_prefix(from) {
+        Ok(rest) => to.join(rest),
+        Err(_) => path.to_path_buf(),
     }
 }
 
-fn get_status(home_path: &Path, repo_path: &Path) -> Result<Status> {
-    let status = if !home_path.exists() {
+// Returns the leading directories of the pattern that contain no glob,
+// e.g. `nvim` for `nvim/**/*` and `git` for `git/config`.
+fn static_base(pattern: &Path) -> PathBuf {
+    if !is_glob_path(pattern) {
+        return pattern.parent().map(Path::to_path_buf).unwrap_or_default();
+    }
+
+    let mut base = PathBuf::new();
+    for component in pattern.components() {
+        if is_glob_path(Path::new(component.as_os_str())) {
+            break;
+        }
+        base.push(component);
+    }
+    base
+}
+
+fn get_status(fs: &dyn Filesystem, home_path: &Path, repo_path: &Path) -> Result<Status> {
+    let status = if !fs.exists(home_path) {
         Status::MissingHome
-    } else if !repo_path.exists() {
+    } else if !fs.exists(repo_path) {
         Status::MissingRepo
     } else {
-        let s = files::read_string(home_path)?;
+        let s = fs.read_string(home_path)?;
         let hash_src = files::digest(s.as_bytes())?;
 
-        let s = files::read_string(repo_path)?;
+        let s = fs.read_string(repo_path)?;
         let hash_dst = files::digest(s.as_bytes())?;
 
         if hash_src.eq(&hash_dst) {
@@ -265,10 +821,20 @@ fn get_status(home_path: &Path, repo_path: &Path) -> Result<Status> {
     Ok(status)
 }
 
-fn should_ignore(path: &str, patterns: &[GlobPattern]) -> bool {
-    patterns.iter().any(|p| p.matches(path))
+fn find_ignore<'a>(path: &Path, patterns: &'a [GlobPattern]) -> Option<&'a GlobPattern> {
+    patterns.iter().find(|p| matches(p, path))
+}
+
+// Unlike `GlobPattern::matches_path`, paths that are not valid UTF-8 are
+// matched as well, e.g. against *.backup.
+fn matches(pattern: &GlobPattern, path: &Path) -> bool {
+    pattern.matches(&path.to_string_lossy())
 }
 
 pub fn is_glob(s: &str) -> bool {
     s.contains('*')
 }
+
+pub fn is_glob_path(path: &Path) -> bool {
+    is_glob(&path.to_string_lossy())
+}
@@ -1,6 +1,10 @@
-use crate::testing::TestContext;
+use crate::testing::{create_with_path, TestContext};
 
 use super::*;
+use crate::data::Root;
+use crate::files::MemoryFs;
+use crate::handler::Selection;
+use std::sync::Arc;
 
 #[test]
 fn should_ignore_true() {
@@ -16,16 +20,28 @@ fn should_ignore_true() {
     ];
 
     for path in paths {
-        assert!(should_ignore(path, patterns));
+        assert!(find_ignore(Path::new(path), patterns).is_some());
     }
 }
 
-// Setup test context and indexer.
+// Setup test context and an indexer of its files in memory.
+fn setup() -> (TestContext, Arc<MemoryFs>, Indexer) {
+    let cx = TestContext::default();
+    let fs = Arc::new(cx.setup_memory());
+    let indexer = new_indexer(&cx, &fs, None);
+    (cx, fs, indexer)
+}
+
+fn new_indexer(cx: &TestContext, fs: &Arc<MemoryFs>, only: Option<Only>) -> Indexer {
+    Indexer::new(cx.home_dir.clone(), cx.repo_dir.clone(), only).with_filesystem(fs.clone())
+}
+
+// Setup test context and indexer of files on disk, for tests that need
+// real files.
 // NOTE! Make sure that context is also stored, else it is dropped directly.
 // That is, use it like this:
-//     let (_cx, indexer) = setup();
-
-fn setup() -> (TestContext, Indexer) {
+//     let (_cx, indexer) = setup_disk();
+fn setup_disk() -> (TestContext, Indexer) {
     let cx = TestContext::default();
     cx.setup().expect("to setup test context");
     let indexer = Indexer::new(cx.home_dir.clone(), cx.repo_dir.clone(), None);
@@ -34,7 +50,7 @@ fn setup() -> (TestContext, Indexer) {
 
 #[test]
 fn return_zero_entries_given_empty_list() {
-    let (_, indexer) = setup();
+    let (_, _, indexer) = setup();
     let res = indexer.index(&[]).unwrap();
     assert!(res.is_empty());
 }
@@ -42,7 +58,7 @@ fn return_zero_entries_given_empty_list() {
 #[test]
 fn return_entry_given_one() {
     // Arrange
-    let (_cx, indexer) = setup();
+    let (_cx, _, indexer) = setup();
     let items = vec![Item::simple_new("diff", "diffed.txt")];
     // Act
     let indexed = indexer.index(&items).unwrap();
@@ -57,7 +73,7 @@ fn return_entry_given_one() {
 #[test]
 fn return_entry_in_directory() {
     // Arrange
-    let (_cx, indexer) = setup();
+    let (_cx, _, indexer) = setup();
     let items = vec![Item::simple_new("space", "config/spaceship.yml")];
     // Act
     let indexed = indexer.index(&items).unwrap();
@@ -67,14 +83,14 @@ fn return_entry_in_directory() {
     let (_, entries) = indexed.first().expect("to get first");
     let contains_file = entries
         .iter()
-        .any(|e| e.get_relpath().contains("spaceship"));
+        .any(|e| e.get_relpath().to_string_lossy().contains("spaceship"));
     assert!(contains_file);
 }
 
 #[test]
 fn respect_ignore() {
     // Arrange
-    let (_cx, indexer) = setup();
+    let (_cx, _, indexer) = setup();
     let items = vec![Item::object_new(
         "glob",
         &["deepglob/src/*"],
@@ -93,7 +109,7 @@ fn respect_ignore() {
 #[test]
 fn ignores_special_directories() {
     // Arrange
-    let (_cx, indexer) = setup();
+    let (_cx, _, indexer) = setup();
     let items = vec![Item::object_new("glob", &["deepglob/**/*"], None)];
     // Act
     let indexed = indexer.index(&items).unwrap();
@@ -103,6 +119,626 @@ fn ignores_special_directories() {
     let (_, entries) = indexed.first().expect("to get first");
     let contains_git = entries
         .iter()
-        .any(|entry| entry.get_relpath().contains(".git"));
+        .any(|entry| entry.get_relpath().to_string_lossy().contains(".git"));
     assert!(!contains_git);
 }
+
+#[test]
+fn static_base_of_patterns() {
+    let tests = [
+        ("nvim/**/*", "nvim"),
+        (".config/nvim/*.lua", ".config/nvim"),
+        ("*.md", ""),
+        ("git/config", "git"),
+        (".zshrc", ""),
+    ];
+    for (pattern, expected) in tests {
+        assert_eq!(PathBuf::from(expected), static_base(Path::new(pattern)));
+    }
+}
+
+#[test]
+fn mapping_without_repo_dir_is_identity() {
+    let mapping = Mapping::new(
+        PathBuf::from("/home"),
+        PathBuf::from("/repo"),
+        Path::new("config/*"),
+        None,
+    );
+    let path = Path::new("config/a.yml");
+    assert_eq!(path, mapping.to_repo(path));
+    assert_eq!(path, mapping.to_home(path));
+}
+
+#[test]
+fn respect_repo_dir() {
+    // Arrange
+    let (cx, fs, indexer) = setup();
+    fs.add_file(&cx.repo_path("mapped/extra.yml"), b"extra");
+    let items = vec![Item::simple_new("conf", "config/*")
+        .with_repo_dir("mapped")
+        .unwrap()];
+
+    // Act
+    let indexed = indexer.index(&items).unwrap();
+
+    // Assert
+    let (_, entries) = indexed.first().expect("to get first");
+    assert_eq!(2, entries.len());
+    for entry in entries {
+        if let Entry::Ok {
+            relpath,
+            status,
+            repo_path,
+            ..
+        } = entry
+        {
+            match relpath.to_str().unwrap() {
+                "config/spaceship.yml" => {
+                    assert!(matches!(status, Status::MissingRepo));
+                    assert_eq!(&cx.repo_path("mapped/spaceship.yml"), repo_path);
+                }
+                "config/extra.yml" => {
+                    assert!(matches!(status, Status::MissingHome));
+                    assert_eq!(&cx.repo_path("mapped/extra.yml"), repo_path);
+                }
+                other => panic!("unexpected entry: {}", other),
+            }
+        }
+    }
+}
+
+#[test]
+fn glob_errors_are_relative() {
+    // Arrange
+    let (cx, indexer) = setup_disk();
+    create_with_path(&cx.home_path("notes"), "not a directory").unwrap();
+    create_with_path(&cx.repo_path("mapped/bin"), "not a directory").unwrap();
+    let items = vec![
+        Item::simple_new("notes", "notes/*.md"),
+        Item::simple_new("bin", "bin/*")
+            .with_repo_dir("mapped/bin")
+            .unwrap(),
+    ];
+
+    // Act
+    let indexed = indexer.index(&items).unwrap();
+
+    // Assert
+    let errors: Vec<&Path> = indexed
+        .iter()
+        .flat_map(|(_, entries)| entries)
+        .filter_map(|entry| match entry {
+            Entry::Err(err) => Some(err.path.as_path()),
+            Entry::Ok { .. } => None,
+        })
+        .collect();
+    assert_eq!(vec![Path::new("bin"), Path::new("notes")], errors);
+}
+
+#[test]
+fn repo_dir_collisions_are_errors() {
+    // Arrange
+    let (cx, fs, indexer) = setup();
+    fs.add_file(&cx.home_path("a/x"), b"a");
+    fs.add_file(&cx.home_path("b/x"), b"b");
+    fs.add_file(&cx.home_path("b/y"), b"y");
+    let items = vec![Item::new(
+        "mapped".to_string(),
+        vec!["a/*".to_string(), "b/*".to_string()],
+        None,
+    )
+    .with_repo_dir("d")
+    .unwrap()];
+
+    // Act
+    let indexed = indexer.index(&items).unwrap();
+
+    // Assert
+    let (_, entries) = indexed.first().expect("to get first");
+    let errors: Vec<&Path> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::Err(err) => Some(err.path.as_path()),
+            Entry::Ok { .. } => None,
+        })
+        .collect();
+    assert_eq!(vec![Path::new("a/x"), Path::new("b/x")], errors);
+    assert_eq!(Path::new("b/y"), entries[2].get_relpath());
+}
+
+#[test]
+fn respect_root() {
+    // Arrange
+    let (cx, fs, indexer) = setup();
+    let root_dir = cx.temp_dir.join("etc");
+    fs.add_file(&root_dir.join("hosts"), b"127.0.0.1");
+    fs.add_file(&cx.repo_path("etc/fstab"), b"/dev/sda");
+    let items =
+        vec![Item::simple_new("etc.files", "*").with_root(Root::new("etc", root_dir.clone()))];
+
+    // Act
+    let indexed = indexer.index(&items).unwrap();
+
+    // Assert
+    let (_, entries) = indexed.first().expect("to get first");
+    assert_eq!(2, entries.len());
+    for entry in entries {
+        if let Entry::Ok {
+            relpath,
+            home_path,
+            repo_path,
+            ..
+        } = entry
+        {
+            assert_eq!(&root_dir.join(relpath), home_path);
+            assert_eq!(&cx.repo_path("etc").join(relpath), repo_path);
+        }
+    }
+}
+
+#[test]
+fn root_and_home_collisions_are_errors() {
+    // Arrange
+    let (cx, fs, indexer) = setup();
+    let root_dir = cx.temp_dir.join("etc");
+    fs.add_file(&root_dir.join("hosts"), b"127.0.0.1");
+    fs.add_file(&cx.home_path("etc/hosts"), b"::1");
+    let items = vec![
+        Item::simple_new("etc.hosts", "hosts").with_root(Root::new("etc", root_dir)),
+        Item::simple_new("home", "etc/hosts"),
+        Item::simple_new("vim", "init.vim"),
+    ];
+
+    // Act
+    let indexed = indexer.index(&items).unwrap();
+
+    // Assert
+    for (name, entries) in &indexed {
+        assert_eq!(1, entries.len());
+        match name.as_str() {
+            "vim" => assert!(entries[0].is_ok()),
+            _ => assert!(
+                matches!(&entries[0], Entry::Err(err) if err.cause.contains("etc/hosts")),
+                "{}: {:?}",
+                name,
+                entries[0]
+            ),
+        }
+    }
+}
+
+#[test]
+fn watch_paths_of_items() {
+    // Arrange
+    let (cx, _, indexer) = setup();
+    let items = vec![
+        Item::simple_new("vim", "init.vim"),
+        Item::simple_new("conf", "config/*"),
+        Item::simple_new("deep", "deepglob/**/*"),
+        Item::simple_new("src", "deepglob/src/*.json"),
+        Item::simple_new("missing", "missing/*"),
+    ];
+
+    // Act
+    let paths = indexer.watch_paths(&items);
+
+    // Assert
+    let expected = vec![
+        (cx.home_dir.clone(), false),
+        (cx.home_path("config"), false),
+        (cx.home_path("deepglob"), true),
+        (cx.home_path("deepglob/src"), false),
+    ];
+    assert_eq!(expected, paths);
+}
+
+#[test]
+fn watch_ancestors_of_missing_directories() {
+    // Arrange
+    let (cx, _, indexer) = setup();
+    let items = vec![
+        Item::simple_new("missing", "config/missing/deep/**/*"),
+        Item::simple_new("file", "absent/file.txt"),
+    ];
+
+    // Act
+    let paths = indexer.watch_paths(&items);
+
+    // Assert
+    let expected = vec![
+        (cx.home_path("config"), false),
+        (cx.home_dir.clone(), false),
+    ];
+    assert_eq!(expected, paths);
+}
+
+#[test]
+fn overlapping_items_are_deduped() {
+    // Arrange
+    let (_cx, _, indexer) = setup();
+    let items = vec![
+        Item::simple_new("all", "config/*"),
+        Item::simple_new("space", "config/spaceship.yml"),
+        Item::simple_new("deep", "deepglob/**/*"),
+        Item::simple_new("src", "deepglob/src/*"),
+        Item::simple_new("vim", "init.vim"),
+    ];
+
+    // Act
+    let (indexed, overlaps) = indexer.index_deduped(&items).unwrap();
+
+    // Assert
+    let owners: Vec<(&str, &str)> = overlaps
+        .iter()
+        .map(|o| (o.relpath.to_str().unwrap(), o.owner.as_str()))
+        .collect();
+    assert_eq!(4, owners.len());
+    assert!(owners.contains(&("config/spaceship.yml", "space")));
+    assert!(owners.contains(&("deepglob/src/file.js", "src")));
+
+    let count = |name: &str| {
+        indexed
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, entries)| entries.len())
+            .unwrap()
+    };
+    assert_eq!(0, count("all"));
+    assert_eq!(1, count("space"));
+    assert_eq!(2, count("deep"));
+    assert_eq!(3, count("src"));
+    assert_eq!(1, count("vim"));
+}
+
+#[test]
+fn overlap_ties_are_broken_by_name() {
+    // Arrange
+    let (_cx, _, indexer) = setup();
+    let items = vec![
+        Item::simple_new("b", "config/*"),
+        Item::simple_new("a", "config/*.yml"),
+    ];
+
+    // Act
+    let (_, overlaps) = indexer.index_deduped(&items).unwrap();
+
+    // Assert
+    assert_eq!(1, overlaps.len());
+    assert_eq!("a", overlaps[0].owner);
+    assert_eq!(vec!["a", "b"], overlaps[0].items);
+}
+
+#[test]
+fn duplicates_within_item_are_removed() {
+    // Arrange
+    let (_cx, _, indexer) = setup();
+    let items = vec![Item::new(
+        "conf".to_string(),
+        vec!["config/*".to_string(), "config/spaceship.yml".to_string()],
+        None,
+    )];
+
+    // Act
+    let (indexed, overlaps) = indexer.index_deduped(&items).unwrap();
+
+    // Assert
+    assert!(overlaps.is_empty());
+    assert_eq!(1, indexed[0].1.len());
+}
+
+#[test]
+fn skipped_files_have_ignore_rule() {
+    // Arrange
+    let (cx, fs, indexer) = setup();
+    fs.add_file(&cx.repo_path(".gitignore"), b"*.json\n");
+    let item = Item::object_new("deep", &["deepglob/**/*"], Some(&["*.out"]));
+
+    // Act
+    let (entries, skipped) = indexer.index_item(&item).unwrap();
+
+    // Assert
+    // Files ignored by the .gitignore are still synced.
+    assert_eq!(4, entries.len());
+    let rule = |relpath: &str| {
+        skipped
+            .iter()
+            .find(|s| s.relpath == Path::new(relpath))
+            .map(|s| s.rule.clone())
+    };
+    assert_eq!(
+        Some(IgnoreRule::Builtin("*/.git/*".to_string())),
+        rule("deepglob/.git/config")
+    );
+    assert_eq!(
+        Some(IgnoreRule::Item("*.out".to_string())),
+        rule("deepglob/test.out")
+    );
+    assert_eq!(None, rule("deepglob/src/package.json"));
+}
+
+#[test]
+fn gitignore_rules() {
+    // Arrange
+    let (cx, fs, _) = setup();
+    fs.add_file(
+        &cx.repo_path(".gitignore"),
+        b"# comment\n\n/build\ncache/\n*.json\n!keep.json\nsrc/*.rs\n",
+    );
+
+    // Act
+    let gitignore = Gitignore::read(fs.as_ref(), &cx.repo_dir);
+
+    // Assert
+    let rule = |path: &str| gitignore.rule(&cx.repo_path(path));
+    assert_eq!(Some("/build".to_string()), rule("build/out.txt"));
+    assert_eq!(None, rule("nvim/build/out.txt"));
+    assert_eq!(Some("cache/".to_string()), rule("nvim/cache/file"));
+    assert_eq!(None, rule("cache"));
+    assert_eq!(Some("*.json".to_string()), rule("a/b/package.json"));
+    assert_eq!(None, rule("a/keep.json"));
+    assert_eq!(Some("src/*.rs".to_string()), rule("src/main.rs"));
+    assert_eq!(None, rule("src/bin/main.rs"));
+}
+
+#[test]
+fn gitignore_whitelist() {
+    // Arrange
+    let (cx, fs, _) = setup();
+    fs.add_file(&cx.repo_path(".gitignore"), b"*\n!.zshrc\n");
+
+    // Act
+    let gitignore = Gitignore::read(fs.as_ref(), &cx.repo_dir);
+
+    // Assert
+    assert_eq!(None, gitignore.rule(&cx.repo_path(".zshrc")));
+    assert_eq!(
+        Some("*".to_string()),
+        gitignore.rule(&cx.repo_path(".bashrc"))
+    );
+}
+
+#[test]
+fn select_items_by_name_and_group() {
+    // Arrange
+    let (cx, fs, _) = setup();
+    let mut vim = Item::simple_new("vim", "init.vim");
+    vim.groups = vec!["editor".to_string()];
+    let items = vec![
+        vim,
+        Item::simple_new("tmux", "tmux.conf"),
+        Item::simple_new("conf", "config/*"),
+        Item::simple_new("all", "config/*.yml"),
+    ];
+
+    let tests = [
+        (Selection::default(), vec!["all", "conf", "tmux", "vim"]),
+        (
+            Selection {
+                items: vec!["tmux".to_string()],
+                groups: vec!["editor".to_string()],
+                ..Default::default()
+            },
+            vec!["tmux", "vim"],
+        ),
+        (
+            Selection {
+                exclude: vec!["editor".to_string(), "tmux".to_string()],
+                ..Default::default()
+            },
+            vec!["all", "conf"],
+        ),
+    ];
+
+    for (selection, expected) in tests {
+        let indexer = new_indexer(&cx, &fs, None).with_selection(selection);
+
+        // Act
+        let (indexed, _) = indexer.index_deduped(&items).unwrap();
+
+        // Assert
+        let names: Vec<&str> = indexed.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(expected, names);
+    }
+}
+
+#[test]
+fn selection_does_not_change_owner() {
+    // Arrange
+    let (cx, fs, _) = setup();
+    let items = vec![
+        Item::simple_new("conf", "config/*"),
+        Item::simple_new("space", "config/spaceship.yml"),
+    ];
+    let selection = Selection {
+        items: vec!["conf".to_string()],
+        ..Default::default()
+    };
+    let indexer = new_indexer(&cx, &fs, None).with_selection(selection);
+
+    // Act
+    let (indexed, overlaps) = indexer.index_deduped(&items).unwrap();
+
+    // Assert
+    assert_eq!(1, indexed.len());
+    assert!(indexed[0].1.is_empty());
+    assert_eq!("space", overlaps[0].owner);
+}
+
+#[test]
+fn select_unknown_item() {
+    let (cx, fs, _) = setup();
+    let items = vec![Item::simple_new("vim", "init.vim")];
+    let selection = Selection {
+        groups: vec!["nope".to_string()],
+        ..Default::default()
+    };
+    let indexer = new_indexer(&cx, &fs, None).with_selection(selection);
+
+    let err = indexer.index(&items).expect_err("unknown group");
+    assert_eq!("unknown group: nope", err.to_string());
+}
+
+#[test]
+fn only_keeps_errors() {
+    // Arrange
+    let (cx, fs, _) = setup();
+    let only = Only::from_glob(&vec!["*.vim".to_string()]).unwrap();
+    let indexer = new_indexer(&cx, &fs, Some(only));
+    let items = vec![
+        Item::simple_new("vim", "init.vim"),
+        Item::simple_new("missing", "missing.txt"),
+    ];
+
+    // Act
+    let indexed = indexer.index(&items).unwrap();
+
+    // Assert
+    let (name, entries) = &indexed[0];
+    assert_eq!("missing", name);
+    assert!(!entries[0].is_ok());
+}
+
+#[test]
+fn errors_do_not_discard_other_files() {
+    // Arrange
+    let (_cx, _, indexer) = setup();
+    let items = vec![Item::new(
+        "mixed".to_string(),
+        vec![
+            "missing.txt".to_string(),
+            "diffed.txt".to_string(),
+            "deepglob".to_string(),
+            "init.vim".to_string(),
+        ],
+        None,
+    )];
+
+    // Act
+    let indexed = indexer.index(&items).unwrap();
+
+    // Assert
+    let entries = &indexed[0].1;
+    assert_eq!(4, entries.len());
+    let errors: Vec<&str> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::Err(err) => Some(err.path.to_str().unwrap()),
+            Entry::Ok { .. } => None,
+        })
+        .collect();
+    assert_eq!(vec!["missing.txt", "deepglob"], errors);
+}
+
+#[test]
+fn fail_fast_returns_first_error() {
+    // Arrange
+    let (cx, fs, _) = setup();
+    let indexer = new_indexer(&cx, &fs, None).with_fail_fast(true);
+    let items = vec![Item::new(
+        "mixed".to_string(),
+        vec!["diffed.txt".to_string(), "missing.txt".to_string()],
+        None,
+    )];
+
+    // Act
+    let result = indexer.index(&items);
+
+    // Assert
+    let err = result.expect_err("index should fail");
+    assert_eq!(
+        "mixed: missing.txt: does not exists in either home or repository",
+        err.to_string()
+    );
+}
+
+#[test]
+fn fail_fast_ignores_unselected_items() {
+    // Arrange
+    let (cx, fs, _) = setup();
+    let items = vec![
+        Item::simple_new("broken", "nope.txt"),
+        Item::simple_new("diff", "diffed.txt"),
+    ];
+    let selections = [
+        Selection {
+            items: vec!["diff".to_string()],
+            ..Default::default()
+        },
+        Selection {
+            exclude: vec!["broken".to_string()],
+            ..Default::default()
+        },
+    ];
+
+    for selection in selections {
+        let indexer = new_indexer(&cx, &fs, None)
+            .with_selection(selection)
+            .with_fail_fast(true);
+
+        // Act
+        let (indexed, _) = indexer.index_deduped(&items).unwrap();
+
+        // Assert
+        assert_eq!(1, indexed.len());
+        assert!(indexed[0].1[0].is_diff());
+    }
+}
+
+#[test]
+fn index_in_memory() {
+    // Arrange
+    let (cx, _, indexer) = setup();
+    let items = vec![
+        Item::simple_new("diff", "diffed.txt"),
+        Item::object_new("deep", &["deepglob/**/*"], Some(&["*.out"])),
+    ];
+
+    // Act
+    let indexed = indexer.index(&items).unwrap();
+
+    // Assert
+    assert!(!cx.temp_dir.exists());
+    let (name, entries) = &indexed[0];
+    assert_eq!("deep", name);
+    let mut relpaths: Vec<&str> = entries
+        .iter()
+        .map(|e| e.get_relpath().to_str().unwrap())
+        .collect();
+    relpaths.sort();
+    assert_eq!(
+        vec![
+            "deepglob/config.yml",
+            "deepglob/src/file.js",
+            "deepglob/src/package-lock.json",
+            "deepglob/src/package.json",
+        ],
+        relpaths
+    );
+    assert!(indexed[1].1[0].is_diff());
+}
+
+#[test]
+#[cfg(unix)]
+fn index_paths_that_are_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    // Arrange
+    let (cx, indexer) = setup_disk();
+    let name = OsStr::from_bytes(b"bad\xff.yml");
+    create_with_path(&cx.home_path("config").join(name), "home").unwrap();
+    create_with_path(&cx.repo_path("config").join(name), "repo").unwrap();
+    let items = vec![Item::simple_new("conf", "config/*")];
+
+    // Act
+    let indexed = indexer.index(&items).unwrap();
+
+    // Assert
+    let (_, entries) = indexed.first().expect("to get first");
+    let entry = entries
+        .iter()
+        .find(|e| e.get_relpath() == Path::new("config").join(name))
+        .expect("to find the file");
+    assert!(entry.is_diff());
+}
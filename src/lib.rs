@@ -1,12 +1,16 @@
 pub mod cli;
 pub mod cmd;
 pub mod data;
+pub mod error;
 pub mod files;
 pub mod handler;
 pub mod index;
 pub mod logging;
 pub mod path;
 pub mod prompt;
+pub mod report;
+pub mod session;
+pub mod style;
 
 #[cfg(test)]
 mod testing;
@@ -1,9 +1,14 @@
 use dotfiles::cli::Cli;
+use dotfiles::error;
 
 fn main() {
-    let cli = Cli::default();
+    let cli = Cli;
     if let Err(err) = cli.exec() {
-        eprintln!("error: {}", err);
-        std::process::exit(1);
+        eprintln!("error: {:#}", err);
+        let dotf_error = error::find(&err);
+        if let Some(hint) = dotf_error.and_then(|e| e.hint()) {
+            eprintln!("hint: {}", hint);
+        }
+        std::process::exit(dotf_error.map_or(1, |e| e.exit_code()));
     }
 }
@@ -1,52 +1,124 @@
-use std::path::PathBuf;
+use std::env;
+use std::path::{Path, PathBuf};
 
+use anyhow::{bail, Result};
 use directories::BaseDirs;
 use lazy_static::lazy_static;
 
-/// Converts a Path/PathBuf into String type.
-/// Uses unwrap(), which may panic for a path with /// invalid utf-8 for certain OSes.
-#[macro_export]
-macro_rules! path_str {
-    ($p:expr) => {
-        $p.to_str().expect("valid utf-8 string path").to_string()
-    };
-}
-
 lazy_static! {
-    pub static ref SEP: String = std::path::MAIN_SEPARATOR.to_string();
     static ref BASE_DIRS: BaseDirs = BaseDirs::new().expect("to resolve base directory");
-    pub static ref HOME_DIR: String = path_str!(BASE_DIRS.home_dir());
-    pub static ref LOCAL_CONFIG_DIR: String = path_str!(BASE_DIRS.config_dir());
-    pub static ref LOCAL_DATA_DIR: String = path_str!(BASE_DIRS.data_local_dir());
+    pub static ref HOME_DIR: PathBuf = BASE_DIRS.home_dir().to_path_buf();
+    pub static ref LOCAL_CONFIG_DIR: PathBuf = BASE_DIRS.config_dir().to_path_buf();
+    pub static ref LOCAL_DATA_DIR: PathBuf = BASE_DIRS.data_local_dir().to_path_buf();
 }
 
 pub fn home_path() -> PathBuf {
-    PathBuf::from(HOME_DIR.as_str())
+    HOME_DIR.clone()
+}
+
+pub fn try_strip_home_prefix(path: &Path) -> PathBuf {
+    try_strip_prefix(&HOME_DIR, path)
 }
 
-pub fn try_strip_home_prefix(path: &str) -> String {
-    try_strip_prefix(HOME_DIR.as_str(), path)
+pub fn try_strip_prefix(prefix: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(prefix).unwrap_or(path).to_path_buf()
 }
 
-pub fn try_strip_prefix(prefix: &str, path: &str) -> String {
-    let path = PathBuf::from(path);
-    match path.strip_prefix(prefix) {
-        Ok(p) => path_str!(p),
-        Err(_) => path_str!(path),
+/// Expands a leading `~` and any `$VAR`/`${VAR}` references in `s`
+/// using the environment. A relative result is resolved against `base`.
+pub fn expand(s: &str, base: &Path) -> Result<PathBuf> {
+    let path = expand_str(s)?;
+    Ok(base.join(path))
+}
+
+/// Like `expand`, but relative paths are left untouched.
+pub fn expand_str(s: &str) -> Result<PathBuf> {
+    let path = expand_vars(s, |name| env::var(name).ok())?;
+    Ok(expand_tilde(&path, &HOME_DIR))
+}
+
+/// Replaces a leading `~` with `home`.
+pub fn expand_tilde(s: &str, home: &Path) -> PathBuf {
+    if s == "~" {
+        home.to_path_buf()
+    } else if let Some(rest) = s.strip_prefix("~/") {
+        home.join(rest)
+    } else {
+        PathBuf::from(s)
     }
 }
 
+/// Replaces `$VAR` and `${VAR}` with the value returned by `lookup`.
+/// `$$` is a literal `$`. Fails if a variable is not defined.
+pub fn expand_vars<F>(s: &str, lookup: F) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+
+        let name = match chars.peek() {
+            Some('$') => {
+                chars.next();
+                out.push('$');
+                continue;
+            }
+            Some('{') => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => bail!("unterminated variable reference in '{}'", s),
+                    }
+                }
+                if name.is_empty() {
+                    bail!("empty variable reference in '{}'", s);
+                }
+                name
+            }
+            _ => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        name.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                if name.is_empty() {
+                    out.push('$');
+                    continue;
+                }
+                name
+            }
+        };
+
+        match lookup(&name) {
+            Some(value) => out.push_str(&value),
+            None => bail!("undefined environment variable ${} in '{}'", name, s),
+        }
+    }
+
+    Ok(out)
+}
+
 /// Builds a configuration path based on CONFIG_DIR.
 #[macro_export]
 macro_rules! config_path {
     ($($p:expr),*) => {
         {
-            let mut v = Vec::new();
-            v.push($crate::path::LOCAL_CONFIG_DIR.as_str());
-            $(
-                v.push($p);
-            )*
-            v.join(&$crate::path::SEP)
+            let mut path = $crate::path::LOCAL_CONFIG_DIR.clone();
+            $(path.push($p);)*
+            path
         }
    }
 }
@@ -56,27 +128,80 @@ macro_rules! config_path {
 macro_rules! data_path {
     ($($p:expr),*) => {
         {
-            let mut v = Vec::new();
-            v.push($crate::path::LOCAL_DATA_DIR.as_str());
-            $(
-                v.push($p);
-            )*
-            v.join(&$crate::path::SEP)
+            let mut path = $crate::path::LOCAL_DATA_DIR.clone();
+            $(path.push($p);)*
+            path
         }
    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "XDG_DATA_HOME" => Some("/home/user/.local/share".to_string()),
+            "NAME" => Some("nvim".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        let tests = [
+            ("plain/path", "plain/path"),
+            ("$XDG_DATA_HOME/fonts", "/home/user/.local/share/fonts"),
+            ("${XDG_DATA_HOME}/fonts", "/home/user/.local/share/fonts"),
+            ("config/${NAME}_old/*", "config/nvim_old/*"),
+            ("cost$$", "cost$"),
+            ("trailing$", "trailing$"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, expand_vars(input, lookup).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_expand_vars_invalid() {
+        for input in ["$UNDEFINED/x", "${UNDEFINED}", "${XDG_DATA_HOME", "${}"] {
+            assert!(expand_vars(input, lookup).is_err());
+        }
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let home = Path::new("/home/user");
+        assert_eq!(PathBuf::from("/home/user"), expand_tilde("~", home));
+        assert_eq!(
+            PathBuf::from("/home/user/code"),
+            expand_tilde("~/code", home)
+        );
+        assert_eq!(
+            PathBuf::from("~user/code"),
+            expand_tilde("~user/code", home)
+        );
+        assert_eq!(PathBuf::from("code/~"), expand_tilde("code/~", home));
+    }
+
+    #[test]
+    fn test_expand_relative_to_base() {
+        let path = expand("repo", Path::new("/base")).unwrap();
+        assert_eq!(PathBuf::from("/base/repo"), path);
+
+        let path = expand("/abs/repo", Path::new("/base")).unwrap();
+        assert_eq!(PathBuf::from("/abs/repo"), path);
+    }
+
     #[test]
     fn test_config_path() {
         let path = config_path!("nvim", "lua", "init.lua");
-        assert!(path.contains("nvim/lua/init"));
+        assert!(path.ends_with("nvim/lua/init.lua"));
     }
 
     #[test]
     fn test_data_path() {
         let path = data_path!("nvim", "lua", "init.lua");
-        assert!(path.contains("nvim/lua/init"));
+        assert!(path.ends_with("nvim/lua/init.lua"));
     }
 }
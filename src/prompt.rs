@@ -1,21 +1,44 @@
+use crate::error::DotfError;
 use anyhow::Result;
-use inquire::{Confirm, Text};
+use inquire::{Confirm, InquireError, Select, Text};
 
 pub trait Prompt {
     fn prompt(&self, msg: &str) -> Result<String>;
     fn confirm(&self, msg: &str, default_yes: bool) -> Result<bool>;
+    /// Lets the user pick one of the options and returns its index.
+    fn select(&self, msg: &str, options: &[String]) -> Result<usize>;
 }
 
 pub struct StdinPrompt {}
 
 impl Prompt for StdinPrompt {
     fn prompt(&self, msg: &str) -> Result<String> {
-        let text = Text::new(msg).prompt()?;
+        let text = Text::new(msg).prompt().map_err(aborted)?;
         Ok(text)
     }
 
     fn confirm(&self, msg: &str, default_yes: bool) -> Result<bool> {
-        let ok = Confirm::new(msg).with_default(default_yes).prompt()?;
+        let ok = Confirm::new(msg)
+            .with_default(default_yes)
+            .prompt()
+            .map_err(aborted)?;
         Ok(ok)
     }
+
+    fn select(&self, msg: &str, options: &[String]) -> Result<usize> {
+        let selected = Select::new(msg, options.to_vec())
+            .raw_prompt()
+            .map_err(aborted)?;
+        Ok(selected.index)
+    }
+}
+
+// Escape and ctrl-c abort the command rather than failing it.
+fn aborted(err: InquireError) -> anyhow::Error {
+    match err {
+        InquireError::OperationCanceled | InquireError::OperationInterrupted => {
+            DotfError::Aborted.into()
+        }
+        err => err.into(),
+    }
 }
@@ -0,0 +1,243 @@
+use crate::data::Status;
+use crate::handler::{Action, ActionKind, SyncSummary};
+use crate::style::{paint, Icon};
+use crossterm::style::Stylize;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Mutex;
+
+/// Something that happened while running a command, reported to the user
+/// through a `Reporter`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The following events, count of them, belong to the item.
+    ItemStarted {
+        item: String,
+        count: usize,
+    },
+    /// The status of a file of the item.
+    Entry {
+        item: String,
+        relpath: String,
+        status: Status,
+    },
+    /// A file was written or backed up, or a directory created. On dry runs
+    /// this only describes what would have been done.
+    Copied {
+        action: Action,
+        dryrun: bool,
+    },
+    Skipped {
+        action: Action,
+    },
+    /// A file, or pattern, of the item that could not be handled.
+    Error {
+        item: String,
+        path: String,
+        reason: String,
+    },
+    /// A file that is tracked by more than one item.
+    Overlap {
+        relpath: String,
+        items: Vec<String>,
+        owner: String,
+    },
+    /// A hook was run. Its stdout is only included if it was captured.
+    Hook {
+        name: String,
+        cmd: String,
+        output: Option<String>,
+        error: Option<String>,
+    },
+    /// A note for the user, e.g. that all files are up to date.
+    Info {
+        message: String,
+    },
+    /// The number of files by status, at the end of dotf status.
+    StatusSummary {
+        ok: usize,
+        diff: usize,
+        invalid: usize,
+        missing_home: usize,
+        missing_repo: usize,
+    },
+    SyncSummary {
+        summary: SyncSummary,
+    },
+    /// The result of a command as a single JSON document, when the output
+    /// format is JSON.
+    Document {
+        value: Value,
+    },
+}
+
+/// Receives the events of handlers, e.g. to print them.
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: Event);
+}
+
+/// Prints events for humans, using colors and icons.
+#[derive(Default)]
+pub struct TerminalReporter {
+    state: Mutex<TerminalState>,
+}
+
+#[derive(Default)]
+struct TerminalState {
+    // An item with a single event, that is printed on the same line as the
+    // event if it is an entry or an error.
+    pending: Option<String>,
+    // The current section, e.g. hooks, with its header already printed.
+    section: Option<&'static str>,
+}
+
+impl TerminalReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Prints a line belonging to the current item.
+    fn line(&self, state: &mut TerminalState, line: String, inline: bool) {
+        match state.pending.take() {
+            Some(item) if inline => println!(" {}: {}", item, line),
+            Some(item) => {
+                println!("\n {}", item);
+                println!("   {}", line);
+            }
+            None => println!("   {}", line),
+        }
+    }
+
+    fn section(&self, state: &mut TerminalState, header: &'static str) {
+        if state.section != Some(header) {
+            println!("{}", header);
+            state.section = Some(header);
+        }
+    }
+}
+
+impl Reporter for TerminalReporter {
+    fn report(&self, event: Event) {
+        let mut state = self.state.lock().unwrap();
+        match event {
+            Event::ItemStarted { item, count } => {
+                state.section = None;
+                if count == 1 {
+                    state.pending = Some(item);
+                } else {
+                    state.pending = None;
+                    println!("\n {}", item);
+                }
+            }
+            Event::Entry {
+                relpath, status, ..
+            } => self.line(&mut state, format!("{} {}", status, relpath), true),
+            Event::Copied { action, dryrun } => {
+                let icon = match dryrun {
+                    true => paint(Icon::Pending.as_str().yellow()),
+                    false => paint(Icon::Done.as_str().green()),
+                };
+                self.line(&mut state, format!("{} {}", icon, action), false);
+            }
+            Event::Skipped { action } => {
+                self.line(&mut state, format!("{} {}", paint(Icon::Skipped.as_str().dark_grey()), action), false);
+            }
+            Event::Error { path, reason, .. } => {
+                let line = format!("{} {}: {}", paint(Icon::Error.as_str().red()), path, reason);
+                self.line(&mut state, line, true);
+            }
+            Event::Overlap {
+                relpath,
+                items,
+                owner,
+            } => {
+                self.section(&mut state, "\n Tracked by multiple items");
+                println!(
+                    "   {} {}: {} (synced as part of {})",
+                    paint(Icon::Warning.as_str().yellow()),
+                    relpath,
+                    items.join(", "),
+                    owner,
+                );
+            }
+            Event::Hook {
+                name, cmd, error, ..
+            } => {
+                self.section(&mut state, "\nHooks:");
+                match error {
+                    None => println!("  {} {}: {}", paint(Icon::Done.as_str().green()), name, cmd),
+                    Some(err) => println!("  {} {}: {} ({})", paint(Icon::Error.as_str().red()), name, cmd, err),
+                }
+            }
+            Event::Info { message } => println!("{}", message),
+            Event::StatusSummary {
+                ok,
+                diff,
+                invalid,
+                missing_home,
+                missing_repo,
+            } => println!(
+                "\n{} {} ok | {} {} diff | {} {} invalid | {} {} missing home | {} {} missing repository",
+                Status::Ok,
+                ok,
+                Status::Diff,
+                diff,
+                paint(Icon::Error.as_str().red()),
+                invalid,
+                Status::MissingHome,
+                missing_home,
+                Status::MissingRepo,
+                missing_repo,
+            ),
+            Event::SyncSummary { summary } => println!("\n{}", summary),
+            Event::Document { value } => println!("{}", value),
+        }
+    }
+}
+
+/// Prints each event as a JSON object on a line of its own.
+#[derive(Default)]
+pub struct JsonLinesReporter;
+
+impl Reporter for JsonLinesReporter {
+    fn report(&self, event: Event) {
+        match serde_json::to_string(&event) {
+            Ok(s) => println!("{}", s),
+            Err(err) => log::error!("Failed to serialize event {:?}: {}", event, err),
+        }
+    }
+}
+
+/// Keeps the events in memory, e.g. to assert on them in tests.
+#[derive(Default)]
+pub struct CaptureReporter {
+    events: Mutex<Vec<Event>>,
+}
+
+impl CaptureReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Returns the kinds of the actions that were copied or skipped.
+    pub fn actions(&self) -> Vec<ActionKind> {
+        self.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Copied { action, .. } | Event::Skipped { action } => Some(action.kind),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Reporter for CaptureReporter {
+    fn report(&self, event: Event) {
+        self.events.lock().unwrap().push(event);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::data::{Dotfile, Entry, FileError, Hooks, Item, Status};
+use crate::error::DotfError;
+use crate::files::{Filesystem, RealFs};
+use crate::handler::{
+    ConflictError, ConflictPolicy, Plan, Selection, StalePlanError, SyncHandler, SyncOptions,
+    SyncSummary, Target,
+};
+use crate::index::{Indexed, Indexer, Overlap};
+use crate::path::home_path;
+use crate::prompt::Prompt;
+use crate::report::{CaptureReporter, Event};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(test)]
+mod tests;
+
+/// The errors returned by a `Session`.
+#[derive(Debug)]
+pub enum Error {
+    /// The dotfile could not be read or is invalid.
+    Dotfile { path: PathBuf, cause: String },
+    /// Files differ between home and repository, and the conflict policy is
+    /// to fail.
+    Conflicts(ConflictError),
+    /// Files changed since the plan was made, so nothing was applied.
+    StalePlan(StalePlanError),
+    /// A failure that can be acted on, e.g. a file that cannot be read or a
+    /// command that failed.
+    Dotf(DotfError),
+    /// Indexing or syncing failed, e.g. with fail fast or a failing hook.
+    Failed(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Dotfile { path, cause } => write!(f, "{}: {}", path.display(), cause),
+            Error::Conflicts(err) => write!(f, "{}", err),
+            Error::StalePlan(err) => write!(f, "{}", err),
+            Error::Dotf(err) => write!(f, "{}", err),
+            Error::Failed(cause) => write!(f, "{}", cause),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Dotf(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<ConflictError>() {
+            Ok(err) => return Error::Conflicts(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<StalePlanError>() {
+            Ok(err) => return Error::StalePlan(err),
+            Err(err) => err,
+        };
+        match err.downcast::<DotfError>() {
+            Ok(err) => Error::Dotf(err),
+            Err(err) => Error::Failed(format!("{:#}", err)),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The status of the tracked files, returned by `Session::index`.
+#[derive(Debug)]
+pub struct Report {
+    /// The entries of each item, where files tracked by more than one item
+    /// only belong to their owner.
+    pub items: Indexed,
+    pub overlaps: Vec<Overlap>,
+}
+
+impl Report {
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.items
+            .iter()
+            .flat_map(|(name, entries)| entries.iter().map(move |e| (name.as_str(), e)))
+    }
+
+    /// Returns the files, or patterns, that could not be indexed.
+    pub fn errors(&self) -> Vec<(&str, &FileError)> {
+        self.entries()
+            .filter_map(|(name, entry)| match entry {
+                Entry::Err(err) => Some((name, err)),
+                Entry::Ok { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Returns the relative paths of the files with the status.
+    pub fn with_status(&self, status: Status) -> Vec<&Path> {
+        self.entries()
+            .filter_map(|(_, entry)| match entry {
+                Entry::Ok {
+                    relpath, status: s, ..
+                } if *s == status => Some(relpath.as_path()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn is_up_to_date(&self) -> bool {
+        self.entries().all(|(_, entry)| entry.is_status_ok())
+    }
+}
+
+/// The result of applying a plan.
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub summary: SyncSummary,
+    /// Everything that happened, in order, e.g. each file copied.
+    pub events: Vec<Event>,
+}
+
+/// Entry point for using dotf as a library. Built from a dotfile, it indexes,
+/// plans and syncs files without prompting or writing to stdout.
+pub struct Session {
+    home: PathBuf,
+    repository: PathBuf,
+    items: Vec<Item>,
+    hooks: Hooks,
+    fs: Arc<dyn Filesystem>,
+    selection: Selection,
+    // How to handle files that differ, fails by default.
+    on_conflict: ConflictPolicy,
+    // Create a backup of home files before overwriting them.
+    backup: bool,
+    // Hooks are disabled by default, since they run arbitrary commands.
+    run_hooks: bool,
+}
+
+// Public methods.
+impl Session {
+    pub fn new(dotfile: Dotfile, home: PathBuf) -> Self {
+        Self {
+            home,
+            repository: dotfile.repository(),
+            hooks: dotfile.hooks(),
+            items: dotfile.items(),
+            fs: Arc::new(RealFs),
+            selection: Selection::default(),
+            on_conflict: ConflictPolicy::Fail,
+            backup: true,
+            run_hooks: false,
+        }
+    }
+
+    /// Reads the dotfile at the path, using the home directory of the user.
+    pub fn load(path: &Path) -> Result<Self> {
+        let dotfile_error = |cause: String| Error::Dotfile {
+            path: path.to_path_buf(),
+            cause,
+        };
+        let s = std::fs::read_to_string(path).map_err(|err| dotfile_error(err.to_string()))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let dotfile = Dotfile::from(&s, dir).map_err(|err| dotfile_error(format!("{:#}", err)))?;
+        Ok(Self::new(dotfile, home_path()))
+    }
+
+    /// Use the filesystem instead of the real one, e.g. a `MemoryFs`.
+    pub fn with_filesystem(mut self, fs: Arc<dyn Filesystem>) -> Self {
+        self.fs = fs;
+        self
+    }
+
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn with_conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.on_conflict = policy;
+        self
+    }
+
+    pub fn with_backup(mut self, backup: bool) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    /// Run the hooks of the dotfile when applying plans. Their output is
+    /// captured and returned as `Event::Hook` in the outcome.
+    pub fn with_hooks(mut self, run_hooks: bool) -> Self {
+        self.run_hooks = run_hooks;
+        self
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    /// Returns the status of every tracked file.
+    pub fn index(&self) -> Result<Report> {
+        let indexer = Indexer::new(self.home.clone(), self.repository.clone(), None)
+            .with_selection(self.selection.clone())
+            .with_filesystem(self.fs.clone());
+        let (items, overlaps) = indexer.index_deduped(&self.items)?;
+        Ok(Report { items, overlaps })
+    }
+
+    /// Plans how to sync the files to the target, without changing any files.
+    /// The digests of the files are recorded, to be checked by apply.
+    pub fn plan_sync(&self, target: Target) -> Result<Plan> {
+        let handler = self.handler(Arc::new(CaptureReporter::new()));
+        let mut plan = handler.plan(target)?;
+        plan.record_digests(self.fs.as_ref())?;
+        Ok(plan)
+    }
+
+    /// Executes the plan. Fails without changing any files if a file changed
+    /// since the plan was made.
+    pub fn apply(&self, plan: &Plan) -> Result<Outcome> {
+        let reporter = Arc::new(CaptureReporter::new());
+        let handler = self.handler(reporter.clone());
+        let summary = handler.apply(plan)?;
+        Ok(Outcome {
+            summary,
+            events: reporter.events(),
+        })
+    }
+}
+
+// Private methods.
+impl Session {
+    fn handler(&self, reporter: Arc<CaptureReporter>) -> SyncHandler {
+        let options = SyncOptions {
+            non_interactive: true,
+            confirm: false,
+            on_conflict: Some(self.on_conflict),
+            backup: self.backup,
+            hooks: self.hooks.clone(),
+            run_hooks: self.run_hooks,
+            capture_hooks: true,
+            ..Default::default()
+        };
+        SyncHandler::new(
+            Box::new(NoPrompt),
+            self.home.clone(),
+            self.repository.clone(),
+            self.items.clone(),
+            options,
+            None,
+        )
+        .with_selection(self.selection.clone())
+        .with_filesystem(self.fs.clone())
+        .with_reporter(reporter)
+    }
+}
+
+// Fails instead of prompting, which sessions never should.
+struct NoPrompt;
+
+impl Prompt for NoPrompt {
+    fn prompt(&self, msg: &str) -> anyhow::Result<String> {
+        anyhow::bail!("cannot prompt in a session: {}", msg)
+    }
+
+    fn confirm(&self, msg: &str, _default_yes: bool) -> anyhow::Result<bool> {
+        anyhow::bail!("cannot prompt in a session: {}", msg)
+    }
+
+    fn select(&self, msg: &str, _options: &[String]) -> anyhow::Result<usize> {
+        anyhow::bail!("cannot prompt in a session: {}", msg)
+    }
+}
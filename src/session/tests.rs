@@ -0,0 +1,145 @@
+use super::{Error, Session};
+use crate::data::{Dotfile, Status};
+use crate::error::DotfError;
+use crate::handler::{ActionKind, ConflictPolicy, Target};
+use crate::report::Event;
+use crate::testing::{create_with_path, TestContext};
+
+fn setup() -> (TestContext, Session) {
+    let context = TestContext::default();
+    context.setup().unwrap();
+    let s = r#"
+    repository = "repo"
+
+    [home]
+    diff = "diffed.txt"
+    env = "env.toml"
+    vim = "init.vim"
+    missing = "missing.txt"
+    "#;
+    let dotfile = Dotfile::from(s, &context.temp_dir).unwrap();
+    let session = Session::new(dotfile, context.home_dir.clone());
+    (context, session)
+}
+
+#[test]
+fn index_returns_report() {
+    // Arrange
+    let (_context, session) = setup();
+
+    // Act
+    let report = session.index().unwrap();
+
+    // Assert
+    assert_eq!(vec!["diffed.txt"], report.with_status(Status::Diff));
+    assert_eq!(vec!["env.toml"], report.with_status(Status::MissingHome));
+    let errors = report.errors();
+    assert_eq!(1, errors.len());
+    assert_eq!("missing", errors[0].0);
+    assert!(!report.is_up_to_date());
+}
+
+#[test]
+fn plan_sync_fails_on_conflicts() {
+    // Arrange
+    let (_context, session) = setup();
+
+    // Act
+    let err = session.plan_sync(Target::Home).expect_err("conflicts");
+
+    // Assert
+    match err {
+        Error::Conflicts(err) => assert_eq!(vec!["diffed.txt"], err.relpaths),
+        err => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn apply_plan() {
+    // Arrange
+    let (context, session) = setup();
+    let session = session.with_conflict_policy(ConflictPolicy::Theirs);
+    let plan = session.plan_sync(Target::Home).unwrap();
+
+    // Act
+    let outcome = session.apply(&plan).unwrap();
+
+    // Assert
+    assert_eq!(2, outcome.summary.written);
+    assert_eq!(1, outcome.summary.backed_up);
+    assert!(context.home_path("env.toml").exists());
+    assert!(outcome.events.iter().any(|e| matches!(
+        e,
+        Event::Copied { action, .. } if action.kind == ActionKind::Create
+    )));
+    assert!(session
+        .index()
+        .unwrap()
+        .with_status(Status::Diff)
+        .is_empty());
+}
+
+#[test]
+fn apply_refuses_stale_plan() {
+    // Arrange
+    let (context, session) = setup();
+    let session = session.with_conflict_policy(ConflictPolicy::Theirs);
+    let plan = session.plan_sync(Target::Home).unwrap();
+    create_with_path(&context.home_path("diffed.txt"), "changed").unwrap();
+
+    // Act
+    let err = session.apply(&plan).expect_err("stale plan");
+
+    // Assert
+    match err {
+        Error::StalePlan(err) => assert_eq!(
+            vec![("diff".to_string(), "diffed.txt".to_string())],
+            err.files
+        ),
+        err => panic!("unexpected error: {}", err),
+    }
+    assert!(!context.home_path("env.toml").exists());
+}
+
+#[test]
+fn dotf_errors_are_kept() {
+    // Arrange
+    let err = anyhow::Error::from(DotfError::Aborted).context("failed to sync");
+
+    // Act
+    let err = Error::from(err);
+
+    // Assert
+    match err {
+        Error::Dotf(err) => assert_eq!(130, err.exit_code()),
+        err => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn apply_captures_output_of_hooks() {
+    // Arrange
+    let context = TestContext::default();
+    context.setup().unwrap();
+    let s = r#"
+    repository = "repo"
+    post_sync = [ "echo synced" ]
+
+    [home]
+    env = "env.toml"
+    "#;
+    let dotfile = Dotfile::from(s, &context.temp_dir).unwrap();
+    let session = Session::new(dotfile, context.home_dir.clone()).with_hooks(true);
+    let plan = session.plan_sync(Target::Home).unwrap();
+
+    // Act
+    let outcome = session.apply(&plan).unwrap();
+
+    // Assert
+    assert!(outcome.events.contains(&Event::Hook {
+        name: "post_sync".to_string(),
+        cmd: "echo synced".to_string(),
+        output: Some("synced\n".to_string()),
+        error: None,
+    }));
+}
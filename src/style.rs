@@ -0,0 +1,156 @@
+use anyhow::{bail, Result};
+use crossterm::style::{self, StyledContent};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// When to use colors in the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Color {
+    // Only when stdout is a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            _ => bail!("invalid color: {}", s),
+        }
+    }
+}
+
+/// Sets of icons used in the output for humans.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Icons {
+    // Requires a patched font, see https://www.nerdfonts.com.
+    #[default]
+    Nerd,
+    Unicode,
+    // Letters and symbols, like git's M, A and D.
+    Ascii,
+}
+
+impl FromStr for Icons {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "nerd" => Ok(Icons::Nerd),
+            "unicode" => Ok(Icons::Unicode),
+            "ascii" => Ok(Icons::Ascii),
+            _ => bail!("invalid icons: {}", s),
+        }
+    }
+}
+
+/// Something that is displayed as an icon, e.g. the status of a file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Icon {
+    Ok,
+    Diff,
+    MissingHome,
+    MissingRepo,
+    Error,
+    Warning,
+    Skipped,
+    // Something that would be done, e.g. on a dry run.
+    Pending,
+    Done,
+}
+
+// Indexed by Icon.
+const NERD: [&str; 9] = ["", "", "", "", "", "", "", "", ""];
+const UNICODE: [&str; 9] = ["✓", "≠", "⌂?", "⎇?", "✗", "⚠", "⊘", "…", "✓"];
+const ASCII: [&str; 9] = ["=", "M", "D", "A", "E", "!", "-", "~", "+"];
+
+// The icons of the process, as set by set_icons.
+static ICONS: AtomicU8 = AtomicU8::new(Icons::Nerd as u8);
+// Whether the process uses colors, as set by set_colors.
+static COLORS: AtomicBool = AtomicBool::new(true);
+
+impl Icon {
+    /// The icon in the set used by the process.
+    pub fn as_str(&self) -> &'static str {
+        self.get(icons())
+    }
+
+    pub fn get(&self, icons: Icons) -> &'static str {
+        let set = match icons {
+            Icons::Nerd => NERD,
+            Icons::Unicode => UNICODE,
+            Icons::Ascii => ASCII,
+        };
+        set[*self as usize]
+    }
+}
+
+/// Sets the icons used by the rest of the process, like colors are set using
+/// crossterm::style::force_color_output.
+pub fn set_icons(icons: Icons) {
+    ICONS.store(icons as u8, Ordering::Relaxed);
+}
+
+pub fn icons() -> Icons {
+    match ICONS.load(Ordering::Relaxed) {
+        x if x == Icons::Unicode as u8 => Icons::Unicode,
+        x if x == Icons::Ascii as u8 => Icons::Ascii,
+        _ => Icons::Nerd,
+    }
+}
+
+/// Sets whether the rest of the process uses colors, including prompts.
+pub fn set_colors(enabled: bool) {
+    COLORS.store(enabled, Ordering::Relaxed);
+    style::force_color_output(enabled);
+}
+
+/// Whether the process uses colors, e.g. to pass on to external commands.
+pub fn colors() -> bool {
+    COLORS.load(Ordering::Relaxed)
+}
+
+/// Content styled using crossterm, displayed without escape codes when
+/// colors are disabled. Crossterm still writes empty ones.
+pub fn paint<D: Display>(content: StyledContent<D>) -> Painted<D> {
+    Painted(content)
+}
+
+pub struct Painted<D: Display>(StyledContent<D>);
+
+impl<D: Display> Display for Painted<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match colors() {
+            true => write!(f, "{}", self.0),
+            false => write!(f, "{}", self.0.content()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icons_of_each_set() {
+        assert_eq!("M", Icon::Diff.get(Icons::Ascii));
+        assert_eq!("A", Icon::MissingRepo.get(Icons::Ascii));
+        assert_eq!("D", Icon::MissingHome.get(Icons::Ascii));
+        assert_eq!("✗", Icon::Error.get(Icons::Unicode));
+        assert_eq!("", Icon::Ok.get(Icons::Nerd));
+    }
+
+    #[test]
+    fn parse_icons_and_color() {
+        assert_eq!(Icons::Ascii, "ascii".parse().unwrap());
+        assert_eq!(Color::Never, "never".parse().unwrap());
+        assert!("emoji".parse::<Icons>().is_err());
+    }
+}
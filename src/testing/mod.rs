@@ -1,7 +1,10 @@
 use crate::data::Status;
+use crate::files::MemoryFs;
 use crate::prompt::Prompt;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use rand::{distributions::Alphanumeric, Rng};
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -44,7 +47,11 @@ pub fn create_with_path(path: &Path, content: &str) -> Result<()> {
         fs::create_dir_all(dir)?;
     }
 
-    let mut file = fs::OpenOptions::new().write(true).create(true).open(path)?;
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
     file.write_all(content.as_bytes())?;
     Ok(())
 }
@@ -75,35 +82,51 @@ impl TestContext {
     }
 
     pub fn setup(&self) -> Result<()> {
+        self.create_files(create_with_path)
+    }
+
+    /// Creates the files in memory instead of on disk, so nothing needs to
+    /// be cleaned up.
+    pub fn setup_memory(&self) -> MemoryFs {
+        let fs = MemoryFs::new();
+        self.create_files(|path, content| {
+            fs.add_file(path, content.as_bytes());
+            Ok(())
+        })
+        .unwrap();
+        fs
+    }
+
+    fn create_files(&self, create: impl Fn(&Path, &str) -> Result<()>) -> Result<()> {
         for spec in self.file_specs.iter() {
             let h = self.home_dir.join(&spec.path);
             let r = self.repo_dir.join(&spec.path);
 
             if spec.special {
                 let content = random_string(10);
-                create_with_path(&h, &content)?;
+                create(&h, &content)?;
                 continue;
             }
 
             match spec.status {
                 Status::Ok => {
                     let content = random_string(10);
-                    create_with_path(&h, &content)?;
-                    create_with_path(&r, &content)?;
+                    create(&h, &content)?;
+                    create(&r, &content)?;
                 }
                 Status::Diff => {
                     let content = random_string(10);
-                    create_with_path(&h, &content)?;
+                    create(&h, &content)?;
                     let content = random_string(10);
-                    create_with_path(&r, &content)?;
+                    create(&r, &content)?;
                 }
                 Status::MissingHome => {
                     let content = random_string(10);
-                    create_with_path(&r, &content)?;
+                    create(&r, &content)?;
                 }
                 Status::MissingRepo => {
                     let content = random_string(10);
-                    create_with_path(&h, &content)?;
+                    create(&h, &content)?;
                 }
             }
         }
@@ -173,7 +196,21 @@ fn create_with_path_dir() {
     fs::remove_dir_all("newdir").unwrap();
 }
 
-pub struct PromptMock;
+/// Answers yes to every confirmation. Selections are answered, in order,
+/// by the option labels given to `with_selections`.
+#[derive(Default)]
+pub struct PromptMock {
+    selections: RefCell<VecDeque<String>>,
+}
+
+impl PromptMock {
+    pub fn with_selections(labels: &[&str]) -> Self {
+        let selections = labels.iter().map(|s| s.to_string()).collect();
+        Self {
+            selections: RefCell::new(selections),
+        }
+    }
+}
 
 impl Prompt for PromptMock {
     fn prompt(&self, _msg: &str) -> Result<String> {
@@ -183,4 +220,15 @@ impl Prompt for PromptMock {
     fn confirm(&self, _msg: &str, _default_yes: bool) -> Result<bool> {
         Ok(true)
     }
+
+    fn select(&self, msg: &str, options: &[String]) -> Result<usize> {
+        let label = match self.selections.borrow_mut().pop_front() {
+            Some(label) => label,
+            None => bail!("no selection left for: {}", msg),
+        };
+        match options.iter().position(|o| *o == label) {
+            Some(index) => Ok(index),
+            None => bail!("{} is not an option of {}: {:?}", label, msg, options),
+        }
+    }
 }